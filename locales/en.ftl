@@ -13,4 +13,16 @@ author-list = Authors list
 
 author-article-count = { $number } articles
 
-editor = Editor
\ No newline at end of file
+editor = Editor
+
+callout-note = Note
+
+callout-tip = Tip
+
+callout-warning = Warning
+
+callout-danger = Danger
+
+callout-quote = Quote
+
+footnotes = Notes
@@ -13,4 +13,16 @@ author-list = 作者列表
 
 author-article-count = { $number } 篇文章
 
-editor = 责任编辑
\ No newline at end of file
+editor = 责任编辑
+
+callout-note = 注意
+
+callout-tip = 提示
+
+callout-warning = 警告
+
+callout-danger = 危险
+
+callout-quote = 引用
+
+footnotes = 注释
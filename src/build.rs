@@ -1,12 +1,19 @@
 use std::{
+    env, fs,
     path::{Path, PathBuf},
     sync::mpsc,
     time::Duration,
 };
 
-use crate::{data, entity::Zine, error::ZineError, ZineEngine};
-use anyhow::{anyhow, Context, Result};
-use notify::{watcher, RecursiveMode, Watcher};
+use crate::{
+    check::{self, ExternalCheckOptions, LinkReport},
+    data,
+    entity::Zine,
+    error::ZineError,
+    ZineEngine,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 use walkdir::WalkDir;
 
 pub async fn watch_build<P: AsRef<Path>>(source: P, dest: P, watch: bool) -> Result<()> {
@@ -22,7 +29,7 @@ pub async fn watch_build<P: AsRef<Path>>(source: P, dest: P, watch: bool) -> Res
     let source_path = source.clone();
     tokio::spawn(async move {
         tokio::signal::ctrl_c().await.unwrap();
-        // Save zine data only when the process gonna exist
+        // Save zine data when the process gonna exit, such as during watching.
         data::export(source_path).unwrap();
         std::process::exit(0);
     });
@@ -45,8 +52,12 @@ pub async fn watch_build<P: AsRef<Path>>(source: P, dest: P, watch: bool) -> Res
                 watcher.watch("static", RecursiveMode::Recursive)?;
             }
 
+            // The build directory may be inside the source directory.
+            let dest = fs::canonicalize(&engine.dest)?;
             loop {
                 match rx.recv() {
+                    // The files written by the build itself or the compiler, ignore them to avoid rebuilding endlessly.
+                    Ok(event) if is_ignored_event(&event, &engine.source, &dest) => {}
                     Ok(_) => build(&mut engine, true)?,
                     Err(err) => println!("watch error: {:?}", &err),
                 }
@@ -66,11 +77,127 @@ pub async fn watch_build<P: AsRef<Path>>(source: P, dest: P, watch: bool) -> Res
     Ok(())
 }
 
+// The prefix of temporal build dir for `zine check-links` command.
+static TEMP_ZINE_CHECK_DIR: &str = "__zine_check";
+
+/// Remove the build directory `dest`, so that the stale pages and assets of
+/// previous builds wouldn't hide the broken links.
+/// Return error if `dest` is the source directory or contains it.
+pub fn clean_build_dir<P: AsRef<Path>>(source: P, dest: P) -> Result<()> {
+    let dest = dest.as_ref();
+    if !dest.exists() {
+        return Ok(());
+    }
+
+    let source = fs::canonicalize(source)?;
+    let dest = fs::canonicalize(dest)?;
+    if source.starts_with(&dest) {
+        bail!(
+            "Refuse to clean the build directory `{}`, it contains the source directory.",
+            dest.display()
+        );
+    }
+    fs::remove_dir_all(&dest)
+        .with_context(|| format!("Failed to clean the build directory `{}`", dest.display()))
+}
+
+/// Check the internal links of the site built into `dest`.
+/// Return error if there are any broken links.
+pub fn check_build_links<P: AsRef<Path>>(source: P, dest: P) -> Result<()> {
+    let (_, zine) = locate_root_zine_folder(std::fs::canonicalize(source)?)?
+        .with_context(|| "Failed to find the root zine.toml file".to_string())?;
+
+    let report = check::check_internal_links(dest.as_ref(), &zine.site.url)?;
+    print_link_report(report)
+}
+
+/// Build the site into a temporal directory, then check the internal links,
+/// and also check the external links if `external` options specified.
+/// Return error if there are any broken links.
+pub async fn check_links<P: AsRef<Path>>(
+    source: P,
+    external: Option<ExternalCheckOptions>,
+) -> Result<()> {
+    // Build into a fresh directory of each run, the stale pages of
+    // previous runs or other projects would hide the broken links.
+    let dest = env::temp_dir().join(format!("{}_{}", TEMP_ZINE_CHECK_DIR, std::process::id()));
+    if dest.exists() {
+        fs::remove_dir_all(&dest)?;
+    }
+    let result = check_links_in(source.as_ref(), &dest, external).await;
+    if dest.exists() {
+        fs::remove_dir_all(&dest)?;
+    }
+    result
+}
+
+async fn check_links_in(
+    source: &Path,
+    dest: &Path,
+    external: Option<ExternalCheckOptions>,
+) -> Result<()> {
+    watch_build(source, dest, false).await?;
+
+    let (source, zine) = locate_root_zine_folder(std::fs::canonicalize(source)?)?
+        .with_context(|| "Failed to find the root zine.toml file".to_string())?;
+    let mut report = check::check_internal_links(dest, &zine.site.url)?;
+    if let Some(options) = external {
+        report.merge(check::check_external_links(dest, &zine.site.url, &options).await?);
+        // Save the checked results of external links.
+        data::export(&source)?;
+    }
+    print_link_report(report)
+}
+
+fn print_link_report(report: LinkReport) -> Result<()> {
+    if report.is_empty() {
+        println!("No broken links found.");
+        Ok(())
+    } else {
+        print!("{}", report);
+        Err(anyhow!("Found {} broken link(s).", report.count()))
+    }
+}
+
 fn build(engine: &mut ZineEngine, reload: bool) -> Result<()> {
     let instant = std::time::Instant::now();
     engine.build(reload)?;
     println!("Build cost: {}ms", instant.elapsed().as_millis());
-    Ok(())
+    // Only keep the plugin outputs used by this build.
+    data::write().prune_plugin_outputs();
+    // Save zine data after every successful build, such as the fetched URL previews.
+    data::export(&engine.source)
+}
+
+// Whether the watch `event` should be ignored, which is about the files written
+// by the build itself or the compiler, such as:
+// - the data file or its temporary file,
+// - the build directory `dest`,
+// - the `target` directories of the crates inside the `source` directory,
+//   whose files may be included by the include code block.
+fn is_ignored_event(event: &DebouncedEvent, source: &Path, dest: &Path) -> bool {
+    let is_ignored = |path: &Path| {
+        let is_data_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with(data::DATA_FILE))
+            .unwrap_or_default();
+        let in_target_dir = path
+            .strip_prefix(source)
+            .map(|path| path.components().any(|c| c.as_os_str() == "target"))
+            .unwrap_or_default();
+        is_data_file || in_target_dir || path.starts_with(dest)
+    };
+    match event {
+        DebouncedEvent::NoticeWrite(path)
+        | DebouncedEvent::NoticeRemove(path)
+        | DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path) => is_ignored(path),
+        DebouncedEvent::Rename(from, to) => is_ignored(from) && is_ignored(to),
+        _ => false,
+    }
 }
 
 /// Find the root zine file in current dir and try to parse it
@@ -87,8 +214,8 @@ fn parse_root_zine_file<P: AsRef<Path>>(path: P) -> Result<Option<Zine>> {
     Ok(None)
 }
 
-// Locate folder contains the root `zine.toml`, and return path info and Zine instance.
-fn locate_root_zine_folder(path: PathBuf) -> Result<Option<(PathBuf, Zine)>> {
+/// Locate folder contains the root `zine.toml`, and return path info and Zine instance.
+pub fn locate_root_zine_folder(path: PathBuf) -> Result<Option<(PathBuf, Zine)>> {
     match parse_root_zine_file(&path) {
         Ok(Some(zine)) => return Ok(Some((path, zine))),
         Err(err) => match err.downcast::<ZineError>() {
@@ -107,3 +234,60 @@ fn locate_root_zine_folder(path: PathBuf) -> Result<Option<(PathBuf, Zine)>> {
         None => Ok(None),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use notify::DebouncedEvent;
+
+    use super::{clean_build_dir, is_ignored_event};
+
+    #[test]
+    fn test_clean_build_dir() {
+        let root = env::temp_dir().join("__zine_clean_build_dir");
+        let _ = fs::remove_dir_all(&root);
+        let (source, dest) = (root.join("site"), root.join("site/build"));
+        fs::create_dir_all(dest.join("stale")).unwrap();
+        fs::write(dest.join("stale/index.html"), "").unwrap();
+
+        clean_build_dir(&source, &dest).unwrap();
+        assert!(!dest.exists());
+        // The missing build directory is ok.
+        clean_build_dir(&source, &dest).unwrap();
+
+        // Never remove the source directory.
+        assert!(clean_build_dir(&source, &source).is_err());
+        assert!(clean_build_dir(&source, &root).is_err());
+        assert!(source.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_is_ignored_event() {
+        let (source, dest) = (PathBuf::from("/site"), PathBuf::from("/site/build"));
+        let is_ignored =
+            |path: &str| is_ignored_event(&DebouncedEvent::Write(path.into()), &source, &dest);
+        assert!(is_ignored("/site/zine-data.json"));
+        assert!(is_ignored("/site/build/index.html"));
+        assert!(is_ignored("/site/examples/target/debug/main"));
+        assert!(is_ignored("/site/target/debug/main"));
+        assert!(!is_ignored("/site/issue-1/first.md"));
+        assert!(!is_ignored("/site/examples/src/main.rs"));
+        // The `target` outside the source directory is not ignored.
+        assert!(!is_ignored("/target/site/first.md"));
+
+        // The rename is ignored only if both paths are ignored.
+        let rename = |from: &str, to: &str| {
+            is_ignored_event(
+                &DebouncedEvent::Rename(from.into(), to.into()),
+                &source,
+                &dest,
+            )
+        };
+        assert!(rename("/site/target/a", "/site/build/a"));
+        assert!(!rename("/site/target/a.md", "/site/a.md"));
+        assert!(!is_ignored_event(&DebouncedEvent::Rescan, &source, &dest));
+    }
+}
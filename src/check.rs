@@ -0,0 +1,630 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use hyper::{Method, Uri};
+use parking_lot::Mutex;
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::{sync::Semaphore, time::Instant};
+use walkdir::WalkDir;
+
+use crate::{
+    data,
+    helpers::fetch_status,
+    html::{parse_html_links, HtmlLinks},
+};
+
+/// The reason why a link is broken.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BrokenReason {
+    /// The target page, image or static asset doesn't exist.
+    NotFound,
+    /// The target page exists, but the anchor doesn't exist in that page.
+    AnchorNotFound,
+    /// The external link responded with an error status code.
+    Status(u16),
+    /// The external link request failed.
+    Error(String),
+}
+
+impl fmt::Display for BrokenReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokenReason::NotFound => write!(f, "not found"),
+            BrokenReason::AnchorNotFound => write!(f, "anchor not found"),
+            BrokenReason::Status(status) => write!(f, "status {}", status),
+            BrokenReason::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BrokenLink {
+    pub link: String,
+    pub reason: BrokenReason,
+}
+
+/// The link check report, all broken links are grouped by the URL of the source page.
+#[derive(Debug, Default)]
+pub struct LinkReport {
+    broken_links: BTreeMap<String, Vec<BrokenLink>>,
+}
+
+impl LinkReport {
+    fn record(&mut self, page_url: &str, link: &str, reason: BrokenReason) {
+        self.broken_links
+            .entry(page_url.to_owned())
+            .or_default()
+            .push(BrokenLink {
+                link: link.to_owned(),
+                reason,
+            });
+    }
+
+    /// The number of broken links.
+    pub fn count(&self) -> usize {
+        self.broken_links.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.broken_links.is_empty()
+    }
+
+    /// Merge the broken links of `other` report into this report.
+    pub fn merge(&mut self, other: LinkReport) {
+        for (page_url, links) in other.broken_links {
+            self.broken_links.entry(page_url).or_default().extend(links);
+        }
+    }
+}
+
+impl fmt::Display for LinkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (page_url, links) in &self.broken_links {
+            writeln!(f, "Broken links in `{}`:", page_url)?;
+            for link in links {
+                writeln!(f, "  - `{}` ({})", link.link, link.reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check all internal links of the built site located in `dest` directory.
+///
+/// The links to pages, anchors, images and static assets which don't exist
+/// in the `dest` directory are reported. External links are ignored.
+pub fn check_internal_links(dest: &Path, site_url: &str) -> Result<LinkReport> {
+    let pages = parse_site_pages(dest)?;
+
+    let mut report = LinkReport::default();
+    let mut files = pages.keys().collect::<Vec<_>>();
+    files.sort();
+    for file in files {
+        let page_url = page_url(file);
+        for link in &pages[file].links {
+            let (url_path, fragment) = match resolve_internal_link(link, &page_url, site_url) {
+                Some(resolved) => resolved,
+                None => continue,
+            };
+
+            match target_file(dest, &url_path) {
+                Some(target) => {
+                    if fragment.is_empty() {
+                        continue;
+                    }
+                    // Only check anchors of HTML pages.
+                    if let Some(target_page) = pages.get(&target) {
+                        if !target_page.ids.contains(fragment) {
+                            report.record(&page_url, link, BrokenReason::AnchorNotFound);
+                        }
+                    }
+                }
+                None => report.record(&page_url, link, BrokenReason::NotFound),
+            }
+        }
+    }
+    Ok(report)
+}
+
+// Parse all HTML pages of the site, keyed by the relative file path.
+fn parse_site_pages(dest: &Path) -> Result<HashMap<PathBuf, HtmlLinks>> {
+    WalkDir::new(dest)
+        .into_iter()
+        .par_bridge()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.is_file() && path.extension().map(|ext| ext == "html") == Some(true) {
+                Some(path.to_owned())
+            } else {
+                None
+            }
+        })
+        .map(|path| {
+            let html_links = parse_html_links(&fs::read(&path)?)?;
+            anyhow::Ok((path.strip_prefix(dest)?.to_owned(), html_links))
+        })
+        .collect()
+}
+
+// Get the page URL from the relative HTML file path.
+// For example: `cloud/first/index.html` => `/cloud/first`.
+fn page_url(file: &Path) -> String {
+    let file = if file.ends_with("index.html") {
+        file.parent().unwrap_or(file)
+    } else {
+        file
+    };
+    let segments = file
+        .components()
+        .filter_map(|component| component.as_os_str().to_str())
+        .collect::<Vec<_>>();
+    format!("/{}", segments.join("/"))
+}
+
+// Resolve the internal `link` in the page of `page_url` into
+// the absolute URL path and fragment.
+//
+// Return `None` if the link is an external link.
+fn resolve_internal_link<'a>(
+    link: &'a str,
+    page_url: &str,
+    site_url: &str,
+) -> Option<(String, &'a str)> {
+    let site_url = site_url.trim_end_matches('/');
+    // The root path links have been rewritten with the site url
+    // if the site url has a path, we treat them as internal links.
+    let (link, is_site_url) = match link.strip_prefix(site_url) {
+        Some(rest) if !site_url.is_empty() && (rest.is_empty() || rest.starts_with(['/', '#'])) => {
+            (rest, true)
+        }
+        _ => (link, false),
+    };
+
+    if link.starts_with("//") {
+        return None;
+    }
+    // Any scheme, such as `https:`, `mailto:`, `data:`.
+    if let Some(index) = link.find(':') {
+        if !link[..index].contains(['/', '?', '#']) {
+            return None;
+        }
+    }
+
+    let (path, fragment) = link.split_once('#').unwrap_or((link, ""));
+    let path = path.split('?').next().unwrap_or_default();
+    let url_path = if path.is_empty() {
+        if is_site_url {
+            String::from("/")
+        } else {
+            page_url.to_owned()
+        }
+    } else if path.starts_with('/') {
+        path.to_owned()
+    } else {
+        // The page URL has no trailing slash, relative links are
+        // resolved against the parent of page URL.
+        let base = &page_url[..page_url.rfind('/').unwrap_or_default() + 1];
+        format!("{}{}", base, path)
+    };
+
+    Some((normalize_url_path(&url_path), fragment))
+}
+
+// Normalize the URL path, resolve the `.` and `..` segments.
+fn normalize_url_path(url_path: &str) -> String {
+    let mut segments = Vec::new();
+    for segment in url_path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+// Get the relative path of the target file of `url_path` in `dest` directory.
+fn target_file(dest: &Path, url_path: &str) -> Option<PathBuf> {
+    let file = PathBuf::from(url_path.trim_start_matches('/'));
+    if dest.join(&file).is_file() {
+        Some(file)
+    } else if dest.join(&file).join("index.html").is_file() {
+        Some(file.join("index.html"))
+    } else {
+        None
+    }
+}
+
+/// The result of checking an external link.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkCheck {
+    /// The status code of the final response after redirects, `None` if the request failed.
+    pub status: Option<u16>,
+    /// The final URL if the link is redirected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirect: Option<String>,
+    /// The error message if the request failed.
+    pub error: Option<String>,
+    /// The time of this check.
+    #[serde(with = "time::serde::rfc3339")]
+    pub checked_at: OffsetDateTime,
+}
+
+impl LinkCheck {
+    fn broken_reason(&self) -> Option<BrokenReason> {
+        match (self.status, self.error.as_ref()) {
+            (Some(status), _) if status >= 400 => match self.redirect.as_ref() {
+                Some(redirect) => Some(BrokenReason::Error(format!(
+                    "status {} after redirecting to {}",
+                    status, redirect
+                ))),
+                None => Some(BrokenReason::Status(status)),
+            },
+            // The redirection is not healthy unless its target is checked,
+            // such as the results cached before following redirects.
+            (Some(status), _) if (300..400).contains(&status) => Some(BrokenReason::Error(
+                format!("unfollowed redirection of status {}", status),
+            )),
+            (Some(_), _) => None,
+            (None, Some(err)) => Some(BrokenReason::Error(err.clone())),
+            (None, None) => Some(BrokenReason::Error(String::from("unknown error"))),
+        }
+    }
+}
+
+/// The options of external link checking.
+#[derive(Debug, Clone)]
+pub struct ExternalCheckOptions {
+    /// The max number of concurrent requests.
+    pub concurrency: usize,
+    /// The timeout of each request.
+    pub timeout: Duration,
+    /// The min interval between two requests to the same host.
+    pub host_interval: Duration,
+    /// The cached results checked within this duration are reused.
+    pub max_age: Duration,
+}
+
+/// Check all external links of the built site located in `dest` directory.
+///
+/// The results are cached into zine data, only the broken links and the links
+/// which haven't been checked within `max_age` would be requested.
+pub async fn check_external_links(
+    dest: &Path,
+    site_url: &str,
+    options: &ExternalCheckOptions,
+) -> Result<LinkReport> {
+    let pages = parse_site_pages(dest)?;
+
+    // The external links of each page, keyed by the page URL.
+    let mut page_links = BTreeMap::new();
+    for (file, html_links) in &pages {
+        let links = html_links
+            .links
+            .iter()
+            .filter(|link| is_external_link(link, site_url))
+            .collect::<BTreeSet<_>>();
+        if !links.is_empty() {
+            page_links.insert(page_url(file), links);
+        }
+    }
+
+    let urls = page_links
+        .values()
+        .flatten()
+        .map(|link| strip_fragment(link).to_owned())
+        .collect::<BTreeSet<_>>();
+    let stale_urls = {
+        let now = OffsetDateTime::now_utc();
+        let data = data::read();
+        urls.iter()
+            // Always recheck the broken links, they may be transient failures.
+            .filter(|url| match data.link_checks().get(*url) {
+                Some(check) => {
+                    check.broken_reason().is_some() || now - check.checked_at > options.max_age
+                }
+                None => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    println!(
+        "Checking {} external links, {} cached.",
+        urls.len(),
+        urls.len() - stale_urls.len()
+    );
+
+    let results = check_urls(stale_urls, options).await;
+    let mut data = data::write();
+    for (url, check) in results {
+        data.insert_link_check(&url, check);
+    }
+
+    let mut report = LinkReport::default();
+    for (page_url, links) in page_links {
+        for link in links {
+            if let Some(reason) = data
+                .link_checks()
+                .get(strip_fragment(link))
+                .and_then(LinkCheck::broken_reason)
+            {
+                report.record(&page_url, link, reason);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Check `urls` concurrently, return the check result of each url.
+pub async fn check_urls(
+    urls: Vec<String>,
+    options: &ExternalCheckOptions,
+) -> Vec<(String, LinkCheck)> {
+    let semaphore = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let limiter = Arc::new(HostLimiter::new(options.host_interval));
+    let tasks = urls
+        .into_iter()
+        .map(|url| {
+            let semaphore = Arc::clone(&semaphore);
+            let limiter = Arc::clone(&limiter);
+            let timeout = options.timeout;
+            tokio::spawn(async move {
+                limiter.wait(&url).await;
+                let _permit = semaphore.acquire_owned().await.expect("Semaphore closed.");
+                let check = check_url(&url, timeout).await;
+                (url, check)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.expect("Check url task failed."));
+    }
+    results
+}
+
+async fn check_url(url: &str, timeout: Duration) -> LinkCheck {
+    let response = match fetch_status(Method::HEAD, url, timeout).await {
+        // Some servers don't support HEAD method, retry with GET method.
+        Ok((status, _)) if status.is_client_error() || status.is_server_error() => {
+            fetch_status(Method::GET, url, timeout).await
+        }
+        result => result,
+    };
+
+    let (status, redirect, error) = match response {
+        Ok((status, final_url)) => (
+            Some(status.as_u16()),
+            (final_url != url).then_some(final_url),
+            None,
+        ),
+        Err(err) => (None, None, Some(err.to_string())),
+    };
+    LinkCheck {
+        status,
+        redirect,
+        error,
+        checked_at: OffsetDateTime::now_utc(),
+    }
+}
+
+/// The limiter to keep a min interval between two requests to the same host.
+struct HostLimiter {
+    interval: Duration,
+    // The host => the scheduled time of the latest request.
+    schedules: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    fn new(interval: Duration) -> Self {
+        HostLimiter {
+            interval,
+            schedules: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Wait until the request to the host of `url` is allowed.
+    async fn wait(&self, url: &str) {
+        let host = url
+            .parse::<Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(str::to_owned))
+            .unwrap_or_default();
+
+        let scheduled = {
+            let now = Instant::now();
+            let mut schedules = self.schedules.lock();
+            let scheduled = match schedules.get(&host) {
+                Some(latest) => (*latest + self.interval).max(now),
+                None => now,
+            };
+            schedules.insert(host, scheduled);
+            scheduled
+        };
+        tokio::time::sleep_until(scheduled).await;
+    }
+}
+
+fn is_external_link(link: &str, site_url: &str) -> bool {
+    (link.starts_with("http://") || link.starts_with("https://"))
+        && resolve_internal_link(link, "/", site_url).is_none()
+}
+
+fn strip_fragment(link: &str) -> &str {
+    link.split('#').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, env, fs, net::SocketAddr, path::Path, time::Duration};
+
+    use hyper::{
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Method, Response, Server, StatusCode,
+    };
+    use test_case::test_case;
+    use tokio::time::Instant;
+
+    use super::{
+        check_internal_links, check_urls, page_url, resolve_internal_link, BrokenReason,
+        ExternalCheckOptions,
+    };
+
+    #[test_case("index.html", "/")]
+    #[test_case("cloud/index.html", "/cloud")]
+    #[test_case("cloud/first/index.html", "/cloud/first")]
+    #[test_case("404.html", "/404.html")]
+    fn test_page_url(file: &str, expected: &str) {
+        assert_eq!(expected, page_url(Path::new(file)));
+    }
+
+    #[test_case("/cloud/first", "/cloud/first", ""; "absolute")]
+    #[test_case("/cloud/first/", "/cloud/first", ""; "trailing slash")]
+    #[test_case("/cloud/first#title", "/cloud/first", "title"; "fragment")]
+    #[test_case("/cloud/first?a=b#title", "/cloud/first", "title"; "query")]
+    #[test_case("#title", "/cloud/second", "title"; "anchor")]
+    #[test_case("./figure.png", "/cloud/figure.png", ""; "relative")]
+    #[test_case("../dev/first", "/dev/first", ""; "parent")]
+    #[test_case("https://zine.dev/cloud/first", "/cloud/first", ""; "site url")]
+    #[test_case("https://zine.dev#title", "/", "title"; "site url anchor")]
+    fn test_resolve_internal_link(link: &str, url_path: &str, fragment: &str) {
+        assert_eq!(
+            Some((url_path.to_owned(), fragment)),
+            resolve_internal_link(link, "/cloud/second", "https://zine.dev/")
+        );
+    }
+
+    #[test_case("https://github.com"; "external")]
+    #[test_case("https://zine.devtools.com"; "similar site url")]
+    #[test_case("//github.com/zineland"; "protocol relative")]
+    #[test_case("mailto:zine@example.com"; "mailto")]
+    #[test_case("data:image/png;base64,aaaa"; "data")]
+    fn test_resolve_external_link(link: &str) {
+        assert_eq!(
+            None,
+            resolve_internal_link(link, "/cloud/second", "https://zine.dev")
+        );
+    }
+
+    #[test]
+    fn test_check_internal_links() {
+        let dest = env::temp_dir().join("__zine_check_links");
+        let _ = fs::remove_dir_all(&dest);
+        fs::create_dir_all(dest.join("cloud/first")).unwrap();
+        fs::create_dir_all(dest.join("static")).unwrap();
+        fs::write(dest.join("static/zine.png"), "").unwrap();
+        fs::write(
+            dest.join("index.html"),
+            r#"<a href="/cloud/first#title"></a><a href="/cloud/first#nope"></a><img src="/static/zine.png">"#,
+        )
+        .unwrap();
+        fs::write(
+            dest.join("cloud/first/index.html"),
+            r#"<h1 id="title"></h1><a href="/cloud/second"></a><img src="/static/missing.png"><a href="https://github.com"></a>"#,
+        )
+        .unwrap();
+
+        let report = check_internal_links(&dest, "http://localhost").unwrap();
+        fs::remove_dir_all(&dest).unwrap();
+
+        assert_eq!(3, report.count());
+        let links = &report.broken_links["/"];
+        assert_eq!(1, links.len());
+        assert_eq!("/cloud/first#nope", links[0].link);
+        assert_eq!(BrokenReason::AnchorNotFound, links[0].reason);
+
+        let links = &report.broken_links["/cloud/first"];
+        assert_eq!(2, links.len());
+        assert_eq!("/cloud/second", links[0].link);
+        assert_eq!(BrokenReason::NotFound, links[0].reason);
+        assert_eq!("/static/missing.png", links[1].link);
+        assert_eq!(BrokenReason::NotFound, links[1].reason);
+    }
+
+    // Spawn a local stand-in HTTP server, return the listening address.
+    fn spawn_server() -> SocketAddr {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req| async move {
+                let (status, location) = match (req.method(), req.uri().path()) {
+                    (_, "/ok") => (StatusCode::OK, None),
+                    (_, "/moved") => (StatusCode::MOVED_PERMANENTLY, Some("/ok")),
+                    (_, "/dead") => (StatusCode::FOUND, Some("/missing")),
+                    (_, "/loop") => (StatusCode::FOUND, Some("/loop")),
+                    (&Method::HEAD, "/no-head") => (StatusCode::METHOD_NOT_ALLOWED, None),
+                    (&Method::GET, "/no-head") => (StatusCode::OK, None),
+                    (_, "/slow") => {
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        (StatusCode::OK, None)
+                    }
+                    _ => (StatusCode::NOT_FOUND, None),
+                };
+                let mut resp = Response::builder().status(status);
+                if let Some(location) = location {
+                    resp = resp.header(header::LOCATION, location);
+                }
+                Ok::<_, Infallible>(resp.body(Body::empty()).unwrap())
+            }))
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_check_urls() {
+        let addr = spawn_server();
+        let options = ExternalCheckOptions {
+            concurrency: 4,
+            timeout: Duration::from_millis(500),
+            host_interval: Duration::from_millis(100),
+            max_age: Duration::from_secs(60),
+        };
+        let urls = [
+            "/ok", "/moved", "/no-head", "/missing", "/slow", "/dead", "/loop",
+        ]
+        .iter()
+        .map(|path| format!("http://{}{}", addr, path))
+        .collect::<Vec<_>>();
+
+        let instant = Instant::now();
+        let results = check_urls(urls, &options).await;
+        // All requests are sent to the same host, at least 4 intervals.
+        assert!(instant.elapsed() >= Duration::from_millis(400));
+
+        let reasons = results
+            .iter()
+            .map(|(_, check)| check.broken_reason())
+            .collect::<Vec<_>>();
+        assert_eq!(None, reasons[0]);
+        assert_eq!(None, reasons[1]);
+        assert_eq!(None, reasons[2]);
+        assert_eq!(Some(BrokenReason::Status(404)), reasons[3]);
+        assert!(matches!(reasons[4], Some(BrokenReason::Error(_))));
+        // The redirects are followed, the final status is recorded.
+        assert_eq!(Some(200), results[1].1.status);
+        assert_eq!(Some(format!("http://{}/ok", addr)), results[1].1.redirect);
+        assert_eq!(
+            Some(BrokenReason::Error(format!(
+                "status 404 after redirecting to http://{}/missing",
+                addr
+            ))),
+            reasons[5]
+        );
+        assert!(matches!(&reasons[6], Some(BrokenReason::Error(err)) if err.contains("redirects")));
+    }
+}
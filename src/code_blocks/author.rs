@@ -1,6 +1,10 @@
 use std::fmt::Write;
 
-use crate::{entity::Author, helpers::capitalize};
+use crate::{
+    entity::Author,
+    helpers::capitalize,
+    html::{escape_html, escape_url},
+};
 
 use super::CodeBlock;
 
@@ -19,9 +23,9 @@ impl<'a> CodeBlock for AuthorCode<'a> {
         writeln!(
             &mut html,
             r#"<a class="author-code" href="/@{}">"#,
-            author.id,
+            escape_html(&author.id),
         )?;
-        if let Some(avatar) = author.avatar.as_ref() {
+        if let Some(avatar) = author.avatar.as_deref().and_then(escape_url) {
             writeln!(
                 &mut html,
                 r#"<img src="{}" alt="avatar" loading="lazy">"#,
@@ -31,9 +35,41 @@ impl<'a> CodeBlock for AuthorCode<'a> {
         writeln!(
             &mut html,
             r#"<span>{}</span>"#,
-            author.name.as_ref().unwrap_or(&capitalize(&author.id))
+            escape_html(author.name.as_ref().unwrap_or(&capitalize(&author.id)))
         )?;
         writeln!(&mut html, r#"</a>"#)?;
         Ok(html)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{code_blocks::CodeBlock, entity::Author};
+
+    use super::AuthorCode;
+
+    #[test]
+    fn test_render_author_code_hostile() {
+        let author = Author {
+            id: String::from("\"><script>alert(1)</script>"),
+            name: Some(String::from("<b onmouseover=alert(1)>Alice</b>")),
+            avatar: Some(String::from("javascript:alert(1)")),
+            bio: None,
+            is_editor: false,
+            sanitize_html: None,
+        };
+        let html = AuthorCode(&author).render().unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<b "));
+        assert!(!html.contains("<img"));
+        assert!(html.contains(r#"href="/@&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;""#));
+        assert!(html.contains("<span>&lt;b onmouseover=alert(1)&gt;Alice&lt;/b&gt;</span>"));
+
+        let author = Author {
+            avatar: Some(String::from("/static/avatar.png\" onerror=\"alert(1)")),
+            ..author
+        };
+        let html = AuthorCode(&author).render().unwrap();
+        assert!(html.contains(r#"src="/static/avatar.png&quot; onerror=&quot;alert(1)""#));
+    }
+}
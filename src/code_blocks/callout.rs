@@ -1,46 +1,169 @@
-use std::collections::HashMap;
-use std::fmt::Write;
+use std::{borrow::Cow, collections::HashMap};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use tera::Context;
 
 use crate::engine;
-use crate::markdown::markdown_to_html;
+use crate::html::escape_html;
+
+// The allowed CSS color values: hex colors, named colors, CSS variables and
+// color functions such as `rgb(1 2 3 / 50%)`.
+static CSS_COLOR_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)^(#[0-9a-f]{3,8}|[a-z]+|var\(--[\w-]+\)|(rgba?|hsla?|hwb|lab|lch|oklab|oklch)\([\d\s.,%/+-]+\))$",
+    )
+    .unwrap()
+});
 
 use super::CodeBlock;
 
+/// The preset type of callout, with builtin styling, icon and localized title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalloutType {
+    Note,
+    Tip,
+    Warning,
+    Danger,
+    Quote,
+}
+
+impl CalloutType {
+    /// Parse the callout type case-insensitively.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "note" => Some(Self::Note),
+            "tip" => Some(Self::Tip),
+            "warning" => Some(Self::Warning),
+            "danger" => Some(Self::Danger),
+            "quote" => Some(Self::Quote),
+            _ => None,
+        }
+    }
+}
+
 /// The CalloutBlock to highlight some pragraphs.
 pub struct CalloutBlock<'a> {
-    bg_color: Option<&'a str>,
-    border_color: Option<&'a str>,
+    callout_type: Option<CalloutType>,
+    title: Option<Cow<'a, str>>,
+    // Whether the callout is collapsible, and expanded by default.
+    collapsible: bool,
+    open: bool,
+    bg_color: Option<Cow<'a, str>>,
+    border_color: Option<Cow<'a, str>>,
     content: &'a str,
     visitor: engine::Vistor<'a>,
 }
 
 impl<'a> CalloutBlock<'a> {
     pub fn new(
-        options: HashMap<String, &'a str>,
+        options: HashMap<String, Cow<'a, str>>,
         block: &'a str,
         visitor: engine::Vistor<'a>,
     ) -> Self {
         CalloutBlock {
-            bg_color: options.get("bg_color").cloned(),
-            border_color: options.get("border_color").cloned(),
+            callout_type: options.get("type").and_then(|value| {
+                let callout_type = CalloutType::parse(value);
+                if callout_type.is_none() {
+                    println!(
+                        "Warn: unknown callout type `{}`, fallback to default.",
+                        value
+                    );
+                }
+                callout_type
+            }),
+            title: options.get("title").cloned(),
+            collapsible: options.get("collapsible").map(AsRef::as_ref) == Some("true"),
+            open: options.get("open").map(AsRef::as_ref) == Some("true"),
+            bg_color: options
+                .get("bg_color")
+                .filter(|color| validate_color(color).is_some())
+                .cloned(),
+            border_color: options
+                .get("border_color")
+                .filter(|color| validate_color(color).is_some())
+                .cloned(),
             content: block,
             visitor,
         }
     }
+
+    // The inline style, the preset type is styled by CSS unless the colors are specified.
+    fn style(&self) -> Option<String> {
+        let (bg_color, border_color) = match self.callout_type {
+            Some(_) => (self.bg_color.as_deref(), self.border_color.as_deref()),
+            None => (
+                Some(self.bg_color.as_deref().unwrap_or("#fff")),
+                Some(
+                    self.border_color
+                        .as_deref()
+                        .unwrap_or("var(--primary-color)"),
+                ),
+            ),
+        };
+        let style = [
+            bg_color.map(|color| format!("background-color: {}", color)),
+            border_color.map(|color| format!("border-color: {}", color)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        (!style.is_empty()).then(|| style.join("; "))
+    }
 }
 
 impl<'a> CodeBlock for CalloutBlock<'a> {
     fn render(&self) -> anyhow::Result<String> {
-        let mut html = String::new();
-        let style = format!(
-            "background-color: {}; border-color: {}",
-            self.bg_color.unwrap_or("#fff"),
-            self.border_color.unwrap_or("var(--primary-color)"),
+        let mut context = Context::new();
+        context.insert("type", &self.callout_type);
+        context.insert("title", &self.title.as_deref().map(escape_html));
+        context.insert("collapsible", &self.collapsible);
+        context.insert("open", &self.open);
+        context.insert("style", &self.style());
+        context.insert("content", &self.visitor.render_markdown(self.content));
+        Ok(engine::get_tera().render("_callout.jinja", &context)?)
+    }
+}
+
+// Return the `color` if it is a valid CSS color, otherwise warn and return `None`.
+// An arbitrary value would break out of the `style` attribute.
+fn validate_color(color: &str) -> Option<&str> {
+    if CSS_COLOR_REGEX.is_match(color) {
+        Some(color)
+    } else {
+        println!(
+            "Warn: invalid callout color `{}`, fallback to default.",
+            color
         );
-        writeln!(&mut html, r#"<div class="callout" style="{}">"#, style)?;
-        let block_html = markdown_to_html(self.content, self.visitor.clone());
-        writeln!(&mut html, r#" <div>{}</div>"#, block_html)?;
-        writeln!(&mut html, r#"</div>"#)?;
-        Ok(html)
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::validate_color;
+
+    #[test_case("#fff"; "short hex")]
+    #[test_case("#ABCDEF80"; "hex with alpha")]
+    #[test_case("red"; "named")]
+    #[test_case("var(--primary-color)"; "variable")]
+    #[test_case("rgb(1 2 3 / 50%)"; "rgb")]
+    #[test_case("hsla(120, 100%, 50%, 0.3)"; "hsla")]
+    fn test_valid_color(color: &str) {
+        assert_eq!(Some(color), validate_color(color));
+    }
+
+    #[test_case("#fff\" onmouseover=\"alert(1)"; "break attribute")]
+    #[test_case("red; background-image: url(https://evil.com)"; "inject declaration")]
+    #[test_case("url(javascript:alert(1))"; "url")]
+    #[test_case("expression(alert(1))"; "expression")]
+    #[test_case("var(--a);}</style><script>"; "break style")]
+    #[test_case(""; "empty")]
+    fn test_invalid_color(color: &str) {
+        assert_eq!(None, validate_color(color));
     }
 }
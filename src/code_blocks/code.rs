@@ -0,0 +1,150 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Write, ops::RangeInclusive};
+
+use crate::html::escape_html;
+
+use super::CodeBlock;
+
+/// The code snippet of an ordinary language, such as ```` ```rust, linenos, hl_lines: 3-5, title: main.rs ````.
+///
+/// The options:
+/// - `linenos`: show the line numbers.
+/// - `hl_lines`: the highlighted lines, such as `3-5` or `"1, 3-5"`.
+/// - `title`: the title shown in the header, normally the filename.
+pub struct CodeSnippet<'a> {
+    lang: &'a str,
+    title: Option<Cow<'a, str>>,
+    linenos: bool,
+    hl_lines: Vec<RangeInclusive<usize>>,
+    // The HTML of each line, without the line ending.
+    lines: Vec<String>,
+    // The attributes of `<pre>`, such as the background color style of highlight theme.
+    pre_attrs: Option<String>,
+}
+
+impl<'a> CodeSnippet<'a> {
+    pub fn new(
+        lang: &'a str,
+        options: &HashMap<String, Cow<'a, str>>,
+        lines: Vec<String>,
+        pre_attrs: Option<String>,
+    ) -> Self {
+        CodeSnippet {
+            lang,
+            title: options.get("title").cloned(),
+            linenos: options.get("linenos").map(AsRef::as_ref) == Some("true"),
+            hl_lines: options
+                .get("hl_lines")
+                .map(|value| parse_line_ranges(value))
+                .unwrap_or_default(),
+            lines,
+            pre_attrs,
+        }
+    }
+
+    fn is_highlighted(&self, lineno: usize) -> bool {
+        self.hl_lines.iter().any(|range| range.contains(&lineno))
+    }
+}
+
+impl<'a> CodeBlock for CodeSnippet<'a> {
+    fn render(&self) -> anyhow::Result<String> {
+        let mut html = String::new();
+        writeln!(&mut html, r#"<div class="code-block">"#)?;
+        if let Some(title) = self.title.as_deref() {
+            writeln!(
+                &mut html,
+                r#"<div class="code-block-title">{}</div>"#,
+                escape_html(title)
+            )?;
+        }
+        match self.pre_attrs.as_ref() {
+            Some(attrs) => write!(&mut html, "<pre {}>", attrs)?,
+            None => write!(&mut html, "<pre>")?,
+        }
+        if self.lang.is_empty() {
+            write!(&mut html, "<code>")?;
+        } else {
+            write!(
+                &mut html,
+                r#"<code class="language-{}">"#,
+                escape_html(self.lang)
+            )?;
+        }
+        for (i, line) in self.lines.iter().enumerate() {
+            let lineno = i + 1;
+            if self.is_highlighted(lineno) {
+                write!(&mut html, r#"<span class="code-line hl">"#)?;
+            } else {
+                write!(&mut html, r#"<span class="code-line">"#)?;
+            }
+            if self.linenos {
+                write!(&mut html, r#"<span class="code-lineno">{}</span>"#, lineno)?;
+            }
+            writeln!(&mut html, "{}</span>", line)?;
+        }
+        writeln!(&mut html, "</code></pre>")?;
+        writeln!(&mut html, "</div>")?;
+        Ok(html)
+    }
+}
+
+// Parse the line ranges separated by comma or space, such as `1, 3-5`.
+// The invalid range is ignored with a warning.
+pub(super) fn parse_line_ranges(value: &str) -> Vec<RangeInclusive<usize>> {
+    value
+        .split([',', ' '])
+        .filter(|range| !range.is_empty())
+        .filter_map(|range| {
+            let parsed = match range.split_once('-') {
+                Some((start, end)) => start.trim().parse().ok().zip(end.trim().parse().ok()),
+                None => range.trim().parse().ok().map(|line| (line, line)),
+            };
+            if parsed.is_none() {
+                println!("Warn: invalid line range `{}`.", range);
+            }
+            parsed.map(|(start, end)| start..=end)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashMap};
+
+    use test_case::test_case;
+
+    use crate::code_blocks::CodeBlock;
+
+    use super::{parse_line_ranges, CodeSnippet};
+
+    #[test_case("3-5", vec![3..=5]; "range")]
+    #[test_case("1, 3-5", vec![1..=1, 3..=5]; "comma")]
+    #[test_case("1 3 - 5 x", vec![1..=1, 3..=3, 5..=5]; "invalid")]
+    #[test_case("", vec![]; "empty")]
+    fn test_parse_line_ranges(value: &str, expected: Vec<std::ops::RangeInclusive<usize>>) {
+        assert_eq!(parse_line_ranges(value), expected);
+    }
+
+    #[test]
+    fn test_render_code_snippet() {
+        let options = HashMap::from([
+            (String::from("linenos"), Cow::Borrowed("true")),
+            (String::from("hl_lines"), Cow::Borrowed("2")),
+            (String::from("title"), Cow::Borrowed("<main>.rs")),
+        ]);
+        let lines = vec![String::from("fn main() {"), String::from("}")];
+        let html = CodeSnippet::new("rust", &options, lines, None)
+            .render()
+            .unwrap();
+        assert_eq!(
+            html,
+            r#"<div class="code-block">
+<div class="code-block-title">&lt;main&gt;.rs</div>
+<pre><code class="language-rust"><span class="code-line"><span class="code-lineno">1</span>fn main() {</span>
+<span class="code-line hl"><span class="code-lineno">2</span>}</span>
+</code></pre>
+</div>
+"#
+        );
+    }
+}
@@ -0,0 +1,867 @@
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::{bail, Result};
+
+use crate::html::escape_html;
+
+use super::CodeBlock;
+
+const FONT_SIZE: f64 = 14.0;
+const LINE_HEIGHT: f64 = 18.0;
+const NODE_PADDING: f64 = 12.0;
+// The gap between nodes in the same rank, and the gap between ranks.
+const NODE_GAP: f64 = 30.0;
+const RANK_GAP: f64 = 50.0;
+const MARGIN: f64 = 20.0;
+const ARROW_SIZE: f64 = 8.0;
+
+/// The diagram written in a subset of the Graphviz DOT language,
+/// which is rendered into inline SVG at build time.
+///
+/// ````markdown
+/// ```diagram
+/// digraph {
+///     rankdir = LR;
+///     node [shape = box];
+///     browser [label = "Browser", shape = ellipse];
+///     browser -> server [label = "HTTP"];
+///     server -> { cache db };
+/// }
+/// ```
+/// ````
+///
+/// The supported attributes:
+/// - graph: `rankdir` (`TB` or `LR`).
+/// - node: `label` and `shape` (`box`, `ellipse`, `circle`, `diamond` or `plaintext`).
+/// - edge: `label` and `style` (`solid`, `dashed` or `dotted`).
+pub struct DiagramBlock<'a> {
+    source: &'a str,
+}
+
+impl<'a> DiagramBlock<'a> {
+    pub fn new(block: &'a str) -> Self {
+        DiagramBlock { source: block }
+    }
+}
+
+impl<'a> CodeBlock for DiagramBlock<'a> {
+    fn render(&self) -> Result<String> {
+        let graph = Parser::new(self.source)?.parse()?;
+        Ok(format!(
+            r#"<div class="diagram">{}</div>"#,
+            Layout::new(&graph).render()
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // The identifier, number or quoted string.
+    Id(String),
+    // The edge operator `->` or `--`.
+    EdgeOp,
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                chars.find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            '-' if matches!(chars.peek(), Some('>' | '-')) => {
+                chars.next();
+                tokens.push(Token::EdgeOp);
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('"') => value.push('"'),
+                            // The line breaks of label.
+                            Some('n' | 'l' | 'r') => value.push('\n'),
+                            Some(c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                            None => bail!("Unterminated string"),
+                        },
+                        Some(c) => value.push(c),
+                        None => bail!("Unterminated string"),
+                    }
+                }
+                tokens.push(Token::Id(value));
+            }
+            '{' | '}' | '[' | ']' | '=' | ';' | ',' => tokens.push(Token::Symbol(c)),
+            c if c.is_alphanumeric() || matches!(c, '_' | '.' | '-') => {
+                let mut value = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | '.'))
+                {
+                    value.push(c);
+                }
+                tokens.push(Token::Id(value));
+            }
+            c => bail!("Unexpected character `{}`", c),
+        }
+    }
+    Ok(tokens)
+}
+
+type Attributes = HashMap<String, String>;
+
+#[derive(Debug)]
+struct Graph {
+    directed: bool,
+    attrs: Attributes,
+    // The node ids and attributes in declaration order.
+    nodes: Vec<(String, Attributes)>,
+    // The source and target node index, and the attributes.
+    edges: Vec<(usize, usize, Attributes)>,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    graph: Graph,
+    node_defaults: Attributes,
+    edge_defaults: Attributes,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self> {
+        Ok(Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            graph: Graph {
+                directed: true,
+                attrs: Attributes::new(),
+                nodes: vec![],
+                edges: vec![],
+            },
+            node_defaults: Attributes::new(),
+            edge_defaults: Attributes::new(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_id(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Id(id)) => Ok(id),
+            _ => bail!("Expect an identifier"),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    fn parse(mut self) -> Result<Graph> {
+        if self.is_keyword("strict") {
+            self.pos += 1;
+        }
+        if self.is_keyword("graph") {
+            self.graph.directed = false;
+        } else if !self.is_keyword("digraph") {
+            bail!("Expect `graph` or `digraph`");
+        }
+        self.pos += 1;
+        // The optional graph name.
+        if let Some(Token::Id(_)) = self.peek() {
+            self.pos += 1;
+        }
+        if !self.eat('{') {
+            bail!("Expect `{{`");
+        }
+        self.parse_statements()?;
+        if self.peek().is_some() {
+            bail!("Unexpected content after `}}`");
+        }
+        if self.graph.nodes.is_empty() {
+            bail!("The diagram has no nodes");
+        }
+        Ok(self.graph)
+    }
+
+    // Parse the statements until the closing `}`, return the nodes in statements.
+    fn parse_statements(&mut self) -> Result<Vec<usize>> {
+        let mut nodes = vec![];
+        loop {
+            if self.eat('}') {
+                return Ok(nodes);
+            }
+            if self.peek().is_none() {
+                bail!("Missing `}}`");
+            }
+            nodes.extend(self.parse_statement()?);
+            while self.eat(';') || self.eat(',') {}
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Vec<usize>> {
+        for (keyword, is_node) in [("node", true), ("edge", false)] {
+            if self.is_keyword(keyword) {
+                self.pos += 1;
+                let attrs = self.parse_attributes()?;
+                let defaults = if is_node {
+                    &mut self.node_defaults
+                } else {
+                    &mut self.edge_defaults
+                };
+                defaults.extend(attrs);
+                return Ok(vec![]);
+            }
+        }
+        if self.is_keyword("graph") {
+            self.pos += 1;
+            let attrs = self.parse_attributes()?;
+            self.graph.attrs.extend(attrs);
+            return Ok(vec![]);
+        }
+        // The graph attribute, such as `rankdir = LR`.
+        if let (Some(Token::Id(key)), Some(Token::Symbol('='))) =
+            (self.peek().cloned(), self.tokens.get(self.pos + 1))
+        {
+            self.pos += 2;
+            let value = self.expect_id()?;
+            self.graph.attrs.insert(key, value);
+            return Ok(vec![]);
+        }
+
+        let mut operand = self.parse_operand()?;
+        let mut nodes = operand.clone();
+        let mut edges = vec![];
+        while self.peek() == Some(&Token::EdgeOp) {
+            self.pos += 1;
+            let target = self.parse_operand()?;
+            for from in &operand {
+                for to in &target {
+                    edges.push((*from, *to));
+                }
+            }
+            nodes.extend(&target);
+            operand = target;
+        }
+        let attrs = self.parse_attributes()?;
+        if edges.is_empty() {
+            // The node statement, such as `a [label = "A"]`.
+            for node in &operand {
+                self.graph.nodes[*node].1.extend(attrs.clone());
+            }
+        } else {
+            for (from, to) in edges {
+                let mut edge_attrs = self.edge_defaults.clone();
+                edge_attrs.extend(attrs.clone());
+                self.graph.edges.push((from, to, edge_attrs));
+            }
+        }
+        Ok(nodes)
+    }
+
+    // Parse a node id, or a subgraph such as `{ a b }` and `subgraph cluster { a b }`.
+    fn parse_operand(&mut self) -> Result<Vec<usize>> {
+        if self.is_keyword("subgraph") {
+            self.pos += 1;
+            if let Some(Token::Id(_)) = self.peek() {
+                self.pos += 1;
+            }
+        }
+        if self.eat('{') {
+            return self.parse_statements();
+        }
+        let id = self.expect_id()?;
+        Ok(vec![self.node_index(id)])
+    }
+
+    fn node_index(&mut self, id: String) -> usize {
+        match self.graph.nodes.iter().position(|(node, _)| *node == id) {
+            Some(index) => index,
+            None => {
+                self.graph.nodes.push((id, self.node_defaults.clone()));
+                self.graph.nodes.len() - 1
+            }
+        }
+    }
+
+    // Parse the optional attribute lists, such as `[label = "A", shape = box]`.
+    fn parse_attributes(&mut self) -> Result<Attributes> {
+        let mut attrs = Attributes::new();
+        while self.eat('[') {
+            while !self.eat(']') {
+                let key = self.expect_id()?;
+                if !self.eat('=') {
+                    bail!("Expect `=` after attribute `{}`", key);
+                }
+                attrs.insert(key, self.expect_id()?);
+                while self.eat(';') || self.eat(',') {}
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Shape {
+    Box,
+    Ellipse,
+    Circle,
+    Diamond,
+    Plaintext,
+}
+
+impl Shape {
+    fn parse(shape: Option<&String>) -> Self {
+        match shape.map(String::as_str) {
+            Some("box" | "rect" | "rectangle" | "square") => Shape::Box,
+            Some("circle" | "doublecircle" | "point") => Shape::Circle,
+            Some("diamond") => Shape::Diamond,
+            Some("plaintext" | "plain" | "none") => Shape::Plaintext,
+            _ => Shape::Ellipse,
+        }
+    }
+}
+
+struct NodeBox {
+    label: Vec<String>,
+    shape: Shape,
+    width: f64,
+    height: f64,
+    // The center of node.
+    x: f64,
+    y: f64,
+}
+
+struct Layout<'a> {
+    graph: &'a Graph,
+    nodes: Vec<NodeBox>,
+    width: f64,
+    height: f64,
+}
+
+// Estimate the text width, the CJK characters are wider.
+fn text_width(text: &str) -> f64 {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii() {
+                FONT_SIZE * 0.6
+            } else {
+                FONT_SIZE
+            }
+        })
+        .sum()
+}
+
+impl<'a> Layout<'a> {
+    fn new(graph: &'a Graph) -> Self {
+        let mut nodes = graph
+            .nodes
+            .iter()
+            .map(|(id, attrs)| {
+                let mut label = attrs
+                    .get("label")
+                    .unwrap_or(id)
+                    .lines()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                // The empty label still takes one line, such as `a [label = ""]`.
+                if label.is_empty() {
+                    label.push(String::new());
+                }
+                let shape = Shape::parse(attrs.get("shape"));
+                let text_width = label
+                    .iter()
+                    .map(|line| text_width(line))
+                    .fold(0.0, f64::max);
+                let (mut width, mut height) = (
+                    text_width + NODE_PADDING * 2.0,
+                    label.len() as f64 * LINE_HEIGHT + NODE_PADDING,
+                );
+                match shape {
+                    Shape::Ellipse => width += NODE_PADDING * 2.0,
+                    Shape::Circle => {
+                        width = width.max(height);
+                        height = width;
+                    }
+                    Shape::Diamond => {
+                        width *= 1.6;
+                        height *= 1.6;
+                    }
+                    _ => {}
+                }
+                NodeBox {
+                    label,
+                    shape,
+                    width,
+                    height,
+                    x: 0.0,
+                    y: 0.0,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let ranks = order_ranks(graph);
+        let horizontal = matches!(
+            graph.attrs.get("rankdir").map(String::as_str),
+            Some("LR" | "RL")
+        );
+        // Layout along the rank axis and the cross axis, which is
+        // the y and x axis in top-bottom direction, or the reverse in left-right.
+        let extent = |node: &NodeBox| {
+            if horizontal {
+                (node.width, node.height)
+            } else {
+                (node.height, node.width)
+            }
+        };
+        let rank_sizes = ranks
+            .iter()
+            .map(|rank| {
+                rank.iter()
+                    .map(|node| extent(&nodes[*node]).0)
+                    .fold(0.0, f64::max)
+            })
+            .collect::<Vec<_>>();
+        let cross_sizes = ranks
+            .iter()
+            .map(|rank| {
+                rank.iter().map(|node| extent(&nodes[*node]).1).sum::<f64>()
+                    + NODE_GAP * (rank.len().saturating_sub(1)) as f64
+            })
+            .collect::<Vec<_>>();
+        let cross_size = cross_sizes.iter().copied().fold(0.0, f64::max);
+
+        let mut main = MARGIN;
+        for ((rank, rank_size), rank_cross_size) in ranks.iter().zip(&rank_sizes).zip(&cross_sizes)
+        {
+            let mut cross = MARGIN + (cross_size - rank_cross_size) / 2.0;
+            for node in rank {
+                let node_cross = extent(&nodes[*node]).1;
+                let (main_pos, cross_pos) = (main + rank_size / 2.0, cross + node_cross / 2.0);
+                let node = &mut nodes[*node];
+                if horizontal {
+                    node.x = main_pos;
+                    node.y = cross_pos;
+                } else {
+                    node.x = cross_pos;
+                    node.y = main_pos;
+                }
+                cross += node_cross + NODE_GAP;
+            }
+            main += rank_size + RANK_GAP;
+        }
+        let main_size = main - RANK_GAP + MARGIN;
+        let cross_size = cross_size + MARGIN * 2.0;
+        // Reserve the space for self-loops on the right side.
+        let loop_space = if graph.edges.iter().any(|(from, to, _)| from == to) {
+            NODE_GAP
+        } else {
+            0.0
+        };
+        let (width, height) = if horizontal {
+            (main_size + loop_space, cross_size)
+        } else {
+            (cross_size + loop_space, main_size)
+        };
+
+        Layout {
+            graph,
+            nodes,
+            width,
+            height,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut svg = String::new();
+        let _ = write!(
+            &mut svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w:.0} {h:.0}" width="{w:.0}" height="{h:.0}" role="img">"#,
+            w = self.width.ceil(),
+            h = self.height.ceil(),
+        );
+        let _ = write!(
+            &mut svg,
+            r#"<g fill="none" stroke="currentColor" font-family="sans-serif" font-size="{}" text-anchor="middle">"#,
+            FONT_SIZE
+        );
+        for (from, to, attrs) in &self.graph.edges {
+            self.render_edge(&mut svg, *from, *to, attrs);
+        }
+        for node in &self.nodes {
+            render_node(&mut svg, node);
+        }
+        svg.push_str("</g></svg>");
+        svg
+    }
+
+    fn render_edge(&self, svg: &mut String, from: usize, to: usize, attrs: &Attributes) {
+        let dash = match attrs.get("style").map(String::as_str) {
+            Some("dashed") => r#" stroke-dasharray="6 4""#,
+            Some("dotted") => r#" stroke-dasharray="2 3""#,
+            _ => "",
+        };
+        let (source, target) = (&self.nodes[from], &self.nodes[to]);
+        let (start, end, label_pos) = if from == to {
+            // The self-loop on the right side of node.
+            let x = source.x + source.width / 2.0;
+            let (top, bottom) = (source.y - 8.0, source.y + 8.0);
+            let _ = write!(
+                svg,
+                r#"<path d="M{x:.1},{top:.1} C{c:.1},{t:.1} {c:.1},{b:.1} {x:.1},{bottom:.1}"{dash}/>"#,
+                x = x,
+                top = top,
+                bottom = bottom,
+                c = x + NODE_GAP,
+                t = top - NODE_GAP / 2.0,
+                b = bottom + NODE_GAP / 2.0,
+                dash = dash,
+            );
+            (
+                (x + NODE_GAP, bottom + NODE_GAP / 2.0),
+                (x, bottom),
+                (x + NODE_GAP, source.y),
+            )
+        } else {
+            let (dx, dy) = (target.x - source.x, target.y - source.y);
+            let (t_start, t_end) = (boundary(source, dx, dy), boundary(target, dx, dy));
+            let start = (source.x + dx * t_start, source.y + dy * t_start);
+            let end = (target.x - dx * t_end, target.y - dy * t_end);
+            let _ = write!(
+                svg,
+                r#"<path d="M{:.1},{:.1} L{:.1},{:.1}"{}/>"#,
+                start.0, start.1, end.0, end.1, dash
+            );
+            (
+                start,
+                end,
+                ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0),
+            )
+        };
+
+        if self.graph.directed && attrs.get("dir").map(String::as_str) != Some("none") {
+            render_arrow(svg, start, end);
+        }
+        if let Some(label) = attrs.get("label") {
+            // Place the label at the upper right of the midpoint to keep it off the line.
+            let (x, y) = (label_pos.0 + 4.0, label_pos.1 - LINE_HEIGHT / 2.0);
+            render_text(svg, label.lines(), x, y, "start");
+        }
+    }
+}
+
+// The scale of the direction (dx, dy) from the node center to its boundary.
+fn boundary(node: &NodeBox, dx: f64, dy: f64) -> f64 {
+    let (rx, ry) = (node.width / 2.0, node.height / 2.0);
+    let (dx, dy) = (dx.abs(), dy.abs());
+    match node.shape {
+        Shape::Box | Shape::Plaintext => {
+            let tx = if dx > 0.0 { rx / dx } else { f64::INFINITY };
+            let ty = if dy > 0.0 { ry / dy } else { f64::INFINITY };
+            tx.min(ty)
+        }
+        Shape::Ellipse | Shape::Circle => 1.0 / ((dx / rx).powi(2) + (dy / ry).powi(2)).sqrt(),
+        Shape::Diamond => 1.0 / (dx / rx + dy / ry),
+    }
+}
+
+fn render_node(svg: &mut String, node: &NodeBox) {
+    let (x, y, rx, ry) = (node.x, node.y, node.width / 2.0, node.height / 2.0);
+    let _ = match node.shape {
+        Shape::Box => write!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" rx="4"/>"#,
+            x - rx,
+            y - ry,
+            node.width,
+            node.height
+        ),
+        Shape::Ellipse | Shape::Circle => write!(
+            svg,
+            r#"<ellipse cx="{:.1}" cy="{:.1}" rx="{:.1}" ry="{:.1}"/>"#,
+            x, y, rx, ry
+        ),
+        Shape::Diamond => write!(
+            svg,
+            r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1} {:.1},{:.1}"/>"#,
+            x,
+            y - ry,
+            x + rx,
+            y,
+            x,
+            y + ry,
+            x - rx,
+            y
+        ),
+        Shape::Plaintext => Ok(()),
+    };
+    render_text(
+        svg,
+        node.label.iter().map(String::as_str),
+        x,
+        y - (node.label.len() - 1) as f64 * LINE_HEIGHT / 2.0,
+        "middle",
+    );
+}
+
+fn render_text<'t>(
+    svg: &mut String,
+    lines: impl Iterator<Item = &'t str>,
+    x: f64,
+    y: f64,
+    anchor: &str,
+) {
+    let _ = write!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" fill="currentColor" stroke="none" dominant-baseline="central" text-anchor="{}">"#,
+        x, y, anchor
+    );
+    for (i, line) in lines.enumerate() {
+        let dy = if i == 0 { 0.0 } else { LINE_HEIGHT };
+        let _ = write!(
+            svg,
+            r#"<tspan x="{:.1}" dy="{}">{}</tspan>"#,
+            x,
+            dy,
+            escape_html(line)
+        );
+    }
+    svg.push_str("</text>");
+}
+
+fn render_arrow(svg: &mut String, start: (f64, f64), end: (f64, f64)) {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0.0 {
+        return;
+    }
+    let (ux, uy) = (dx / len, dy / len);
+    let (bx, by) = (end.0 - ux * ARROW_SIZE, end.1 - uy * ARROW_SIZE);
+    let (px, py) = (-uy * ARROW_SIZE / 2.0, ux * ARROW_SIZE / 2.0);
+    let _ = write!(
+        svg,
+        r#"<polygon points="{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}" fill="currentColor"/>"#,
+        end.0,
+        end.1,
+        bx + px,
+        by + py,
+        bx - px,
+        by - py
+    );
+}
+
+// Assign the nodes into ranks by the longest path from the sources,
+// then order the nodes in each rank by the barycenter of their neighbors
+// to reduce the edge crossings.
+fn order_ranks(graph: &Graph) -> Vec<Vec<usize>> {
+    let count = graph.nodes.len();
+    let edges = acyclic_edges(graph);
+
+    // The longest path ranking in topological order.
+    let mut rank = vec![0; count];
+    let mut in_degree = vec![0; count];
+    for (_, to) in &edges {
+        in_degree[*to] += 1;
+    }
+    let mut queue = (0..count)
+        .filter(|node| in_degree[*node] == 0)
+        .collect::<Vec<_>>();
+    while let Some(node) = queue.pop() {
+        for (from, to) in &edges {
+            if *from == node {
+                rank[*to] = rank[*to].max(rank[node] + 1);
+                in_degree[*to] -= 1;
+                if in_degree[*to] == 0 {
+                    queue.push(*to);
+                }
+            }
+        }
+    }
+
+    let rank_count = rank.iter().max().map_or(0, |max| max + 1);
+    let mut ranks = vec![vec![]; rank_count];
+    for node in 0..count {
+        ranks[rank[node]].push(node);
+    }
+
+    let mut position = vec![0.0; count];
+    let update_positions = |ranks: &Vec<Vec<usize>>, position: &mut Vec<f64>| {
+        for rank in ranks {
+            for (i, node) in rank.iter().enumerate() {
+                position[*node] = i as f64;
+            }
+        }
+    };
+    update_positions(&ranks, &mut position);
+    for sweep in 0..4 {
+        let downward = sweep % 2 == 0;
+        let order = (1..rank_count)
+            .map(|i| if downward { i } else { rank_count - 1 - i })
+            .collect::<Vec<_>>();
+        for i in order {
+            let neighbor_rank = if downward { i - 1 } else { i + 1 };
+            let mut barycenters = ranks[i]
+                .iter()
+                .map(|node| {
+                    let neighbors = edges
+                        .iter()
+                        .filter_map(|(from, to)| match (*from == *node, *to == *node) {
+                            (true, _) => Some(*to),
+                            (_, true) => Some(*from),
+                            _ => None,
+                        })
+                        .filter(|neighbor| rank[*neighbor] == neighbor_rank)
+                        .map(|neighbor| position[neighbor])
+                        .collect::<Vec<_>>();
+                    let barycenter = if neighbors.is_empty() {
+                        position[*node]
+                    } else {
+                        neighbors.iter().sum::<f64>() / neighbors.len() as f64
+                    };
+                    (*node, barycenter)
+                })
+                .collect::<Vec<_>>();
+            barycenters.sort_by(|a, b| a.1.total_cmp(&b.1));
+            ranks[i] = barycenters.into_iter().map(|(node, _)| node).collect();
+            update_positions(&ranks, &mut position);
+        }
+    }
+    ranks
+}
+
+// The edges without self-loops, and the edges closing a cycle are reversed.
+fn acyclic_edges(graph: &Graph) -> Vec<(usize, usize)> {
+    let count = graph.nodes.len();
+    // 0: unvisited, 1: visiting, 2: visited.
+    let mut state = vec![0; count];
+    let mut edges = vec![];
+    fn visit(node: usize, graph: &Graph, state: &mut Vec<u8>, edges: &mut Vec<(usize, usize)>) {
+        state[node] = 1;
+        for (from, to, _) in &graph.edges {
+            if *from != node || from == to {
+                continue;
+            }
+            match state[*to] {
+                0 => {
+                    edges.push((*from, *to));
+                    visit(*to, graph, state, edges);
+                }
+                // The back edge closing a cycle.
+                1 => edges.push((*to, *from)),
+                _ => edges.push((*from, *to)),
+            }
+        }
+        state[node] = 2;
+    }
+    for node in 0..count {
+        if state[node] == 0 {
+            visit(node, graph, &mut state, &mut edges);
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::code_blocks::CodeBlock;
+
+    use super::{order_ranks, DiagramBlock, Parser};
+
+    #[test]
+    fn test_parse_diagram() {
+        let graph = Parser::new(
+            r#"digraph G {
+                rankdir = LR; // comment
+                node [shape = box];
+                a [label = "Web\nServer"];
+                a -> b -> { c d } [label = "call", style = dashed];
+                subgraph cluster { e }
+            }"#,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+        assert!(graph.directed);
+        assert_eq!(graph.attrs["rankdir"], "LR");
+        let ids = graph
+            .nodes
+            .iter()
+            .map(|(id, _)| id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(graph.nodes[0].1["label"], "Web\nServer");
+        assert_eq!(graph.nodes[4].1["shape"], "box");
+        let edges = graph
+            .edges
+            .iter()
+            .map(|(from, to, _)| (*from, *to))
+            .collect::<Vec<_>>();
+        assert_eq!(edges, vec![(0, 1), (1, 2), (1, 3)]);
+        assert_eq!(graph.edges[2].2["style"], "dashed");
+
+        assert!(Parser::new("digraph { a -> }").unwrap().parse().is_err());
+        assert!(Parser::new("digraph { a -> b").unwrap().parse().is_err());
+        assert!(Parser::new("flowchart { a }").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_order_ranks() {
+        let graph = Parser::new("digraph { a -> b -> c -> a; a -> d; d -> d }")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(order_ranks(&graph), vec![vec![0], vec![1, 3], vec![2]]);
+    }
+
+    #[test]
+    fn test_render_diagram() {
+        let html = DiagramBlock::new("graph { a -- \"<b>\" }")
+            .render()
+            .unwrap();
+        assert!(html.starts_with(r#"<div class="diagram"><svg "#));
+        assert!(html.contains("&lt;b&gt;"));
+        // Undirected graph has no arrows.
+        assert!(!html.contains("<polygon"));
+        assert_eq!(html.matches("<ellipse").count(), 2);
+
+        let html = DiagramBlock::new(r#"digraph { a [label = ""]; a -> b }"#)
+            .render()
+            .unwrap();
+        assert_eq!(html.matches("<ellipse").count(), 2);
+        assert!(!html.contains("NaN"));
+    }
+}
@@ -0,0 +1,363 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Write};
+
+use anyhow::{anyhow, bail, Result};
+use hyper::Uri;
+
+use crate::{
+    data, engine,
+    html::{escape_html, escape_url},
+};
+
+use super::CodeBlock;
+
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "ogg", "oga", "opus", "wav", "flac"];
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "webm", "ogv", "mov"];
+
+/// The code block to embed a video, audio or social post, such as ```` ```embed ````
+/// with a YouTube, Vimeo or Bilibili URL, an X, Mastodon or Bluesky post URL,
+/// or a local audio or video file in the body.
+///
+/// The videos of providers are rendered as a click-to-load facade, nothing is
+/// loaded from the provider until the reader clicks it. The social posts are
+/// rendered as static quotes without the provider's script. The title and text
+/// are taken from the cached URL preview if available.
+///
+/// The options:
+/// - `title`: the title of the video or audio, or the author of the post.
+/// - `poster`: the local poster image of the video, the remote thumbnail of
+///   the provider is never loaded.
+pub struct EmbedBlock<'a> {
+    url: &'a str,
+    options: HashMap<String, Cow<'a, str>>,
+    visitor: engine::Vistor<'a>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Embed {
+    // The provider name and the player URL.
+    Player(&'static str, String),
+    // The provider name of the social post.
+    Post(&'static str),
+    Audio,
+    Video,
+}
+
+impl<'a> EmbedBlock<'a> {
+    pub fn new(
+        options: HashMap<String, Cow<'a, str>>,
+        block: &'a str,
+        visitor: engine::Vistor<'a>,
+    ) -> Self {
+        EmbedBlock {
+            url: block.trim(),
+            options,
+            visitor,
+        }
+    }
+
+    fn render_player(&self, provider: &str, player: &str) -> Result<String> {
+        let href = escape_url(self.url).ok_or_else(|| anyhow!("Unsafe URL `{}`", self.url))?;
+        let data = data::read();
+        let preview = data.url_previews().get(self.url);
+        let title = match self.options.get("title") {
+            Some(title) => Cow::Borrowed(title.as_ref()),
+            None => match preview {
+                Some(info) if !info.title.is_empty() => Cow::Borrowed(info.title.as_str()),
+                _ => Cow::Owned(format!("{} video", provider)),
+            },
+        };
+        let poster = match self.options.get("poster") {
+            Some(poster) => Some(self.resolve_url(poster)?),
+            None => None,
+        };
+
+        let mut html = String::new();
+        writeln!(
+            &mut html,
+            r#"<div class="embed embed-player" data-embed-src="{}" data-embed-title="{}">"#,
+            escape_html(player),
+            escape_html(&title)
+        )?;
+        writeln!(
+            &mut html,
+            r#" <a class="embed-facade" href="{}" target="_blank" rel="noopener" aria-label="Play: {}">"#,
+            href,
+            escape_html(&title)
+        )?;
+        if let Some(poster) = poster {
+            writeln!(
+                &mut html,
+                r#"  <img class="embed-poster" src="{}" alt="" loading="lazy">"#,
+                poster
+            )?;
+        }
+        writeln!(
+            &mut html,
+            r#"  <span class="embed-title">{}</span>"#,
+            escape_html(&title)
+        )?;
+        writeln!(&mut html, r#"  <span class="embed-play"></span>"#)?;
+        writeln!(
+            &mut html,
+            r#"  <span class="embed-provider">{}</span>"#,
+            provider
+        )?;
+        writeln!(&mut html, " </a>")?;
+        writeln!(&mut html, "</div>")?;
+        Ok(html)
+    }
+
+    fn render_post(&self, provider: &str) -> Result<String> {
+        let href = escape_url(self.url).ok_or_else(|| anyhow!("Unsafe URL `{}`", self.url))?;
+        let data = data::read();
+        let preview = data.url_previews().get(self.url);
+        let author = match self.options.get("title") {
+            Some(title) => Some(title.as_ref()),
+            None => preview
+                .map(|info| info.title.as_str())
+                .filter(|title| !title.is_empty()),
+        };
+        let text = preview
+            .map(|info| info.description.as_str())
+            .filter(|text| !text.is_empty());
+
+        let mut html = String::new();
+        writeln!(
+            &mut html,
+            r#"<blockquote class="embed embed-post" cite="{}">"#,
+            href
+        )?;
+        if let Some(author) = author {
+            writeln!(
+                &mut html,
+                r#" <p class="embed-post-author">{}</p>"#,
+                escape_html(author)
+            )?;
+        }
+        if let Some(text) = text {
+            writeln!(
+                &mut html,
+                r#" <p class="embed-post-text">{}</p>"#,
+                escape_html(text)
+            )?;
+        }
+        writeln!(
+            &mut html,
+            r#" <a class="embed-post-link" href="{}" target="_blank" rel="noopener">View on {}</a>"#,
+            href, provider
+        )?;
+        writeln!(&mut html, "</blockquote>")?;
+        Ok(html)
+    }
+
+    fn render_media(&self, tag: &str) -> Result<String> {
+        let src = self.resolve_url(self.url)?;
+        let title = self.options.get("title");
+
+        let mut html = String::new();
+        writeln!(&mut html, r#"<figure class="embed embed-media">"#)?;
+        write!(
+            &mut html,
+            r#"<{} src="{}" controls preload="metadata""#,
+            tag, src
+        )?;
+        if let Some(poster) = self.options.get("poster").filter(|_| tag == "video") {
+            write!(&mut html, r#" poster="{}""#, self.resolve_url(poster)?)?;
+        }
+        if let Some(title) = title {
+            write!(&mut html, r#" title="{}""#, escape_html(title))?;
+        }
+        writeln!(&mut html, "></{}>", tag)?;
+        if let Some(title) = title {
+            writeln!(&mut html, "<figcaption>{}</figcaption>", escape_html(title))?;
+        }
+        writeln!(&mut html, "</figure>")?;
+        Ok(html)
+    }
+
+    // Resolve the local file relative to the markdown file into URL.
+    fn resolve_url(&self, link: &str) -> Result<String> {
+        let url = self
+            .visitor
+            .resolve_link(link)
+            .unwrap_or_else(|| link.to_owned());
+        match escape_url(&url) {
+            Some(url) => Ok(url.into_owned()),
+            None => bail!("Unsafe URL `{}`", link),
+        }
+    }
+}
+
+impl<'a> CodeBlock for EmbedBlock<'a> {
+    fn render(&self) -> Result<String> {
+        if self.url.is_empty() {
+            bail!("Missing the URL to embed");
+        }
+        match parse_embed(self.url) {
+            Some(Embed::Player(provider, player)) => self.render_player(provider, &player),
+            Some(Embed::Post(provider)) => self.render_post(provider),
+            Some(Embed::Audio) => self.render_media("audio"),
+            Some(Embed::Video) => self.render_media("video"),
+            None => bail!("Unsupported URL `{}` to embed", self.url),
+        }
+    }
+}
+
+/// Whether the `url` is a video or social post of the supported providers,
+/// whose URL preview is fetched as the metadata.
+pub fn is_provider_url(url: &str) -> bool {
+    matches!(parse_embed(url), Some(Embed::Player(..) | Embed::Post(_)))
+}
+
+fn parse_embed(url: &str) -> Option<Embed> {
+    let path = url.split(['#', '?']).next().unwrap_or_default();
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    if AUDIO_EXTENSIONS.contains(&ext.as_str()) {
+        return Some(Embed::Audio);
+    }
+    if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+        return Some(Embed::Video);
+    }
+
+    let uri = url.parse::<Uri>().ok()?;
+    if !matches!(uri.scheme_str(), Some("http" | "https")) {
+        return None;
+    }
+    let host = uri
+        .host()?
+        .trim_start_matches("www.")
+        .trim_start_matches("m.");
+    let segments = uri
+        .path()
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    let query = |key: &str| {
+        uri.query()?
+            .split('&')
+            .find_map(|pair| match pair.split_once('=') {
+                Some((k, v)) if k == key && !v.is_empty() => Some(v),
+                _ => None,
+            })
+    };
+    let is_id = |id: &str| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+
+    match host {
+        "youtube.com" | "youtu.be" | "youtube-nocookie.com" => {
+            let id = match segments.as_slice() {
+                [id] if host == "youtu.be" => *id,
+                ["watch"] => query("v")?,
+                ["embed" | "shorts" | "live", id] => *id,
+                _ => return None,
+            };
+            if !is_id(id) {
+                return None;
+            }
+            let mut player = format!("https://www.youtube-nocookie.com/embed/{}?autoplay=1", id);
+            // The start time, such as `t=90` or `t=90s`.
+            if let Some(start) = query("t").or_else(|| query("start")) {
+                let start = start.trim_end_matches('s');
+                if !start.is_empty() && start.chars().all(|c| c.is_ascii_digit()) {
+                    let _ = write!(player, "&start={}", start);
+                }
+            }
+            Some(Embed::Player("YouTube", player))
+        }
+        "vimeo.com" | "player.vimeo.com" => {
+            let id = match segments.as_slice() {
+                ["video", id] if host == "player.vimeo.com" => *id,
+                [id] => *id,
+                _ => return None,
+            };
+            if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            Some(Embed::Player(
+                "Vimeo",
+                format!("https://player.vimeo.com/video/{}?autoplay=1&dnt=1", id),
+            ))
+        }
+        "bilibili.com" => {
+            let id = match segments.as_slice() {
+                ["video", id] => *id,
+                _ => return None,
+            };
+            let mut player = match id.strip_prefix("av") {
+                Some(aid) if aid.chars().all(|c| c.is_ascii_digit()) => {
+                    format!("https://player.bilibili.com/player.html?aid={}", aid)
+                }
+                _ if id.starts_with("BV") && is_id(id) => {
+                    format!("https://player.bilibili.com/player.html?bvid={}", id)
+                }
+                _ => return None,
+            };
+            if let Some(page) = query("p").filter(|p| p.chars().all(|c| c.is_ascii_digit())) {
+                let _ = write!(player, "&page={}", page);
+            }
+            player.push_str("&autoplay=1");
+            Some(Embed::Player("Bilibili", player))
+        }
+        "twitter.com" | "x.com" => match segments.as_slice() {
+            [_, "status", id] if id.chars().all(|c| c.is_ascii_digit()) => Some(Embed::Post("X")),
+            _ => None,
+        },
+        "bsky.app" => match segments.as_slice() {
+            ["profile", _, "post", id] if is_id(id) => Some(Embed::Post("Bluesky")),
+            _ => None,
+        },
+        // The Mastodon instances are on any host, such as `mastodon.social/@user/123`.
+        _ => match segments.as_slice() {
+            [user, id]
+                if user.len() > 1
+                    && user.starts_with('@')
+                    && id.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Some(Embed::Post("Mastodon"))
+            }
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{parse_embed, Embed};
+
+    #[test_case("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s", "YouTube", "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?autoplay=1&start=42"; "youtube")]
+    #[test_case("https://youtu.be/dQw4w9WgXcQ", "YouTube", "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?autoplay=1"; "youtube short link")]
+    #[test_case("https://m.youtube.com/shorts/dQw4w9WgXcQ", "YouTube", "https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?autoplay=1"; "youtube shorts")]
+    #[test_case("https://vimeo.com/76979871", "Vimeo", "https://player.vimeo.com/video/76979871?autoplay=1&dnt=1"; "vimeo")]
+    #[test_case("https://www.bilibili.com/video/BV1GJ411x7h7?p=2", "Bilibili", "https://player.bilibili.com/player.html?bvid=BV1GJ411x7h7&page=2&autoplay=1"; "bilibili")]
+    #[test_case("https://www.bilibili.com/video/av170001", "Bilibili", "https://player.bilibili.com/player.html?aid=170001&autoplay=1"; "bilibili av")]
+    fn test_parse_player(url: &str, provider: &'static str, player: &str) {
+        assert_eq!(
+            parse_embed(url),
+            Some(Embed::Player(provider, player.to_owned()))
+        );
+    }
+
+    #[test_case("https://x.com/rustlang/status/1234567890", Some(Embed::Post("X")); "x post")]
+    #[test_case("https://twitter.com/rustlang/status/1234567890?s=20", Some(Embed::Post("X")); "twitter post")]
+    #[test_case("https://mastodon.social/@rust/112233445566", Some(Embed::Post("Mastodon")); "mastodon post")]
+    #[test_case("https://bsky.app/profile/rust-lang.org/post/3kabc123", Some(Embed::Post("Bluesky")); "bluesky post")]
+    #[test_case("https://x.com/rustlang", None; "x profile")]
+    #[test_case("./talk.mp3", Some(Embed::Audio); "audio")]
+    #[test_case("/static/demo.WebM?v=1", Some(Embed::Video); "video")]
+    #[test_case("https://www.youtube.com/watch?v=<script>", None; "invalid id")]
+    #[test_case("https://vimeo.com/channels/staffpicks", None; "vimeo channel")]
+    #[test_case("javascript:alert(1)", None; "javascript scheme")]
+    #[test_case("https://example.com/talk", None; "unsupported")]
+    fn test_parse_embed(url: &str, expected: Option<Embed>) {
+        assert_eq!(parse_embed(url), expected);
+    }
+}
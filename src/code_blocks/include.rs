@@ -0,0 +1,198 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::engine;
+
+use super::{code::parse_line_ranges, CodeBlock};
+
+/// The code block to include the code snippet from a source file,
+/// such as ```` ```include, file: examples/main.rs, lines: 10-30 ````.
+///
+/// The options:
+/// - `file`: the file path relative to the markdown file,
+///   or relative to the root directory if it starts with `/`.
+///   The file must be inside the root directory.
+/// - `lines`: the included lines, such as `10-30` or `"1, 10-30"`.
+/// - `region`: the named region between the `ANCHOR: name` and `ANCHOR_END: name` comments.
+/// - `lang`: the language of code, default to the file extension.
+///
+/// Other options such as `linenos`, `hl_lines` and `title` are the same as the ordinary code.
+pub struct IncludeBlock<'a> {
+    options: HashMap<String, Cow<'a, str>>,
+    visitor: engine::Vistor<'a>,
+}
+
+impl<'a> IncludeBlock<'a> {
+    pub fn new(options: HashMap<String, Cow<'a, str>>, visitor: engine::Vistor<'a>) -> Self {
+        IncludeBlock { options, visitor }
+    }
+}
+
+impl<'a> CodeBlock for IncludeBlock<'a> {
+    fn render(&self) -> Result<String> {
+        let file = self
+            .options
+            .get("file")
+            .ok_or_else(|| anyhow!("Missing the `file` option"))?;
+        let path = resolve_file(self.visitor.root(), self.visitor.path(), file)?;
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read file `{}`", path.display()))?;
+
+        let code = match self.options.get("region") {
+            Some(region) => select_region(&source, region)?,
+            None => source.lines().collect(),
+        };
+        let code = match self.options.get("lines") {
+            Some(lines) => select_lines(&code, lines)?,
+            None => code,
+        };
+
+        let lang = match self.options.get("lang") {
+            Some(lang) => lang,
+            None => path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default(),
+        };
+        let mut text = code.join("\n");
+        text.push('\n');
+        Ok(self.visitor.render_code(lang, &self.options, &text))
+    }
+}
+
+// Resolve the included `file` relative to the markdown file `path`, or relative to
+// the `root` directory if it starts with `/`. The file must be inside the root directory,
+// otherwise any file of the building machine could be included.
+fn resolve_file(root: &Path, path: Option<&Path>, file: &str) -> Result<PathBuf> {
+    let joined = match file.strip_prefix('/') {
+        Some(file) => root.join(file),
+        None => {
+            let dir = path
+                .and_then(Path::parent)
+                .ok_or_else(|| anyhow!("Relative file `{}` outside of markdown file", file))?;
+            root.join(dir).join(file)
+        }
+    };
+    // Normalize the `..` components and symlinks.
+    let resolved = fs::canonicalize(&joined)
+        .with_context(|| format!("Failed to read file `{}`", joined.display()))?;
+    let root = fs::canonicalize(root)?;
+    if !resolved.starts_with(&root) {
+        bail!("File `{}` is outside of the root directory", file);
+    }
+    Ok(resolved)
+}
+
+// Select the `lines` of code, such as `10-30` or `1, 10-30`. The lines out of range
+// are an error, the file has probably changed since the lines were written.
+fn select_lines<'s>(code: &[&'s str], lines: &str) -> Result<Vec<&'s str>> {
+    let mut selected = vec![];
+    for range in parse_line_ranges(lines) {
+        if *range.start() == 0 || range.start() > range.end() || *range.end() > code.len() {
+            bail!(
+                "Lines `{}-{}` out of range, there are only {} lines",
+                range.start(),
+                range.end(),
+                code.len()
+            );
+        }
+        selected.extend_from_slice(&code[range.start() - 1..*range.end()]);
+    }
+    Ok(selected)
+}
+
+// Select the lines between `ANCHOR: region` and `ANCHOR_END: region` comments,
+// the lines of other anchor comments are removed.
+fn select_region<'s>(source: &'s str, region: &str) -> Result<Vec<&'s str>> {
+    let anchor = |line: &str, prefix: &str| {
+        line.split_once(prefix)
+            .map(|(_, name)| {
+                name.trim()
+                    .trim_end_matches("*/")
+                    .trim_end_matches("-->")
+                    .trim()
+                    == region
+            })
+            .unwrap_or_default()
+    };
+    let mut lines = source.lines();
+    if !lines.any(|line| anchor(line, "ANCHOR:")) {
+        bail!("Region `{}` not found", region);
+    }
+    Ok(lines
+        .take_while(|line| !anchor(line, "ANCHOR_END:"))
+        .filter(|line| !line.contains("ANCHOR:") && !line.contains("ANCHOR_END:"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use super::{resolve_file, select_lines, select_region};
+
+    const SOURCE: &str = r#"use std::io;
+
+// ANCHOR: main
+fn main() {
+    // ANCHOR: print
+    println!("Hello");
+    // ANCHOR_END: print
+}
+// ANCHOR_END: main
+"#;
+
+    #[test]
+    fn test_select_region() {
+        assert_eq!(
+            select_region(SOURCE, "main").unwrap(),
+            vec!["fn main() {", "    println!(\"Hello\");", "}"]
+        );
+        assert_eq!(
+            select_region(SOURCE, "print").unwrap(),
+            vec!["    println!(\"Hello\");"]
+        );
+        assert!(select_region(SOURCE, "none").is_err());
+    }
+
+    #[test]
+    fn test_select_lines() {
+        let code = SOURCE.lines().collect::<Vec<_>>();
+        assert_eq!(
+            select_lines(&code, "1, 4-5").unwrap(),
+            vec!["use std::io;", "fn main() {", "    // ANCHOR: print"]
+        );
+        assert_eq!(
+            select_lines(&code, "9").unwrap(),
+            vec!["// ANCHOR_END: main"]
+        );
+        assert!(select_lines(&code, "9-10").is_err());
+        assert!(select_lines(&code, "20").is_err());
+        assert!(select_lines(&code, "0-2").is_err());
+    }
+
+    #[test]
+    fn test_resolve_file() {
+        let temp = env::temp_dir().join("__zine_include_file");
+        let root = temp.join("root");
+        fs::create_dir_all(root.join("issue-1")).unwrap();
+        fs::write(root.join("main.rs"), SOURCE).unwrap();
+        fs::write(temp.join("secret.txt"), "secret").unwrap();
+
+        let path = Some(Path::new("issue-1/first.md"));
+        let file = fs::canonicalize(root.join("main.rs")).unwrap();
+        assert_eq!(resolve_file(&root, path, "../main.rs").unwrap(), file);
+        assert_eq!(resolve_file(&root, None, "/main.rs").unwrap(), file);
+        assert!(resolve_file(&root, None, "main.rs").is_err());
+        assert!(resolve_file(&root, path, "missing.rs").is_err());
+        assert!(resolve_file(&root, path, "../../secret.txt").is_err());
+        assert!(resolve_file(&root, None, "/../secret.txt").is_err());
+        assert!(resolve_file(&root, path, "/etc/hostname").is_err());
+    }
+}
@@ -1,16 +1,31 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
 use anyhow::{bail, Result};
 
 mod author;
 mod callout;
+mod code;
+mod diagram;
+mod embed;
+mod include;
+mod plugin;
+mod template;
 mod url_preview;
 
-use crate::{data, engine::Vistor, helpers, html};
+use crate::{
+    data,
+    engine::{self, Vistor},
+    plugin::find_code_block_plugin,
+};
 pub use author::AuthorCode;
-use url_preview::{UrlPreviewBlock, UrlPreviewError};
+pub use code::CodeSnippet;
+pub use url_preview::{collect_preview_urls, fetch_url_previews, prefetch_url_previews};
+use url_preview::{UrlPreviewBlock, UrlPreviewError, UrlPreviewLink};
 
-use self::callout::CalloutBlock;
+use self::{
+    callout::CalloutBlock, diagram::DiagramBlock, embed::EmbedBlock, include::IncludeBlock,
+    plugin::PluginBlock, template::TemplateBlock,
+};
 
 pub trait CodeBlock {
     fn render(&self) -> Result<String>;
@@ -18,13 +33,23 @@ pub trait CodeBlock {
 
 const CALLOUT: &str = "callout";
 const URL_PREVIEW: &str = "urlpreview";
+const INCLUDE: &str = "include";
+const MATH: &str = "math";
+const DIAGRAM: &str = "diagram";
+const EMBED: &str = "embed";
 
-const ALL_CODE_BLOCKS: &[&str] = &[CALLOUT, URL_PREVIEW];
+const ALL_CODE_BLOCKS: &[&str] = &[CALLOUT, URL_PREVIEW, INCLUDE, MATH, DIAGRAM, EMBED];
+
+/// Check whether `name` is a builtin code block, which can't be overridden
+/// by the user-defined code block templates.
+pub fn is_builtin_code_block(name: &str) -> bool {
+    ALL_CODE_BLOCKS.contains(&name)
+}
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Fenced<'a> {
     pub name: &'a str,
-    pub options: HashMap<String, &'a str>,
+    pub options: HashMap<String, Cow<'a, str>>,
 }
 
 impl<'a> Fenced<'a> {
@@ -33,44 +58,34 @@ impl<'a> Fenced<'a> {
         Self::default()
     }
 
+    /// Check whether the fenced is a builtin code block, a code block handled by plugin
+    /// or a user-defined code block in `templates/blocks` directory.
     pub fn is_custom_code_block(&self) -> bool {
-        ALL_CODE_BLOCKS.contains(&self.name)
+        is_builtin_code_block(self.name)
+            || find_code_block_plugin(self.name).is_some()
+            || engine::has_code_block_template(self.name)
     }
 
     /// Render code block. Return rendered HTML string if success,
     /// otherwise return URL preview error HTML string to remind user we have error.
     ///
-    /// If the fenced is unsupported, we simply return `None`.
-    pub async fn render_code_block(self, block: &'a str, visitor: Vistor<'a>) -> Option<String> {
+    /// The URL previews should have been prefetched by [`prefetch_url_previews`],
+    /// otherwise a plain link card is rendered.
+    ///
+    /// If the fenced is unsupported, or the include, diagram, embed, plugin or user-defined code block
+    /// failed to render, we simply return `None`.
+    pub fn render_code_block(self, block: &'a str, visitor: Vistor<'a>) -> Option<String> {
         match self.name {
             URL_PREVIEW => {
                 let url = block.trim();
-
-                {
-                    // parking_lot Mutex guard isn't async-aware,
-                    // we should keep this guard drop in this scope.
-                    let data = data::read();
-                    if let Some((title, description)) = data.url_previews().get(url) {
-                        return Some(UrlPreviewBlock(url, title, description).render().unwrap());
-                    }
-                }
-
-                println!("Preview new url: {}", url);
-                match helpers::fetch_url(url).await {
-                    Ok(html) => {
-                        let meta = html::parse_html_meta(html);
-                        let html = UrlPreviewBlock(url, &meta.title, &meta.description)
-                            .render()
-                            .unwrap();
-                        data::write().insert_url_preview(
-                            url,
-                            (meta.title.into_owned(), meta.description.into_owned()),
-                        );
-                        Some(html)
-                    }
-                    // Return a preview error block.
-                    Err(err) => Some(UrlPreviewError(url, &err.to_string()).render().unwrap()),
-                }
+                let data = data::read();
+                let html = match (data.url_previews().get(url), data.url_preview_error(url)) {
+                    (Some(info), _) => UrlPreviewBlock(url, info).render(),
+                    (None, Some(error)) => UrlPreviewError(url, error).render(),
+                    // Not fetched, such as in offline mode.
+                    (None, None) => UrlPreviewLink(url).render(),
+                };
+                Some(html.unwrap())
             }
             CALLOUT => {
                 let html = CalloutBlock::new(self.options, block, visitor)
@@ -78,7 +93,43 @@ impl<'a> Fenced<'a> {
                     .unwrap();
                 Some(html)
             }
-            _ => None,
+            MATH => Some(visitor.render_math(block, true)),
+            INCLUDE => match IncludeBlock::new(self.options, visitor).render() {
+                Ok(html) => Some(html),
+                Err(err) => {
+                    println!("Warn: failed to include code: {:#}", err);
+                    None
+                }
+            },
+            DIAGRAM => match DiagramBlock::new(block).render() {
+                Ok(html) => Some(html),
+                Err(err) => {
+                    println!("Warn: failed to render diagram: {:#}", err);
+                    None
+                }
+            },
+            EMBED => match EmbedBlock::new(self.options, block, visitor).render() {
+                Ok(html) => Some(html),
+                Err(err) => {
+                    println!("Warn: failed to embed: {:#}", err);
+                    None
+                }
+            },
+            name => {
+                let result = match find_code_block_plugin(name) {
+                    Some(plugin) => {
+                        PluginBlock::new(plugin, name, self.options, block, visitor).render()
+                    }
+                    None => TemplateBlock::new(name, self.options, block, visitor).render(),
+                };
+                match result {
+                    Ok(html) => Some(html),
+                    Err(err) => {
+                        println!("Warn: failed to render code block `{}`: {:#}", name, err);
+                        None
+                    }
+                }
+            }
         }
     }
 
@@ -88,19 +139,31 @@ impl<'a> Fenced<'a> {
             return Ok(Self::empty());
         }
 
-        let mut raw = input.split(',');
+        let mut raw = split_fenced(input).into_iter();
         match raw.next() {
             Some(name) if !name.is_empty() => {
                 let options = raw
-                    .into_iter()
                     .filter_map(|pair| {
-                        let mut v = pair.split(':').take(2);
-                        match (v.next(), v.next()) {
-                            (Some(key), Some(value)) => {
+                        match pair.split_once(':') {
+                            Some((key, value)) => {
+                                let value = value.trim();
+                                // Strip the quotes of quoted value, such as `title: "Hello, Zine"`,
+                                // and unescape the escaped quotes, such as `title: "Say \"Hi\""`.
+                                let value = match value
+                                    .strip_prefix('"')
+                                    .and_then(|value| value.strip_suffix('"'))
+                                {
+                                    Some(value) => unescape_quoted(value),
+                                    None => Cow::Borrowed(value),
+                                };
                                 // Replace key's dash to underscore.
-                                Some((key.trim().replace('-', "_"), value.trim()))
+                                Some((key.trim().replace('-', "_"), value))
+                            }
+                            // The flag option without value, such as `linenos`.
+                            None if is_flag_option(pair.trim()) => {
+                                Some((pair.trim().replace('-', "_"), Cow::Borrowed("true")))
                             }
-                            _ => {
+                            None => {
                                 println!("Invalid fenced options: {}", pair);
                                 None
                             }
@@ -116,6 +179,68 @@ impl<'a> Fenced<'a> {
     }
 }
 
+// Check whether the option is a flag option, such as `linenos`.
+// The flag must start with a letter, so the unquoted value
+// like `hl_lines: 1, 3-5` wouldn't produce a bogus `3_5` flag.
+fn is_flag_option(option: &str) -> bool {
+    option.starts_with(|c: char| c.is_ascii_alphabetic())
+        && option
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Quote the fenced option value, escape the `"` and `\` in it,
+/// such as `Say "Hi"` to `"Say \"Hi\""`.
+pub fn quote_option(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Unescape the `\"` and `\\` in the quoted option value.
+fn unescape_quoted(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ ('"' | '\\')) => unescaped.push(next),
+                Some(next) => {
+                    unescaped.push(c);
+                    unescaped.push(next);
+                }
+                None => unescaped.push(c),
+            },
+            _ => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+// Split the fenced by commas, except the commas in double quotes.
+fn split_fenced(input: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -146,5 +271,53 @@ mod tests {
 
         let fenced = Fenced::parse("rust").unwrap();
         assert!(!fenced.is_custom_code_block());
+
+        let fenced =
+            Fenced::parse(r#"callout, title: "Hello, Zine", url: https://zine.dev"#).unwrap();
+        let options = fenced.options;
+        assert_eq!(options["title"], "Hello, Zine");
+        assert_eq!(options["url"], "https://zine.dev");
+
+        let fenced = Fenced::parse("rust, linenos, hl-lines: 3-5, title: main.rs").unwrap();
+        assert_eq!(fenced.name, "rust");
+        let options = fenced.options;
+        assert_eq!(options["linenos"], "true");
+        assert_eq!(options["hl_lines"], "3-5");
+        assert_eq!(options["title"], "main.rs");
+
+        let fenced =
+            Fenced::parse(r#"callout, title: "Say \"Hi, Zine\" \\o/", type: note"#).unwrap();
+        let options = fenced.options;
+        assert_eq!(options["title"], r#"Say "Hi, Zine" \o/"#);
+        assert_eq!(options["type"], "note");
+        assert_eq!(
+            Fenced::parse(&format!(
+                "callout, title: {}",
+                quote_option(r#"a\", b: "c"#)
+            ))
+            .unwrap()
+            .options["title"],
+            r#"a\", b: "c"#
+        );
+
+        // The unquoted value with commas doesn't produce a bogus flag.
+        let fenced = Fenced::parse("rust, hl_lines: 1, 3-5, linenos").unwrap();
+        let options = fenced.options;
+        assert_eq!(options.len(), 2);
+        assert_eq!(options["hl_lines"], "1");
+        assert_eq!(options["linenos"], "true");
+        assert_eq!(options.get("3_5"), None);
+    }
+
+    #[test]
+    fn test_is_flag_option() {
+        assert!(is_flag_option("linenos"));
+        assert!(is_flag_option("no-copy"));
+        assert!(is_flag_option("line_numbers2"));
+        assert!(!is_flag_option(""));
+        assert!(!is_flag_option("3-5"));
+        assert!(!is_flag_option("_hidden"));
+        assert!(!is_flag_option("-x"));
+        assert!(!is_flag_option("bg color"));
     }
 }
@@ -0,0 +1,53 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{
+    engine,
+    html::sanitize_html,
+    plugin::{Plugin, PluginInput},
+};
+
+use super::CodeBlock;
+
+/// The code block handled by an external plugin, which returns the HTML.
+pub struct PluginBlock<'a> {
+    plugin: Plugin,
+    name: &'a str,
+    options: HashMap<String, Cow<'a, str>>,
+    body: &'a str,
+    visitor: engine::Vistor<'a>,
+}
+
+impl<'a> PluginBlock<'a> {
+    pub fn new(
+        plugin: Plugin,
+        name: &'a str,
+        options: HashMap<String, Cow<'a, str>>,
+        block: &'a str,
+        visitor: engine::Vistor<'a>,
+    ) -> Self {
+        PluginBlock {
+            plugin,
+            name,
+            options,
+            body: block,
+            visitor,
+        }
+    }
+}
+
+impl<'a> CodeBlock for PluginBlock<'a> {
+    fn render(&self) -> anyhow::Result<String> {
+        let html = self.plugin.run(&PluginInput::CodeBlock {
+            name: self.name,
+            options: &self.options,
+            body: self.body,
+            path: self.visitor.path(),
+            meta: self.visitor.meta(),
+        })?;
+        if self.visitor.sanitize() {
+            sanitize_html(&html)
+        } else {
+            Ok(html)
+        }
+    }
+}
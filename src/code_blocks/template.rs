@@ -0,0 +1,57 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use tera::Context;
+
+use crate::{engine, html::sanitize_html};
+
+use super::CodeBlock;
+
+/// The user-defined code block, which renders the `templates/blocks/{name}.jinja`
+/// template of the project.
+///
+/// The template context has:
+/// - `name`: the code block name.
+/// - `options`: the fenced options, such as `author` of `quote, author: Alice`.
+/// - `raw`: the raw block body.
+/// - `content`: the block body rendered as markdown.
+pub struct TemplateBlock<'a> {
+    name: &'a str,
+    options: HashMap<String, Cow<'a, str>>,
+    raw: &'a str,
+    visitor: engine::Vistor<'a>,
+}
+
+impl<'a> TemplateBlock<'a> {
+    pub fn new(
+        name: &'a str,
+        options: HashMap<String, Cow<'a, str>>,
+        block: &'a str,
+        visitor: engine::Vistor<'a>,
+    ) -> Self {
+        TemplateBlock {
+            name,
+            options,
+            raw: block,
+            visitor,
+        }
+    }
+}
+
+impl<'a> CodeBlock for TemplateBlock<'a> {
+    fn render(&self) -> anyhow::Result<String> {
+        let mut context = Context::new();
+        context.insert("name", self.name);
+        context.insert("options", &self.options);
+        context.insert("raw", self.raw);
+        context.insert("content", &self.visitor.render_markdown(self.raw));
+        let html =
+            engine::get_tera().render(&engine::code_block_template_name(self.name), &context)?;
+        // The options and raw body are not escaped by the template,
+        // sanitize the whole output for the untrusted content.
+        if self.visitor.sanitize() {
+            sanitize_html(&html)
+        } else {
+            Ok(html)
+        }
+    }
+}
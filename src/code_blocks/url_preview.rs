@@ -1,16 +1,92 @@
-use std::fmt::Write;
+use std::{borrow::Cow, collections::BTreeSet, fmt::Write, sync::Arc};
 
-use super::CodeBlock;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use time::OffsetDateTime;
+use tokio::sync::Semaphore;
 
-pub(super) struct UrlPreviewBlock<'a>(pub &'a str, pub &'a str, pub &'a str);
+use crate::{
+    data::{self, UrlPreviewInfo},
+    helpers,
+    html::{self, escape_html, escape_url},
+    markdown::convert_github_alerts,
+};
+
+use super::{embed, CodeBlock, Fenced, CALLOUT, EMBED, URL_PREVIEW};
+
+/// The URL preview card, the link should be the last child,
+/// which is used to open the URL when clicking the card.
+///
+/// All the text and URLs are escaped, since the preview info is fetched from remote.
+pub(super) struct UrlPreviewBlock<'a>(pub &'a str, pub &'a UrlPreviewInfo);
 
 impl<'a> CodeBlock for UrlPreviewBlock<'a> {
+    fn render(&self) -> anyhow::Result<String> {
+        let UrlPreviewBlock(url, info) = self;
+        // Fallback to the original URL if the canonical URL is unsafe.
+        let href = info
+            .canonical_url
+            .as_deref()
+            .and_then(escape_url)
+            .or_else(|| escape_url(url))
+            .unwrap_or(Cow::Borrowed("#"));
+        let favicon = info.favicon.as_deref().and_then(escape_url);
+        let image = info.image.as_deref().and_then(escape_url);
+
+        let mut html = String::new();
+        writeln!(&mut html, r#"<div class="url-preview">"#)?;
+        writeln!(&mut html, r#" <div class="url-preview-content">"#)?;
+        if info.site_name.is_some() || favicon.is_some() {
+            write!(&mut html, r#"  <div class="url-preview-site">"#)?;
+            if let Some(favicon) = favicon {
+                write!(
+                    &mut html,
+                    r#"<img src="{}" alt="" loading="lazy">"#,
+                    favicon
+                )?;
+            }
+            writeln!(
+                &mut html,
+                r#"<span>{}</span></div>"#,
+                escape_html(info.site_name.as_deref().unwrap_or_default())
+            )?;
+        }
+        writeln!(
+            &mut html,
+            r#"  <div class="url-preview-title">{}</div>"#,
+            escape_html(&info.title)
+        )?;
+        writeln!(
+            &mut html,
+            r#"  <div class="url-preview-description">{}</div>"#,
+            escape_html(&info.description)
+        )?;
+        writeln!(&mut html, r#" </div>"#)?;
+        if let Some(image) = image {
+            writeln!(
+                &mut html,
+                r#" <img class="url-preview-image" src="{}" alt="" loading="lazy">"#,
+                image
+            )?;
+        }
+        writeln!(&mut html, r#" <a href="{}">{}</a>"#, href, escape_html(url))?;
+        writeln!(&mut html, r#"</div>"#)?;
+        Ok(html)
+    }
+}
+
+/// The plain link card of an URL without preview, such as in offline mode.
+pub(super) struct UrlPreviewLink<'a>(pub &'a str);
+
+impl<'a> CodeBlock for UrlPreviewLink<'a> {
     fn render(&self) -> anyhow::Result<String> {
         let mut html = String::new();
         writeln!(&mut html, r#"<div class="url-preview">"#)?;
-        writeln!(&mut html, r#" <div>{}</div>"#, self.1)?;
-        writeln!(&mut html, r#" <div>{}</div>"#, self.2)?;
-        writeln!(&mut html, r#" <a href="{url}">{url}</a>"#, url = self.0)?;
+        writeln!(
+            &mut html,
+            r#" <a href="{}">{}</a>"#,
+            escape_url(self.0).unwrap_or(Cow::Borrowed("#")),
+            escape_html(self.0)
+        )?;
         writeln!(&mut html, r#"</div>"#)?;
         Ok(html)
     }
@@ -22,10 +98,228 @@ impl<'a> CodeBlock for UrlPreviewError<'a> {
     fn render(&self) -> anyhow::Result<String> {
         let mut html = String::new();
         writeln!(&mut html, r#"<div class="url-preview">"#)?;
-        writeln!(&mut html, r#" <div></div>"#)?;
-        writeln!(&mut html, r#" <div>Url preview error: {}</div>"#, self.1)?;
-        writeln!(&mut html, r#" <a href="{url}">{url}</a>"#, url = self.0)?;
+        writeln!(&mut html, r#" <div class="url-preview-content">"#)?;
+        writeln!(
+            &mut html,
+            r#"  <div class="url-preview-description">Url preview error: {}</div>"#,
+            escape_html(self.1)
+        )?;
+        writeln!(&mut html, r#" </div>"#)?;
+        writeln!(
+            &mut html,
+            r#" <a href="{}">{}</a>"#,
+            escape_url(self.0).unwrap_or(Cow::Borrowed("#")),
+            escape_html(self.0)
+        )?;
         writeln!(&mut html, r#"</div>"#)?;
         Ok(html)
     }
 }
+
+/// Collect the URLs of all `urlpreview` code blocks in `markdown` into `urls`,
+/// including the blocks nested in callouts, and the videos and posts of `embed` code blocks.
+pub fn collect_preview_urls(markdown: &str, urls: &mut BTreeSet<String>) {
+    // The GitHub-style alerts are callouts too.
+    let markdown = convert_github_alerts(markdown);
+    let mut fenced = None;
+    // The text of current code block, which is split into multiple
+    // text events if the code block is inside a blockquote or list.
+    let mut block = String::new();
+    for event in Parser::new_ext(&markdown, Options::all()) {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(name))) => {
+                fenced = Fenced::parse(&name)
+                    .ok()
+                    .map(|fenced| fenced.name.to_owned());
+                block.clear();
+            }
+            Event::End(Tag::CodeBlock(_)) => match fenced.take().as_deref() {
+                Some(URL_PREVIEW) => {
+                    urls.insert(block.trim().to_owned());
+                }
+                Some(EMBED) if embed::is_provider_url(block.trim()) => {
+                    urls.insert(block.trim().to_owned());
+                }
+                // The callout content is markdown too.
+                Some(CALLOUT) => collect_preview_urls(&block, urls),
+                _ => {}
+            },
+            Event::Text(text) if fenced.is_some() => block.push_str(&text),
+            _ => {}
+        }
+    }
+}
+
+/// Fetch the previews of uncached `urls` concurrently, at most `concurrency`
+/// requests in flight. The previews are cached in [`data`], so the rendering
+/// never touches the network.
+pub async fn prefetch_url_previews(urls: BTreeSet<String>, concurrency: usize) {
+    let urls = {
+        let data = data::read();
+        urls.into_iter()
+            .filter(|url| !data.url_previews().contains_key(url))
+            .collect::<Vec<_>>()
+    };
+    if !urls.is_empty() {
+        println!("Preview {} new urls...", urls.len());
+        fetch_url_previews(urls, concurrency).await;
+    }
+}
+
+/// Fetch the previews of `urls` concurrently regardless of the cache,
+/// at most `concurrency` requests in flight.
+///
+/// Return the number of previews fetched successfully. The failed URLs
+/// are recorded in [`data`], their cached previews are kept.
+pub async fn fetch_url_previews(urls: Vec<String>, concurrency: usize) -> usize {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let tasks = urls
+        .into_iter()
+        .map(|url| {
+            let semaphore = Arc::clone(&semaphore);
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.expect("Semaphore closed.");
+                match helpers::fetch_url(&url).await {
+                    Ok(page) => {
+                        let mut info = html::parse_html_meta(&page.url, page.html.as_bytes());
+                        info.fetched_at = Some(OffsetDateTime::now_utc());
+                        data::write().insert_url_preview(&url, info);
+                        true
+                    }
+                    Err(err) => {
+                        println!("Warn: failed to preview url `{}`: {}", url, err);
+                        data::write().insert_url_preview_error(&url, err.to_string());
+                        false
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut fetched = 0;
+    for task in tasks {
+        if task.await.expect("Preview url task failed.") {
+            fetched += 1;
+        }
+    }
+    fetched
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{code_blocks::CodeBlock, data::UrlPreviewInfo};
+
+    use super::{collect_preview_urls, UrlPreviewBlock, UrlPreviewError, UrlPreviewLink};
+
+    #[test]
+    fn test_collect_preview_urls() {
+        let markdown = r#"
+```urlpreview
+https://github.com/zineland/zine
+```
+
+```rust
+https://ignored.com
+```
+
+~~~callout, bg_color: #123456
+Nested preview:
+
+```urlpreview
+  https://2d2d.io
+```
+~~~
+
+```urlpreview
+https://github.com/zineland/zine
+```
+
+```embed
+https://youtu.be/dQw4w9WgXcQ
+```
+
+```embed
+./talk.mp3
+```
+"#;
+        let mut urls = BTreeSet::new();
+        collect_preview_urls(markdown, &mut urls);
+        assert_eq!(
+            vec![
+                "https://2d2d.io",
+                "https://github.com/zineland/zine",
+                "https://youtu.be/dQw4w9WgXcQ"
+            ],
+            urls.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_collect_nested_preview_urls() {
+        let markdown = r#"
+> ~~~callout, type: note
+> Quoted callout:
+>
+> ```urlpreview
+> https://2d2d.io
+> ```
+> ~~~
+
+- Listed embed:
+
+  ```embed
+  https://youtu.be/dQw4w9WgXcQ
+  ```
+
+> [!TIP]
+> ```urlpreview
+> https://github.com/zineland/zine
+> ```
+"#;
+        let mut urls = BTreeSet::new();
+        collect_preview_urls(markdown, &mut urls);
+        assert_eq!(
+            vec![
+                "https://2d2d.io",
+                "https://github.com/zineland/zine",
+                "https://youtu.be/dQw4w9WgXcQ"
+            ],
+            urls.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_render_url_preview_hostile() {
+        let info = UrlPreviewInfo {
+            title: String::from("<script>alert('title')</script>"),
+            description: String::from("<img src=x onerror=alert(1)>"),
+            image: Some(String::from("javascript:alert('image')")),
+            site_name: Some(String::from("</div><script>")),
+            favicon: Some(String::from("https://a.com/icon.png\" onload=\"alert(1)")),
+            canonical_url: Some(String::from("javascript:alert('canonical')")),
+            fetched_at: None,
+        };
+        let html = UrlPreviewBlock("https://a.com/?a=1&b=<2>", &info)
+            .render()
+            .unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("<img src=x"));
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("\" onload"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;title&#39;)&lt;/script&gt;"));
+        assert!(html.contains(r#"src="https://a.com/icon.png&quot; onload=&quot;alert(1)""#));
+        // Fallback to the original URL if the canonical URL is unsafe.
+        assert!(html.contains(r#"<a href="https://a.com/?a=1&amp;b=&lt;2&gt;">"#));
+
+        let html = UrlPreviewError("javascript:alert(1)", "<script>")
+            .render()
+            .unwrap();
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(r##"<a href="#">javascript:alert(1)</a>"##));
+
+        let html = UrlPreviewLink("\"><script>").render().unwrap();
+        assert!(!html.contains("<script>"));
+    }
+}
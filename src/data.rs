@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self, File},
     io::Write,
     path::Path,
@@ -8,9 +8,10 @@ use std::{
 use anyhow::Result;
 use once_cell::sync::OnceCell;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use time::OffsetDateTime;
 
-use crate::entity::Author;
+use crate::{check::LinkCheck, entity::Author, link::LinkResolver};
 
 static ZINE_DATA: OnceCell<RwLock<ZineData>> = OnceCell::new();
 
@@ -26,47 +27,181 @@ pub fn write() -> RwLockWriteGuard<'static, ZineData> {
     ZINE_DATA.get().unwrap().write()
 }
 
+/// The file name of the data store.
+pub const DATA_FILE: &str = "zine-data.json";
+
 /// Export all data into the `zine-data.json` file.
 /// If the data is empty, we never create the `zine-data.json` file.
+///
+/// The file is written atomically: write into a temporary file then rename it,
+/// so an interrupted export never leaves a broken file. The file is untouched
+/// if nothing changed.
 pub fn export<P: AsRef<Path>>(path: P) -> Result<()> {
     let data = read();
-    if !data.url_previews.is_empty() {
-        let mut file = File::create(path.as_ref().join("zine-data.json"))?;
-        file.write_all(data.export_to_json()?.as_bytes())?;
+    let path = path.as_ref().join(DATA_FILE);
+    // Still overwrite the existing file if all data has been removed.
+    if data.url_previews.is_empty()
+        && data.link_checks.is_empty()
+        && data.plugin_outputs.is_empty()
+        && !path.exists()
+    {
+        return Ok(());
+    }
+
+    let json = data.export_to_json()?;
+    if fs::read_to_string(&path).ok().as_deref() == Some(json.as_str()) {
+        return Ok(());
     }
+    let temp_path = path.with_extension("json.tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)?;
     Ok(())
 }
 
+/// The preview info of an URL, parsed from the HTML meta of the URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UrlPreviewInfo {
+    pub title: String,
+    pub description: String,
+    /// The absolute url of OpenGraph image.
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub site_name: Option<String>,
+    /// The absolute url of site favicon.
+    #[serde(default)]
+    pub favicon: Option<String>,
+    #[serde(default)]
+    pub canonical_url: Option<String>,
+    /// The time of fetching this preview, `None` if it is edited manually or legacy.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub fetched_at: Option<OffsetDateTime>,
+}
+
+// Deserialize url previews, compatible with the legacy `(title, description)` tuple format.
+fn deserialize_url_previews<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, UrlPreviewInfo>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum UrlPreviewEntry {
+        Legacy(String, String),
+        Info(UrlPreviewInfo),
+    }
+
+    let previews = BTreeMap::<String, UrlPreviewEntry>::deserialize(deserializer)?;
+    Ok(previews
+        .into_iter()
+        .map(|(url, entry)| {
+            let info = match entry {
+                UrlPreviewEntry::Legacy(title, description) => UrlPreviewInfo {
+                    title,
+                    description,
+                    ..Default::default()
+                },
+                UrlPreviewEntry::Info(info) => info,
+            };
+            (url, info)
+        })
+        .collect())
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ZineData {
     #[serde(skip)]
     authors: Vec<Author>,
-    url_previews: BTreeMap<String, (String, String)>,
+    #[serde(skip)]
+    link_resolver: LinkResolver,
+    #[serde(default, deserialize_with = "deserialize_url_previews")]
+    url_previews: BTreeMap<String, UrlPreviewInfo>,
+    // The errors of fetching URL previews in this run, which are never cached.
+    #[serde(skip)]
+    url_preview_errors: HashMap<String, String>,
+    // The cached results of external link checking.
+    #[serde(default)]
+    link_checks: BTreeMap<String, LinkCheck>,
+    // The cached outputs of plugins, keyed by the hash of plugin and input.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    plugin_outputs: BTreeMap<String, String>,
+    // The keys of plugin outputs used since last pruning.
+    #[serde(skip)]
+    used_plugin_outputs: HashSet<String>,
 }
 
 impl ZineData {
     pub fn new(source: impl AsRef<Path>) -> Result<Self> {
-        let path = source.as_ref().join("zine-data.json");
+        let path = source.as_ref().join(DATA_FILE);
         if path.exists() {
             let json = fs::read_to_string(path)?;
             Ok(serde_json::from_str(&json)?)
         } else {
             Ok(ZineData {
                 url_previews: BTreeMap::default(),
+                link_checks: BTreeMap::default(),
+                plugin_outputs: BTreeMap::default(),
+                used_plugin_outputs: HashSet::default(),
+                url_preview_errors: HashMap::default(),
                 authors: Vec::default(),
+                link_resolver: LinkResolver::default(),
             })
         }
     }
 
-    pub fn url_previews(&self) -> &BTreeMap<String, (String, String)> {
+    pub fn url_previews(&self) -> &BTreeMap<String, UrlPreviewInfo> {
         &self.url_previews
     }
 
-    pub fn insert_url_preview(&mut self, url: &str, preview: (String, String)) {
+    pub fn insert_url_preview(&mut self, url: &str, preview: UrlPreviewInfo) {
+        self.url_preview_errors.remove(url);
         self.url_previews.insert(url.to_owned(), preview);
     }
 
+    pub fn remove_url_preview(&mut self, url: &str) -> Option<UrlPreviewInfo> {
+        self.url_previews.remove(url)
+    }
+
+    pub fn url_preview_error(&self, url: &str) -> Option<&str> {
+        self.url_preview_errors.get(url).map(String::as_str)
+    }
+
+    pub fn insert_url_preview_error(&mut self, url: &str, error: String) {
+        self.url_preview_errors.insert(url.to_owned(), error);
+    }
+
+    pub fn link_checks(&self) -> &BTreeMap<String, LinkCheck> {
+        &self.link_checks
+    }
+
+    pub fn insert_link_check(&mut self, url: &str, link_check: LinkCheck) {
+        self.link_checks.insert(url.to_owned(), link_check);
+    }
+
+    /// Get the cached plugin output of `key`, and mark it as used.
+    pub fn use_plugin_output(&mut self, key: &str) -> Option<String> {
+        let output = self.plugin_outputs.get(key)?.clone();
+        self.used_plugin_outputs.insert(key.to_owned());
+        Some(output)
+    }
+
+    pub fn insert_plugin_output(&mut self, key: String, output: String) {
+        self.used_plugin_outputs.insert(key.clone());
+        self.plugin_outputs.insert(key, output);
+    }
+
+    /// Remove the plugin outputs which haven't been used since last pruning,
+    /// such as the outputs of edited code blocks. It's called after every build.
+    pub fn prune_plugin_outputs(&mut self) {
+        let used = std::mem::take(&mut self.used_plugin_outputs);
+        self.plugin_outputs.retain(|key, _| used.contains(key));
+    }
+
     pub fn set_authors(&mut self, authors: Vec<Author>) {
         self.authors = authors;
     }
@@ -77,7 +212,100 @@ impl ZineData {
             .find(|author| author.id.eq_ignore_ascii_case(author_id))
     }
 
+    pub fn set_link_resolver(&mut self, link_resolver: LinkResolver) {
+        self.link_resolver = link_resolver;
+    }
+
+    pub fn link_resolver(&self) -> &LinkResolver {
+        &self.link_resolver
+    }
+
+    // All maps are ordered by key, so the JSON is deterministic and diffs cleanly.
     fn export_to_json(&self) -> Result<String> {
-        Ok(serde_json::to_string_pretty(self)?)
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        Ok(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UrlPreviewInfo, ZineData};
+
+    #[test]
+    fn test_migrate_legacy_url_previews() {
+        let json = r#"{
+            "urlPreviews": {
+                "https://a.com": ["Title A", "Description A"],
+                "https://b.com": {
+                    "title": "Title B",
+                    "description": "Description B",
+                    "image": "https://b.com/cover.png",
+                    "siteName": "B",
+                    "favicon": "https://b.com/favicon.ico",
+                    "canonicalUrl": "https://b.com/",
+                    "fetchedAt": "2022-06-01T00:00:00Z"
+                }
+            }
+        }"#;
+        let data = serde_json::from_str::<ZineData>(json).unwrap();
+        let previews = data.url_previews();
+        assert_eq!(
+            UrlPreviewInfo {
+                title: String::from("Title A"),
+                description: String::from("Description A"),
+                ..Default::default()
+            },
+            previews["https://a.com"]
+        );
+
+        let b = &previews["https://b.com"];
+        assert_eq!("Title B", b.title);
+        assert_eq!(Some("https://b.com/cover.png"), b.image.as_deref());
+        assert_eq!(Some("B"), b.site_name.as_deref());
+        assert_eq!(Some("https://b.com/favicon.ico"), b.favicon.as_deref());
+        assert_eq!(Some("https://b.com/"), b.canonical_url.as_deref());
+        assert_eq!(Some(1654041600), b.fetched_at.map(|t| t.unix_timestamp()));
+
+        // Always export in the new format.
+        let json = data.export_to_json().unwrap();
+        assert!(json.contains(r#""title": "Title A""#));
+    }
+
+    #[test]
+    fn test_prune_plugin_outputs() {
+        let mut data = serde_json::from_str::<ZineData>(
+            r#"{"pluginOutputs": {"a": "output a", "b": "output b"}}"#,
+        )
+        .unwrap();
+        assert_eq!(data.use_plugin_output("a").as_deref(), Some("output a"));
+        assert_eq!(data.use_plugin_output("c"), None);
+        data.insert_plugin_output(String::from("c"), String::from("output c"));
+        data.prune_plugin_outputs();
+        assert_eq!(
+            data.plugin_outputs.keys().collect::<Vec<_>>(),
+            vec!["a", "c"]
+        );
+
+        // Nothing is used since last pruning.
+        data.prune_plugin_outputs();
+        assert!(data.plugin_outputs.is_empty());
+        assert!(!data.export_to_json().unwrap().contains("pluginOutputs"));
+    }
+
+    #[test]
+    fn test_export_to_json_deterministic() {
+        let mut data = serde_json::from_str::<ZineData>("{}").unwrap();
+        for url in ["https://c.com", "https://a.com", "https://b.com"] {
+            data.insert_url_preview(url, UrlPreviewInfo::default());
+        }
+        let json = data.export_to_json().unwrap();
+        assert!(json.ends_with("}\n"));
+
+        let a = json.find("https://a.com").unwrap();
+        let b = json.find("https://b.com").unwrap();
+        let c = json.find("https://c.com").unwrap();
+        assert!(a < b && b < c);
+        assert_eq!(json, data.export_to_json().unwrap());
     }
 }
@@ -1,42 +1,52 @@
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
 
 use crate::{
-    code_blocks::{AuthorCode, CodeBlock, Fenced},
+    code_blocks::{prefetch_url_previews, AuthorCode, CodeBlock, CodeSnippet, Fenced},
     current_mode, data,
-    entity::{Entity, MarkdownConfig, Zine},
-    helpers::copy_dir,
-    html::rewrite_html_base_url,
+    entity::{Entity, HighlightStyle, MarkdownConfig, Zine},
+    helpers::{content_hash, copy_assets, copy_dir},
+    html::{escape_html, escape_url, rewrite_html_base_url, split_highlighted_lines},
+    image::{density_variants, image_size, init_pipeline, local_image_path, rewrite_images},
+    is_offline,
     locales::FluentLoader,
-    markdown::{markdown_to_html, MarkdownVisitor, Visiting},
+    markdown::{
+        convert_extensions, markdown_to_html, parse_math_code, Footnote, MarkdownVisitor, Visiting,
+    },
+    math::latex_to_mathml,
     Mode,
 };
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use hyper::Uri;
-use once_cell::sync::Lazy;
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use pulldown_cmark::*;
 use serde_json::Value;
 use syntect::{
-    dumps::from_binary, highlighting::ThemeSet, html::highlighted_html_for_string,
+    dumps::from_binary,
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{
+        css_for_theme_with_class_style, styled_line_to_highlighted_html, ClassStyle,
+        ClassedHTMLGenerator, IncludeBackground,
+    },
     parsing::SyntaxSet,
+    util::LinesWithEndings,
 };
 use tera::{Context, Function, Tera};
-use tokio::{runtime::Handle, task};
+use tokio::runtime::Handle;
 
-static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
-    let syntax_set: SyntaxSet =
-        from_binary(include_bytes!("../sublime/syntaxes/newlines.packdump"));
-    syntax_set
-});
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
-    let theme_set: ThemeSet = from_binary(include_bytes!("../sublime/themes/all.themedump"));
-    theme_set
-});
+// The built-in syntaxes and themes, merged with the project's custom ones.
+static SYNTAX_SET: OnceCell<SyntaxSet> = OnceCell::new();
+static THEME_SET: OnceCell<ThemeSet> = OnceCell::new();
+// The languages without syntax, which have been warned.
+static UNKNOWN_LANGUAGES: Lazy<parking_lot::Mutex<HashSet<String>>> = Lazy::new(Default::default);
+// The prefixed classes avoid conflicting with the classes of zine or user.
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
 static TERA: OnceCell<parking_lot::RwLock<Tera>> = OnceCell::new();
 
 fn init_tera(source: &Path, zine: &Zine) {
@@ -55,6 +65,14 @@ fn init_tera(source: &Path, zine: &Zine) {
                 "_anchor-link.jinja",
                 include_str!("../templates/_anchor-link.jinja"),
             ),
+            (
+                "_callout.jinja",
+                include_str!("../templates/_callout.jinja"),
+            ),
+            (
+                "_footnotes.jinja",
+                include_str!("../templates/_footnotes.jinja"),
+            ),
             ("_meta.jinja", include_str!("../templates/_meta.jinja")),
             ("base.jinja", include_str!("../templates/base.jinja")),
             ("index.jinja", include_str!("../templates/index.jinja")),
@@ -96,21 +114,63 @@ fn init_tera(source: &Path, zine: &Zine) {
         tera.add_raw_template("article_extend_template.jinja", article_extend_template)
             .expect("Cannot add article_extend_template");
     }
+    register_code_block_templates(&mut tera, &zine.theme.code_block_templates);
+    for page in &zine.pages {
+        if let Some(template) = page.meta.template.as_ref() {
+            tera.add_raw_template(&page.template_name(), template)
+                .expect("Cannot add page template");
+        }
+    }
 
     // Dynamically register functions that need dynamic configuration.
     tera.register_function(
         "markdown_to_html",
         MarkdownRender {
             markdown_config: zine.markdown_config.clone(),
+            source: source.to_owned(),
         },
     );
     tera.register_function("fluent", FluentLoader::new(source, locale));
 }
 
-fn get_tera() -> parking_lot::RwLockReadGuard<'static, Tera> {
+// Register the user-defined code block templates, and unregister the templates
+// removed since last build, such as during `zine serve`.
+fn register_code_block_templates(tera: &mut Tera, templates: &BTreeMap<String, String>) {
+    tera.templates.retain(|name, _| {
+        match name
+            .strip_prefix("blocks/")
+            .and_then(|name| name.strip_suffix(".jinja"))
+        {
+            Some(name) => templates.contains_key(name),
+            None => true,
+        }
+    });
+    for (name, template) in templates {
+        tera.add_raw_template(&code_block_template_name(name), template)
+            .expect("Cannot add code block template");
+    }
+}
+
+pub(crate) fn get_tera() -> parking_lot::RwLockReadGuard<'static, Tera> {
     TERA.get().expect("Tera haven't initialized").read()
 }
 
+/// The tera template name of the user-defined code block.
+pub(crate) fn code_block_template_name(name: &str) -> String {
+    format!("blocks/{}.jinja", name)
+}
+
+/// Check whether the user-defined code block template of `name` exists.
+pub(crate) fn has_code_block_template(name: &str) -> bool {
+    TERA.get()
+        .map(|tera| {
+            tera.read()
+                .get_template(&code_block_template_name(name))
+                .is_ok()
+        })
+        .unwrap_or_default()
+}
+
 #[derive(Debug)]
 pub struct ZineEngine {
     pub source: PathBuf,
@@ -120,6 +180,8 @@ pub struct ZineEngine {
 
 struct MarkdownRender {
     markdown_config: MarkdownConfig,
+    // The root directory of the project.
+    source: PathBuf,
 }
 
 pub fn render(template: &str, context: &Context, dest: impl AsRef<Path>) -> Result<()> {
@@ -132,6 +194,10 @@ pub fn render(template: &str, context: &Context, dest: impl AsRef<Path>) -> Resu
     }
 
     get_tera().render_to(template, context, &mut buf)?;
+    // Rewrite the local images into responsive images if the image pipeline is enabled.
+    if let Some(html) = rewrite_images(&buf)? {
+        buf = html;
+    }
 
     // Rewrite root path links with site url if and only if:
     // 1. in build run mode
@@ -160,6 +226,75 @@ pub fn render_str(raw_template: &str, context: &Context) -> Result<String> {
     Ok(r)
 }
 
+// Load the built-in syntaxes and themes, then merge the `syntaxes/*.sublime-syntax`
+// and `themes/*.tmTheme` of the project. They are loaded only once at startup.
+fn init_highlighting(source: &Path) -> Result<()> {
+    SYNTAX_SET.get_or_try_init(|| load_syntax_set(source))?;
+    THEME_SET.get_or_try_init(|| load_theme_set(source))?;
+    Ok(())
+}
+
+// Load the built-in syntaxes, merged with the `syntaxes/*.sublime-syntax` of the project.
+fn load_syntax_set(source: &Path) -> Result<SyntaxSet> {
+    let syntax_set: SyntaxSet =
+        from_binary(include_bytes!("../sublime/syntaxes/newlines.packdump"));
+    let syntax_dir = source.join("syntaxes");
+    if !syntax_dir.is_dir() {
+        return Ok(syntax_set);
+    }
+
+    let mut builder = syntax_set.into_builder();
+    builder
+        .add_from_folder(&syntax_dir, true)
+        .with_context(|| format!("Failed to load syntaxes from {}", syntax_dir.display()))?;
+    Ok(builder.build())
+}
+
+// Load the built-in themes, merged with the `themes/*.tmTheme` of the project.
+fn load_theme_set(source: &Path) -> Result<ThemeSet> {
+    let mut theme_set: ThemeSet = from_binary(include_bytes!("../sublime/themes/all.themedump"));
+    let theme_dir = source.join("themes");
+    if theme_dir.is_dir() {
+        theme_set
+            .add_from_folder(&theme_dir)
+            .with_context(|| format!("Failed to load themes from {}", theme_dir.display()))?;
+    }
+    Ok(theme_set)
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    SYNTAX_SET.get().expect("Syntax set haven't initialized")
+}
+
+fn find_highlight_theme(name: &str) -> Result<&'static Theme> {
+    THEME_SET
+        .get()
+        .expect("Theme set haven't initialized")
+        .themes
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("No highlight theme: `{}` founded", name))
+}
+
+// Render the stylesheet of highlight classes, the dark theme is
+// applied by `prefers-color-scheme` media query.
+fn render_highlight_css(config: &MarkdownConfig, dest: impl AsRef<Path>) -> Result<()> {
+    let mut css = css_for_theme_with_class_style(
+        find_highlight_theme(&config.highlight_theme)?,
+        HIGHLIGHT_CLASS_STYLE,
+    );
+    if let Some(dark_theme) = config.highlight_dark_theme.as_ref() {
+        let dark_css = css_for_theme_with_class_style(
+            find_highlight_theme(dark_theme)?,
+            HIGHLIGHT_CLASS_STYLE,
+        );
+        css.push_str("\n@media (prefers-color-scheme: dark) {\n");
+        css.push_str(&dark_css);
+        css.push_str("}\n");
+    }
+    fs::write(dest.as_ref().join("static/zine-highlight.css"), css)?;
+    Ok(())
+}
+
 // Render Atom feed
 fn render_atom_feed(context: Context, dest: impl AsRef<Path>) -> Result<()> {
     let mut buf = vec![];
@@ -211,6 +346,24 @@ impl ZineEngine {
         Ok(())
     }
 
+    // Copy the assets located alongside the markdown files, such as images.
+    // Assets in `pages` directory are copied into the dest root directory,
+    // assets in issue directory are copied into the corresponding issue directory.
+    fn copy_content_assets(&self) -> Result<()> {
+        let page_dir = self.source.join("pages");
+        if page_dir.exists() {
+            copy_assets(&page_dir, &self.dest)?;
+        }
+
+        for issue in &self.zine.issues {
+            let issue_dir = self.source.join(&issue.path);
+            if issue_dir.exists() {
+                copy_assets(&issue_dir, &self.dest.join(&issue.slug))?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn build(&mut self, reload: bool) -> Result<()> {
         if reload {
             self.zine = Zine::parse_from_toml(&self.source)?;
@@ -218,7 +371,20 @@ impl ZineEngine {
 
         self.zine.parse(&self.source)?;
 
+        // Fetch all URL previews ahead of rendering,
+        // so that a slow site never stalls the rendering.
+        if !is_offline() {
+            Handle::current().block_on(prefetch_url_previews(
+                self.zine.url_preview_urls(),
+                self.zine.fetch_config.concurrency,
+            ));
+        }
+
         init_tera(&self.source, &self.zine);
+        init_pipeline(&self.source, &self.dest, &self.zine.image_config);
+        if self.zine.markdown_config.highlight_code {
+            init_highlighting(&self.source)?;
+        }
 
         self.zine.render(Context::new(), &self.dest)?;
         #[cfg(debug_assertions)]
@@ -235,7 +401,12 @@ impl ZineEngine {
         sitemap_context.insert("entries", &self.zine.sitemap_entries());
         render_sitemap(sitemap_context, &self.dest)?;
 
-        self.copy_static_assets()
+        self.copy_content_assets()?;
+        self.copy_static_assets()?;
+        if self.zine.markdown_config.use_highlight_css() {
+            render_highlight_css(&self.zine.markdown_config, &self.dest)?;
+        }
+        Ok(())
     }
 }
 
@@ -244,61 +415,310 @@ struct HeadingRef<'a> {
     id: Option<&'a str>,
 }
 
+// The markdown image, whose alt text is collected from the events inside.
+struct ImageRef<'a> {
+    src: CowStr<'a>,
+    title: CowStr<'a>,
+    alt: String,
+}
+
 /// Markdown visitor.
 pub struct Vistor<'a> {
     markdown_config: &'a MarkdownConfig,
+    // The root directory of the project.
+    root: &'a Path,
+    // The source path of current markdown file, relative to the root directory.
+    path: Option<&'a Path>,
+    // Whether to sanitize the raw HTML and links.
+    sanitize: bool,
+    // The metadata of current article or page, such as title and url.
+    meta: Option<&'a Value>,
+    // The prefix of the footnote ids, see [`footnote_prefix`].
+    footnote_prefix: &'a str,
     code_block_fenced: Option<CowStr<'a>>,
+    // The text of the fenced code block, which may be split into multiple events,
+    // such as the code block nested in list items or blockquotes.
+    code_block_text: String,
     heading_ref: Option<HeadingRef<'a>>,
+    image_ref: Option<ImageRef<'a>>,
+    in_paragraph: bool,
 }
 
 impl<'a> Vistor<'a> {
-    fn new(markdown_config: &'a MarkdownConfig) -> Self {
+    fn new(
+        markdown_config: &'a MarkdownConfig,
+        root: &'a Path,
+        path: Option<&'a Path>,
+        sanitize: bool,
+        meta: Option<&'a Value>,
+        footnote_prefix: &'a str,
+    ) -> Self {
         Vistor {
             markdown_config,
+            root,
+            path,
+            sanitize,
+            meta,
+            footnote_prefix,
             code_block_fenced: None,
+            code_block_text: String::new(),
             heading_ref: None,
+            image_ref: None,
+            in_paragraph: false,
         }
     }
 
-    /// Clone a brand-new Visitor only with markdown config.
+    /// Clone a brand-new Visitor only with markdown config, root directory,
+    /// source path, sanitize option, metadata and footnote prefix.
     pub fn clone(&self) -> Self {
         Vistor {
             markdown_config: self.markdown_config,
+            root: self.root,
+            path: self.path,
+            sanitize: self.sanitize,
+            meta: self.meta,
+            footnote_prefix: self.footnote_prefix,
             code_block_fenced: None,
+            code_block_text: String::new(),
             heading_ref: None,
+            image_ref: None,
+            in_paragraph: false,
         }
     }
 
-    fn highlight_syntax(&self, lang: &str, text: &str) -> String {
-        let theme = match THEME_SET.themes.get(&self.markdown_config.highlight_theme) {
-            Some(theme) => theme,
-            None => panic!(
-                "No theme: `{}` founded",
-                self.markdown_config.highlight_theme
-            ),
+    /// Whether to sanitize the raw HTML and links.
+    pub fn sanitize(&self) -> bool {
+        self.sanitize
+    }
+
+    /// The source path of current markdown file, relative to the root directory.
+    pub fn path(&self) -> Option<&'a Path> {
+        self.path
+    }
+
+    /// The root directory of the project.
+    pub fn root(&self) -> &'a Path {
+        self.root
+    }
+
+    /// The metadata of current article or page.
+    pub fn meta(&self) -> Option<&'a Value> {
+        self.meta
+    }
+
+    /// Render the nested `markdown` of code block into HTML, such as the callout content,
+    /// the extensions are converted the same as the top-level markdown.
+    pub fn render_markdown(&self, markdown: &str) -> String {
+        let markdown = convert_extensions(markdown, self.markdown_config.math);
+        markdown_to_html(&markdown, self.clone())
+    }
+
+    /// Render the code snippet, highlight it if enabled.
+    pub fn render_code(
+        &self,
+        lang: &str,
+        options: &HashMap<String, Cow<str>>,
+        text: &str,
+    ) -> String {
+        let (lines, pre_attrs) = if self.markdown_config.highlight_code {
+            // Syntax highlight
+            self.highlight_lines(lang, text)
+        } else {
+            let lines = text.lines().map(|line| escape_html(line).into()).collect();
+            (lines, None)
+        };
+        CodeSnippet::new(lang, options, lines, pre_attrs)
+            .render()
+            .expect("Render code snippet failed.")
+    }
+
+    // Render the fenced code block with the `text` body.
+    fn render_fenced(&self, input: &str, text: &str) -> String {
+        let fenced = Fenced::parse(input).unwrap();
+        let lang = fenced.name;
+        let options = fenced.options.clone();
+        if fenced.is_custom_code_block() {
+            if let Some(html) = fenced.render_code_block(text, self.clone()) {
+                return html;
+            }
+        }
+
+        // Fallback to the plain code UI if the custom code block failed to render.
+        self.render_code(lang, &options, text)
+    }
+
+    /// Render the LaTeX math into MathML. If the math is invalid,
+    /// warn and render the raw math as code.
+    pub fn render_math(&self, math: &str, display: bool) -> String {
+        match latex_to_mathml(math, display) {
+            Ok(html) => html,
+            Err(err) => {
+                println!(
+                    "Warn: invalid math `{}` in `{}`: {}",
+                    math.trim(),
+                    self.path.unwrap_or_else(|| Path::new("")).display(),
+                    err
+                );
+                format!(r#"<code class="math-error">{}</code>"#, escape_html(math))
+            }
+        }
+    }
+
+    /// Resolve the link between markdown files into the final URL.
+    /// Return `None` if the link needn't to be resolved or the target doesn't exist.
+    ///
+    /// The link with dangerous scheme is resolved to `#` if sanitize enabled.
+    pub fn resolve_link(&self, link: &str) -> Option<String> {
+        if self.sanitize && escape_url(link).is_none() {
+            return Some(String::from("#"));
+        }
+        match data::read().link_resolver().resolve(link, self.path) {
+            Ok(url) => url,
+            Err(err) => {
+                match self.path {
+                    Some(path) => println!("Warn: {} in `{}`.", err, path.display()),
+                    None => println!("Warn: {}.", err),
+                }
+                None
+            }
+        }
+    }
+
+    // Render the markdown image, the image with title is rendered as a figure
+    // if it isn't inside a paragraph or heading. The dimensions and the `@2x`/`@3x`
+    // variants of the local image are added to avoid layout shift and for high-density screens.
+    fn render_image(&self, image: ImageRef<'_>) -> String {
+        let src = self
+            .resolve_link(&image.src)
+            .unwrap_or_else(|| image.src.to_string());
+        let mut html = format!(
+            r#"<img src="{}" alt="{}""#,
+            escape_html(&src),
+            escape_html(&image.alt)
+        );
+        let figure = !image.title.is_empty() && !self.in_paragraph && self.heading_ref.is_none();
+        if !image.title.is_empty() && !figure {
+            html.push_str(&format!(r#" title="{}""#, escape_html(&image.title)));
+        }
+        if let Some(file) = local_image_path(self.root, self.path, &image.src) {
+            if let Some((width, height)) = image_size(&file) {
+                html.push_str(&format!(r#" width="{}" height="{}""#, width, height));
+            }
+            let variants = density_variants(&file);
+            if !variants.is_empty() {
+                // The variants are alongside the image, replace the file name of URL.
+                let base = src.rsplit_once('/').map_or("", |(base, _)| base);
+                let srcset = std::iter::once(format!("{} 1x", src))
+                    .chain(variants.into_iter().map(|(name, density)| match base {
+                        "" => format!("{} {}x", name, density),
+                        base => format!("{}/{} {}x", base, name, density),
+                    }))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                html.push_str(&format!(r#" srcset="{}""#, escape_html(&srcset)));
+            }
+        }
+        // Add loading="lazy" attribute for markdown image.
+        html.push_str(r#" loading="lazy">"#);
+        if figure {
+            html = format!(
+                "<figure>{}<figcaption>{}</figcaption></figure>",
+                html,
+                escape_html(&image.title)
+            );
+        }
+        html
+    }
+
+    // Highlight the code line by line, return the HTML of each line without the line ending,
+    // and the attributes of `<pre>` for the highlight theme.
+    fn highlight_lines(&self, lang: &str, text: &str) -> (Vec<String>, Option<String>) {
+        let syntax_set = syntax_set();
+        let syntax = match syntax_set.find_syntax_by_token(lang) {
+            Some(syntax) => syntax,
+            None => {
+                // Warn only once for each language.
+                if !lang.is_empty() && UNKNOWN_LANGUAGES.lock().insert(lang.to_owned()) {
+                    println!(
+                        "Warn: no syntax of language `{}` found in `{}`, fallback to plain text.",
+                        lang,
+                        self.path.unwrap_or_else(|| Path::new("")).display()
+                    );
+                }
+                // Fallback to plain text if code block not supported
+                syntax_set.find_syntax_plain_text()
+            }
         };
 
-        let syntax = SYNTAX_SET
-            .find_syntax_by_token(lang)
-            // Fallback to plain text if code block not supported
-            .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
-        highlighted_html_for_string(text, &SYNTAX_SET, syntax, theme)
+        if self.markdown_config.highlight_style == HighlightStyle::Class {
+            // Highlight the whole code, since the classes of
+            // a multi-line token are spanning the line endings.
+            let mut generator = ClassedHTMLGenerator::new_with_class_style(
+                syntax,
+                syntax_set,
+                HIGHLIGHT_CLASS_STYLE,
+            );
+            for line in LinesWithEndings::from(text) {
+                generator.parse_html_for_line_which_includes_newline(line);
+            }
+            let lines = split_highlighted_lines(&generator.finalize());
+            return (lines, Some(String::from(r#"class="hl-code""#)));
+        }
+
+        let theme = match find_highlight_theme(&self.markdown_config.highlight_theme) {
+            Ok(theme) => theme,
+            Err(err) => panic!("{}", err),
+        };
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let lines = LinesWithEndings::from(text)
+            .map(|line| {
+                let regions = highlighter
+                    .highlight(line, syntax_set)
+                    .into_iter()
+                    .map(|(style, text)| (style, text.trim_end_matches(['\n', '\r'])))
+                    .collect::<Vec<_>>();
+                styled_line_to_highlighted_html(&regions[..], IncludeBackground::No)
+            })
+            .collect();
+        let style = theme.settings.background.map(|color| {
+            format!(
+                r#"style="background-color:#{:02x}{:02x}{:02x};""#,
+                color.r, color.g, color.b
+            )
+        });
+        (lines, style)
     }
 }
 
 impl<'a, 'b: 'a> MarkdownVisitor<'b> for Vistor<'a> {
     fn visit_start_tag(&mut self, tag: &Tag<'b>) -> Visiting {
+        // The tags inside image are part of the alt text.
+        if self.image_ref.is_some() {
+            return Visiting::Ignore;
+        }
         match tag {
             Tag::CodeBlock(CodeBlockKind::Fenced(name)) => {
                 self.code_block_fenced = Some(name.clone());
                 return Visiting::Ignore;
             }
+            Tag::Link(link_type, dest, title) => {
+                if let Some(url) = self.resolve_link(dest) {
+                    return Visiting::Event(Event::Start(Tag::Link(
+                        *link_type,
+                        url.into(),
+                        title.to_string().into(),
+                    )));
+                }
+            }
             Tag::Image(_, src, title) => {
-                // Add loading="lazy" attribute for markdown image.
-                return Visiting::Event(Event::Html(
-                    format!("<img src=\"{}\" alt=\"{}\" loading=\"lazy\">", src, title).into(),
-                ));
+                self.image_ref = Some(ImageRef {
+                    src: src.clone(),
+                    title: title.clone(),
+                    alt: String::new(),
+                });
+                return Visiting::Ignore;
             }
+            Tag::Paragraph => self.in_paragraph = true,
             Tag::Heading(level, id, _) => {
                 self.heading_ref = Some(HeadingRef {
                     level: *level as usize,
@@ -315,38 +735,42 @@ impl<'a, 'b: 'a> MarkdownVisitor<'b> for Vistor<'a> {
     }
 
     fn visit_end_tag(&mut self, tag: &Tag<'_>) -> Visiting {
-        match tag {
-            Tag::CodeBlock(_) => {
-                self.code_block_fenced = None;
-                Visiting::Ignore
+        if let Some(image) = self.image_ref.take() {
+            if let Tag::Image(..) = tag {
+                return Visiting::Event(Event::Html(self.render_image(image).into()));
             }
+            self.image_ref = Some(image);
+            return Visiting::Ignore;
+        }
+        match tag {
+            Tag::CodeBlock(_) => match self.code_block_fenced.take() {
+                // The fenced code block without body has no text event, such as `include`.
+                Some(input) => {
+                    let text = std::mem::take(&mut self.code_block_text);
+                    Visiting::Event(Event::Html(self.render_fenced(&input, &text).into()))
+                }
+                None => Visiting::Ignore,
+            },
             Tag::Heading(..) => {
                 self.heading_ref = None;
                 Visiting::Ignore
             }
+            Tag::Paragraph => {
+                self.in_paragraph = false;
+                Visiting::NotChanged
+            }
             _ => Visiting::NotChanged,
         }
     }
 
     fn visit_text(&mut self, text: &CowStr<'b>) -> Visiting {
-        if let Some(input) = self.code_block_fenced.as_ref() {
-            let fenced = Fenced::parse(input).unwrap();
-            if fenced.is_custom_code_block() {
-                // Block in place to execute async task
-                let rendered_html = task::block_in_place(|| {
-                    Handle::current()
-                        .block_on(async { fenced.render_code_block(text, self.clone()).await })
-                });
-                if let Some(html) = rendered_html {
-                    return Visiting::Event(Event::Html(html.into()));
-                }
-            } else if self.markdown_config.highlight_code {
-                // Syntax highlight
-                let html = self.highlight_syntax(fenced.name, text);
-                return Visiting::Event(Event::Html(html.into()));
-            } else {
-                return Visiting::Event(Event::Html(format!("<pre>{}</pre>", text).into()));
-            }
+        if self.code_block_fenced.is_some() {
+            self.code_block_text.push_str(text);
+            return Visiting::Ignore;
+        }
+        if let Some(image) = self.image_ref.as_mut() {
+            image.alt.push_str(text);
+            return Visiting::Ignore;
         }
 
         // Render heading anchor link.
@@ -366,7 +790,23 @@ impl<'a, 'b: 'a> MarkdownVisitor<'b> for Vistor<'a> {
         Visiting::NotChanged
     }
 
+    fn visit_html(&mut self, _html: &CowStr<'b>) -> Visiting {
+        if self.image_ref.is_some() {
+            return Visiting::Ignore;
+        }
+        // The raw HTML is sanitized along with the fully rendered HTML,
+        // a tag or comment may span several runs of raw HTML.
+        Visiting::NotChanged
+    }
+
     fn visit_code(&mut self, code: &CowStr<'b>) -> Visiting {
+        if let Some(image) = self.image_ref.as_mut() {
+            image.alt.push_str(code);
+            return Visiting::Ignore;
+        }
+        if let Some((math, display)) = parse_math_code(code) {
+            return Visiting::Event(Event::Html(self.render_math(math, display).into()));
+        }
         if let Some(maybe_author_id) = code.strip_prefix('@') {
             let data = data::read();
             if let Some(author) = data.get_author_by_id(maybe_author_id) {
@@ -379,13 +819,52 @@ impl<'a, 'b: 'a> MarkdownVisitor<'b> for Vistor<'a> {
         }
         Visiting::NotChanged
     }
+
+    fn sanitize_raw_html(&self) -> bool {
+        self.sanitize
+    }
+
+    fn footnote_prefix(&self) -> &str {
+        self.footnote_prefix
+    }
+
+    fn render_footnotes(&mut self, footnotes: &[Footnote]) -> String {
+        let mut context = Context::new();
+        context.insert("footnotes", footnotes);
+        context.insert("popover", &self.markdown_config.footnote_popover);
+        get_tera()
+            .render("_footnotes.jinja", &context)
+            .expect("Render footnotes failed.")
+    }
 }
 
 // A tera function to convert markdown into html.
 impl Function for MarkdownRender {
     fn call(&self, map: &HashMap<String, Value>) -> tera::Result<Value> {
         if let Some(Value::String(markdown)) = map.get("markdown") {
-            let html = markdown_to_html(markdown, Vistor::new(&self.markdown_config));
+            // The optional source path of the markdown file, used to resolve relative links.
+            let path = map.get("path").and_then(Value::as_str).map(Path::new);
+            // The optional sanitize option overrides the global config, such as per article.
+            let sanitize = map
+                .get("sanitize")
+                .and_then(Value::as_bool)
+                .unwrap_or(self.markdown_config.sanitize_html);
+            // The optional metadata of current article or page, which is passed to plugins.
+            let meta = map.get("meta");
+            // The optional unique id of the fragment in the page, such as the article slug.
+            let prefix = footnote_prefix(map.get("id").and_then(Value::as_str), markdown);
+            let markdown = convert_extensions(markdown, self.markdown_config.math);
+            let html = markdown_to_html(
+                &markdown,
+                Vistor::new(
+                    &self.markdown_config,
+                    &self.source,
+                    path,
+                    sanitize,
+                    meta,
+                    &prefix,
+                ),
+            );
             Ok(Value::String(html))
         } else {
             Ok(Value::Array(vec![]))
@@ -393,6 +872,28 @@ impl Function for MarkdownRender {
     }
 }
 
+// The prefix of the footnote ids of a markdown fragment, such as `first-` of the
+// footnote `fn-first-1`. The fragment without id is prefixed with its content hash,
+// so that the footnotes of several fragments in one page never share the ids.
+fn footnote_prefix(id: Option<&str>, markdown: &str) -> String {
+    match id.filter(|id| !id.is_empty()) {
+        Some(id) => {
+            let id = id
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '_' {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect::<String>();
+            format!("{}-", id)
+        }
+        None => format!("{:08x}-", content_hash(markdown.as_bytes()) as u32),
+    }
+}
+
 fn get_author_fn(map: &HashMap<String, Value>) -> tera::Result<Value> {
     if let Some(Value::String(author_id)) = map.get("id") {
         let data = data::read();
@@ -402,3 +903,389 @@ fn get_author_fn(map: &HashMap<String, Value>) -> tera::Result<Value> {
         Ok(Value::Null)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashSet},
+        env, fs, ops,
+        path::{Path, PathBuf},
+        process,
+        sync::Once,
+    };
+
+    use tera::{Context, Tera};
+
+    use crate::{
+        data::{self, UrlPreviewInfo},
+        entity::{Entity, MarkdownConfig, Zine},
+        markdown::{convert_extensions, markdown_to_html},
+    };
+
+    use syntect::{
+        easy::HighlightLines,
+        html::{styled_line_to_highlighted_html, IncludeBackground},
+    };
+
+    use super::{
+        footnote_prefix, get_tera, init_highlighting, init_tera, load_syntax_set, load_theme_set,
+        register_code_block_templates, Vistor,
+    };
+
+    // Initialize the data, templates and highlighting once, return the root directory.
+    // The global state is never changed by the tests, since they run in parallel.
+    fn init() -> &'static Path {
+        static INIT: Once = Once::new();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        INIT.call_once(|| {
+            data::load(root);
+            let mut zine = test_zine();
+            zine.theme.code_block_templates.insert(
+                String::from("quote"),
+                String::from(
+                    "<blockquote>{{ content | safe }}<cite>{{ options.author }}</cite></blockquote>",
+                ),
+            );
+            init_tera(root, &zine);
+            init_highlighting(root).unwrap();
+        });
+        root
+    }
+
+    // The unique temporary directory of a test, which is removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("__zine_{}_{}", name, process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn test_zine() -> Zine {
+        toml::from_str(
+            r#"
+            [site]
+            url = "http://localhost"
+            name = "Test"
+            "#,
+        )
+        .unwrap()
+    }
+
+    // Render the markdown like an article with the default config.
+    fn render(markdown: &str) -> String {
+        render_in(init(), markdown)
+    }
+
+    // Render the markdown in the `root` directory.
+    fn render_in(root: &Path, markdown: &str) -> String {
+        init();
+        let config = MarkdownConfig::default();
+        let markdown = convert_extensions(markdown, config.math);
+        markdown_to_html(&markdown, Vistor::new(&config, root, None, false, None, ""))
+    }
+
+    #[test]
+    fn test_render_sanitized() {
+        let root = init();
+        let config = MarkdownConfig::default();
+        let render = |markdown: &str| {
+            let markdown = convert_extensions(markdown, config.math);
+            markdown_to_html(&markdown, Vistor::new(&config, root, None, true, None, ""))
+        };
+
+        // The tag of raw HTML spans the markdown in between.
+        let html = render("<div title=\"x\n\n**Hi**\n\n<p>\" onclick=\"alert(1)\">\n");
+        assert!(!html.contains("onclick"), "{}", html);
+
+        // The rendered code blocks and markdown are kept, the raw HTML inside is sanitized.
+        let html = render(
+            "> [!NOTE]\n> Hi<script>alert(1)</script>[^1]\n\n| a |\n|:-:|\n| b |\n\n- [x] done\n\n[^1]: Note<iframe></iframe>\n",
+        );
+        assert!(html.contains(r#"<div class="callout callout-note">"#));
+        assert!(html.contains("<svg"));
+        assert!(html.contains(r#"<th style="text-align: center">a</th>"#));
+        assert!(html.contains(r#"<input disabled="" type="checkbox" checked=""/>"#));
+        assert!(html.contains("<li id=\"fn-1\">\n<p>Note"));
+        assert!(!html.contains("script"));
+        assert!(!html.contains("iframe"));
+    }
+
+    #[test]
+    fn test_render_code_block_in_list() {
+        let html = render("- item\n\n  ```txt\n  fn a() {}\n  fn b() {}\n  fn c() {}\n  ```\n");
+        let (code, rest) = html.split_once("</pre>").unwrap();
+        assert_eq!(code.matches(r#"<span class="code-line">"#).count(), 3);
+        for line in ["fn a() {}", "fn b() {}", "fn c() {}"] {
+            assert!(code.contains(line), "{} is not in the code block", line);
+        }
+        assert!(!rest.contains("fn"));
+
+        // The GitHub alert is converted into a callout code block.
+        let html = render("- item\n\n  > [!NOTE]\n  > one\n  > two\n");
+        assert!(
+            html.contains("<div class=\"callout-content\"><p>one\ntwo</p>\n</div>\n</div>\n</li>")
+        );
+    }
+
+    #[test]
+    fn test_render_callout() {
+        // The alert title with quotes is kept and escaped.
+        let html = render("> [!TIP]- Say \"Hi\" <b>\n> Hello **Zine**");
+        assert!(html.starts_with(r#"<details class="callout callout-tip">"#));
+        assert!(html.contains(r#"<summary class="callout-title"><svg class="callout-icon""#));
+        assert!(html.contains("<span>Say &quot;Hi&quot; &lt;b&gt;</span></summary>"));
+        assert!(html.contains(r#"<div class="callout-content"><p>Hello <strong>Zine</strong></p>"#));
+
+        // The localized default title of the preset type.
+        let html = render("```callout, type: note\nHi\n```");
+        assert!(html.starts_with(r#"<div class="callout callout-note">"#));
+        assert!(html.contains("<span>Note</span></div>"));
+
+        // The plain callout without title.
+        let html = render("```callout, bg_color: #123456\nHi\n```");
+        assert_eq!(
+            html,
+            r#"<div class="callout" style="background-color: #123456; border-color: var(--primary-color)">
+<div class="callout-content"><p>Hi</p>
+</div>
+</div>
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_footnotes_in_callout() {
+        let html = render(
+            "Hello[^a].\n\n```callout\nInside[^b] and[^a].\n\n[^b]: Callout note.\n```\n\n[^a]: Article note.\n",
+        );
+        assert_eq!(html.matches(r#"<section class="footnotes""#).count(), 1);
+        assert!(html.contains(
+            r##"Inside<sup class="footnote-ref"><a href="#fn-2" id="fnref-2">2</a></sup> and<sup class="footnote-ref"><a href="#fn-1" id="fnref-1-2">1</a></sup>"##
+        ));
+        assert!(html.contains("<li id=\"fn-1\">\n<p>Article note."));
+        assert!(html.contains("<li id=\"fn-2\">\n<p>Callout note."));
+        assert!(!html.contains("[^"));
+    }
+
+    #[test]
+    fn test_render_footnotes_of_fragments() {
+        init();
+        let mut tera = get_tera().clone();
+        tera.add_raw_template(
+            "fragments.jinja",
+            "{{ markdown_to_html(markdown = article, id = \"first post\") }}\
+             {{ markdown_to_html(markdown = comment) }}",
+        )
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("article", "Hello[^1].\n\n[^1]: Article note.\n");
+        context.insert("comment", "Nice[^1].\n\n[^1]: Comment note.\n");
+        let html = tera.render("fragments.jinja", &context).unwrap();
+
+        // The fragment with id is prefixed with the id.
+        assert!(html.contains(
+            r##"Hello<sup class="footnote-ref"><a href="#fn-first-post-1" id="fnref-first-post-1">1</a></sup>"##
+        ));
+        assert!(html.contains("<li id=\"fn-first-post-1\">\n<p>Article note."));
+        assert!(html.contains(r##"<a href="#fnref-first-post-1" class="footnote-backref""##));
+
+        // The fragment without id is prefixed with its content hash.
+        let prefix = footnote_prefix(None, "Nice[^1].\n\n[^1]: Comment note.\n");
+        assert!(html.contains(&format!("<li id=\"fn-{}1\">\n<p>Comment note.", prefix)));
+
+        // No id is duplicated in the page.
+        let ids = html
+            .split(" id=\"")
+            .skip(1)
+            .map(|rest| &rest[..rest.find('"').unwrap()])
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
+    }
+
+    #[test]
+    fn test_render_embed() {
+        let url = "https://youtu.be/dQw4w9WgXcQ";
+        init();
+        data::write().insert_url_preview(
+            url,
+            UrlPreviewInfo {
+                title: String::from("Never gonna"),
+                image: Some(String::from("https://i.ytimg.com/vi/dQw4w9WgXcQ/hq.jpg")),
+                ..Default::default()
+            },
+        );
+        // The thumbnail of the provider is never loaded, only the local poster.
+        let html = render(&format!("```embed\n{}\n```\n", url));
+        assert!(html.contains(r#"data-embed-title="Never gonna""#));
+        assert!(!html.contains("<img") && !html.contains("ytimg.com"));
+        let html = render(&format!(
+            "```embed, poster: /static/talk.png\n{}\n```\n",
+            url
+        ));
+        assert!(html.contains(r#"<img class="embed-poster" src="/static/talk.png""#));
+
+        let html = render("```embed, title: Rust\nhttps://x.com/rustlang/status/123\n```\n");
+        assert!(html.starts_with(
+            r#"<blockquote class="embed embed-post" cite="https://x.com/rustlang/status/123">"#
+        ));
+        assert!(html.contains(r#"<p class="embed-post-author">Rust</p>"#));
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn test_render_image() {
+        let root = TempDir::new("render_image");
+        fs::create_dir_all(root.join("static")).unwrap();
+        // The PNG header of 640x480 image is enough to parse the size.
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x02\x80\0\0\x01\xe0";
+        fs::write(root.join("static/pic.png"), png).unwrap();
+        fs::write(root.join("static/pic@2x.png"), png).unwrap();
+
+        // The standalone image with title is a figure.
+        let html = render_in(&root, r#"![Alt *text*](/static/pic.png "Title")"#);
+        assert_eq!(
+            html.trim_end(),
+            r#"<figure><img src="/static/pic.png" alt="Alt text" width="640" height="480" srcset="/static/pic.png 1x, /static/pic@2x.png 2x" loading="lazy"><figcaption>Title</figcaption></figure>"#
+        );
+        // The inline image keeps the title.
+        let html = render_in(&root, r#"Inline ![Alt](/static/pic.png "Title")"#);
+        assert!(html.contains(
+            r#"<img src="/static/pic.png" alt="Alt" title="Title" width="640" height="480" srcset="#
+        ));
+        // The missing image has no dimensions.
+        let html = render_in(&root, "Missing ![Alt](/static/none.png)");
+        assert!(html.contains(r#"<img src="/static/none.png" alt="Alt" loading="lazy">"#));
+    }
+
+    #[test]
+    fn test_parse_code_block_templates() {
+        let source = TempDir::new("block_templates");
+        let block_dir = source.join("templates/blocks");
+        fs::create_dir_all(&block_dir).unwrap();
+        fs::write(block_dir.join("quote.jinja"), "quote").unwrap();
+        // The builtin code block can't be overridden.
+        fs::write(block_dir.join("callout.jinja"), "overridden").unwrap();
+        fs::write(block_dir.join("notes.txt"), "ignored").unwrap();
+
+        let mut zine = test_zine();
+        zine.theme.parse(&source).unwrap();
+        assert_eq!(
+            zine.theme.code_block_templates.keys().collect::<Vec<_>>(),
+            vec!["quote"]
+        );
+    }
+
+    #[test]
+    fn test_render_code_block_template() {
+        assert_eq!(
+            render("```quote, author: Alice\nHello *Zine*\n```\n"),
+            "<blockquote><p>Hello <em>Zine</em></p>\n<cite>Alice</cite></blockquote>"
+        );
+
+        // The content is converted with the markdown extensions, such as the alerts.
+        let html = render("````quote, author: Alice\n> [!NOTE]\n> Hi\n````\n");
+        assert!(html.starts_with(r#"<blockquote><div class="callout callout-note">"#));
+        assert!(html.contains(r#"<div class="callout-content"><p>Hi</p>"#));
+        assert!(html.ends_with("<cite>Alice</cite></blockquote>"));
+    }
+
+    #[test]
+    fn test_load_highlighting() {
+        let source = TempDir::new("highlighting");
+        fs::create_dir_all(source.join("syntaxes")).unwrap();
+        fs::create_dir_all(source.join("themes")).unwrap();
+        fs::write(
+            source.join("syntaxes/zinetest.sublime-syntax"),
+            r#"%YAML 1.2
+---
+name: Zine Test
+file_extensions: [zinetest]
+scope: source.zinetest
+contexts:
+  main:
+    - match: '\bzine\b'
+      scope: keyword.control.zinetest
+"#,
+        )
+        .unwrap();
+        fs::write(
+            source.join("themes/zine-test.tmTheme"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>name</key><string>Zine Test</string>
+  <key>settings</key>
+  <array>
+    <dict><key>settings</key><dict><key>background</key><string>#123456</string></dict></dict>
+    <dict>
+      <key>scope</key><string>keyword</string>
+      <key>settings</key><dict><key>foreground</key><string>#FF0000</string></dict>
+    </dict>
+  </array>
+</dict>
+</plist>
+"#,
+        )
+        .unwrap();
+
+        // The custom syntaxes and themes are merged into the built-in ones.
+        let syntax_set = load_syntax_set(&source).unwrap();
+        let theme_set = load_theme_set(&source).unwrap();
+        assert!(syntax_set.find_syntax_by_token("rust").is_some());
+        assert!(theme_set.themes.contains_key("base16-ocean-dark"));
+        let syntax = syntax_set.find_syntax_by_token("zinetest").unwrap();
+        let theme = &theme_set.themes["zine-test"];
+
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        let regions = highlighter.highlight("hello zine", &syntax_set);
+        let html = styled_line_to_highlighted_html(&regions, IncludeBackground::No);
+        assert_eq!(
+            html,
+            r#"<span style="color:#000000;">hello </span><span style="color:#ff0000;">zine</span>"#
+        );
+
+        // The invalid custom syntax fails to load.
+        fs::write(source.join("syntaxes/broken.sublime-syntax"), "name: [").unwrap();
+        assert!(load_syntax_set(&source).is_err());
+    }
+
+    #[test]
+    fn test_register_code_block_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("page.jinja", "page").unwrap();
+        let templates = BTreeMap::from([
+            (String::from("a"), String::from("a")),
+            (String::from("b"), String::from("b")),
+        ]);
+        register_code_block_templates(&mut tera, &templates);
+        let templates = BTreeMap::from([(String::from("b"), String::from("b2"))]);
+        register_code_block_templates(&mut tera, &templates);
+
+        let mut names = tera.get_template_names().collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, vec!["blocks/b.jinja", "page.jinja"]);
+        assert_eq!(
+            tera.render("blocks/b.jinja", &Context::new()).unwrap(),
+            "b2"
+        );
+    }
+}
@@ -7,7 +7,12 @@ use serde::{Deserialize, Serialize};
 use tera::Context;
 use time::Date;
 
-use crate::{current_mode, engine, markdown, meta::Meta, Mode};
+use crate::{
+    current_mode, data, engine, markdown,
+    meta::Meta,
+    plugin::{self, PluginHook},
+    Mode,
+};
 
 use super::{AuthorId, EndMatter, Entity};
 
@@ -46,6 +51,10 @@ pub struct Article {
     /// generate HTML file in this mode.
     #[serde(default)]
     pub publish: bool,
+    /// Whether to sanitize the raw HTML in the markdown of this article.
+    /// Fallback to the authors' option, then the `[markdown]` config if missing.
+    #[serde(default)]
+    pub sanitize_html: Option<bool>,
 }
 
 impl std::fmt::Debug for Article {
@@ -76,6 +85,26 @@ impl Article {
         self.publish || matches!(current_mode(), Mode::Serve)
     }
 
+    /// Whether to sanitize the raw HTML of this article.
+    ///
+    /// The article's option takes precedence, otherwise sanitize if any author requires.
+    /// Return `None` to fallback to the `[markdown]` config.
+    pub fn sanitize_html(&self) -> Option<bool> {
+        if self.sanitize_html.is_some() {
+            return self.sanitize_html;
+        }
+        let data = data::read();
+        let options = match self.meta.author.as_ref()? {
+            AuthorId::One(id) => vec![data.get_author_by_id(id)?.sanitize_html],
+            AuthorId::List(ids) => ids
+                .iter()
+                .filter_map(|id| data.get_author_by_id(id))
+                .map(|author| author.sanitize_html)
+                .collect(),
+        };
+        options.into_iter().flatten().reduce(|a, b| a || b)
+    }
+
     pub fn slug(&self) -> String {
         self.meta
             .slug
@@ -99,7 +128,13 @@ impl Entity for Article {
             meta.cover = Some(String::from("/static/zine-placeholder.svg"));
         }
 
-        self.markdown = content.to_owned();
+        // The path relative to the root directory.
+        let path = file_path
+            .strip_prefix(plugin::plugin_root())
+            .unwrap_or(&file_path);
+        self.markdown =
+            plugin::preprocess_markdown(PluginHook::Article, path, &self.meta, content.to_owned())
+                .with_context(|| format!("Failed to preprocess article `{}`", path.display()))?;
         self.end_matter = end_matter;
         Ok(())
     }
@@ -117,6 +152,7 @@ impl Entity for Article {
         context.insert("page_type", "article");
         context.insert("article", &self);
         context.insert("end_matter", &self.end_matter);
+        context.insert("sanitize_html", &self.sanitize_html());
         engine::render("article.jinja", &context, dest)?;
         Ok(())
     }
@@ -32,6 +32,10 @@ pub struct Author {
     #[serde(default)]
     #[serde(rename(deserialize = "editor"))]
     pub is_editor: bool,
+    /// Whether to sanitize the raw HTML in the markdown of this author,
+    /// including the bio and articles. Fallback to `[markdown]` config if missing.
+    #[serde(default)]
+    pub sanitize_html: Option<bool>,
 }
 
 // A [`Author`] struct with additional `article_count` field.
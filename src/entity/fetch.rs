@@ -0,0 +1,27 @@
+use serde::Deserialize;
+
+/// The config to fetch remote URLs, such as URL previews and external link checks.
+#[derive(Debug, Deserialize, Clone)]
+pub struct FetchConfig {
+    /// The `User-Agent` header of requests.
+    /// Default to `Mozilla/5.0 (compatible; zine/<version>; +https://github.com/zineland/zine)`.
+    pub user_agent: Option<String>,
+    /// The max number of URL previews to fetch concurrently. Default to 8.
+    #[serde(default = "FetchConfig::default_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: None,
+            concurrency: Self::default_concurrency(),
+        }
+    }
+}
+
+impl FetchConfig {
+    fn default_concurrency() -> usize {
+        8
+    }
+}
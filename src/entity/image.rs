@@ -0,0 +1,46 @@
+use serde::Deserialize;
+
+/// The config of the build-time image pipeline, which generates the resized
+/// and WebP versions of local images referenced by covers and markdown.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ImageConfig {
+    /// Whether to generate the responsive images. Default to false.
+    #[serde(default)]
+    pub responsive: bool,
+    /// The widths of the resized images, the widths not smaller than
+    /// the original image are skipped. Default to `[480, 960, 1440]`.
+    #[serde(default = "ImageConfig::default_widths")]
+    pub widths: Vec<u32>,
+    /// Whether to generate the WebP versions. Default to true.
+    #[serde(default = "ImageConfig::default_webp")]
+    pub webp: bool,
+    /// The `sizes` attribute of the responsive images without `sizes`.
+    /// Default to `(min-width: 768px) 768px, 100vw`.
+    #[serde(default = "ImageConfig::default_sizes")]
+    pub sizes: String,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            responsive: false,
+            widths: Self::default_widths(),
+            webp: Self::default_webp(),
+            sizes: Self::default_sizes(),
+        }
+    }
+}
+
+impl ImageConfig {
+    fn default_widths() -> Vec<u32> {
+        vec![480, 960, 1440]
+    }
+
+    fn default_webp() -> bool {
+        true
+    }
+
+    fn default_sizes() -> String {
+        String::from("(min-width: 768px) 768px, 100vw")
+    }
+}
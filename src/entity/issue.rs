@@ -7,7 +7,7 @@ use tera::Context;
 
 use crate::{engine, markdown, meta::Meta};
 
-use super::{article::Article, Entity};
+use super::{article::Article, join_render_tasks, Entity};
 
 /// The issue entity config.
 /// It parsed from issue directory's `zine.toml`.
@@ -18,8 +18,14 @@ pub struct Issue {
     pub title: String,
     /// The optional introduction for this issue.
     pub intro: Option<String>,
+    /// The path of the introduction file, relative to the root directory.
+    #[serde(skip_deserializing)]
+    pub intro_path: Option<String>,
     pub cover: Option<String>,
     pub path: String,
+    /// Whether to sanitize the raw HTML of the introduction,
+    /// default to the `sanitize_html` of markdown config.
+    pub sanitize_html: Option<bool>,
     // Skip serialize `articles` since a single article page would
     // contain a issue context, the `articles` is useless for the
     // single article page.
@@ -74,11 +80,12 @@ impl Issue {
 impl Entity for Issue {
     fn parse(&mut self, source: &Path) -> Result<()> {
         // Parse intro file
-        if let Some(intro_path) = &self.intro {
+        if let Some(intro_path) = self.intro.take() {
             self.intro = Some(
                 fs::read_to_string(&source.join(&intro_path))
                     .with_context(|| format!("Failed to read intro from {}", intro_path))?,
             );
+            self.intro_path = Some(intro_path);
         }
 
         // Representing a zine.toml file for issue.
@@ -112,19 +119,20 @@ impl Entity for Issue {
             .filter(|article| article.need_publish())
             .collect::<Vec<_>>();
         // Render articles with number context.
-        for (index, article) in articles.iter().enumerate() {
-            let mut context = context.clone();
-            context.insert("siblings", &self.sibling_articles(index));
-            context.insert("number", &(index + 1));
-            let dest = issue_dir.join(article.slug());
-            let article = (*article).clone();
-
-            tokio::task::spawn_blocking(move || {
-                article
-                    .render(context, &dest)
-                    .expect("Render article failed.");
-            });
-        }
+        let tasks = articles
+            .iter()
+            .enumerate()
+            .map(|(index, article)| {
+                let mut context = context.clone();
+                context.insert("siblings", &self.sibling_articles(index));
+                context.insert("number", &(index + 1));
+                let dest = issue_dir.join(article.slug());
+                let article = (*article).clone();
+
+                tokio::task::spawn_blocking(move || article.render(context, &dest))
+            })
+            .collect();
+        join_render_tasks(tasks)?;
 
         context.insert("articles", &articles);
         context.insert(
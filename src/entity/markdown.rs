@@ -7,6 +7,23 @@ pub struct MarkdownConfig {
     pub highlight_code: bool,
     #[serde(default = "MarkdownConfig::default_highlight_theme")]
     pub highlight_theme: String,
+    /// How to apply the highlight theme, inline styles or CSS classes. Default to inline.
+    #[serde(default)]
+    pub highlight_style: HighlightStyle,
+    /// The highlight theme used in dark mode, only available for the class style.
+    pub highlight_dark_theme: Option<String>,
+    /// Whether to render the `$...$` and `$$...$$` math into MathML. Default to false.
+    /// The `math` code block is always rendered.
+    #[serde(default)]
+    pub math: bool,
+    /// Whether to show the footnote content in a popover when hovering the reference.
+    /// Default to false.
+    #[serde(default)]
+    pub footnote_popover: bool,
+    /// Whether to sanitize the raw HTML in markdown against an allowlist. Default to false.
+    /// It can be overridden per article or per author.
+    #[serde(default)]
+    pub sanitize_html: bool,
 }
 
 impl Default for MarkdownConfig {
@@ -14,6 +31,11 @@ impl Default for MarkdownConfig {
         Self {
             highlight_code: true,
             highlight_theme: Self::default_highlight_theme(),
+            highlight_style: HighlightStyle::default(),
+            highlight_dark_theme: None,
+            math: false,
+            footnote_popover: false,
+            sanitize_html: false,
         }
     }
 }
@@ -28,4 +50,20 @@ impl MarkdownConfig {
     fn default_highlight_code() -> bool {
         true
     }
+
+    /// Whether the highlighted code needs the generated highlight stylesheet.
+    pub fn use_highlight_css(&self) -> bool {
+        self.highlight_code && self.highlight_style == HighlightStyle::Class
+    }
+}
+
+/// The way to apply the highlight theme to the code.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HighlightStyle {
+    /// Emit the theme colors as inline styles.
+    #[default]
+    Inline,
+    /// Emit the CSS classes, the colors are in the generated `/static/zine-highlight.css`.
+    Class,
 }
@@ -2,10 +2,13 @@ use anyhow::Result;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use std::path::Path;
 use tera::Context;
+use tokio::{runtime::Handle, task::JoinHandle};
 
 mod article;
 mod author;
 mod end_matter;
+mod fetch;
+mod image;
 mod issue;
 mod markdown;
 mod page;
@@ -17,10 +20,12 @@ pub use self::zine::Zine;
 pub use article::{Article, MetaArticle};
 pub use author::{Author, AuthorId, AuthorList};
 pub use end_matter::EndMatter;
+pub use fetch::FetchConfig;
+pub use image::ImageConfig;
 pub use issue::Issue;
-pub use markdown::MarkdownConfig;
+pub use markdown::{HighlightStyle, MarkdownConfig};
 pub use page::Page;
-pub use site::Site;
+pub use site::{Menu, Site};
 pub use theme::Theme;
 
 /// A trait represents the entity of zine config file.
@@ -63,14 +68,25 @@ impl<T: Entity + Sync + Send + Clone + 'static> Entity for Vec<T> {
     }
 
     fn render(&self, context: Context, dest: &Path) -> Result<()> {
-        for item in self {
-            let item = item.clone();
-            let render = context.clone();
-            let dest = dest.to_path_buf();
-            tokio::task::spawn_blocking(move || {
-                item.render(render, &dest).expect("Render failed.")
-            });
-        }
-        Ok(())
+        let tasks = self
+            .iter()
+            .map(|item| {
+                let item = item.clone();
+                let render = context.clone();
+                let dest = dest.to_path_buf();
+                tokio::task::spawn_blocking(move || item.render(render, &dest))
+            })
+            .collect();
+        join_render_tasks(tasks)
+    }
+}
+
+// Wait for all spawned render tasks finished, make sure all HTML files
+// have been generated once the rendering returned.
+fn join_render_tasks(tasks: Vec<JoinHandle<Result<()>>>) -> Result<()> {
+    let handle = Handle::current();
+    for task in tasks {
+        handle.block_on(task)??;
     }
+    Ok(())
 }
@@ -1,30 +1,111 @@
 use std::{
     borrow::Cow,
+    fs,
     path::{Path, PathBuf},
 };
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tera::Context;
 
-use crate::{engine, markdown, meta::Meta};
+use crate::{
+    current_mode, engine, markdown,
+    meta::Meta,
+    plugin::{self, PluginHook},
+    Mode,
+};
 
 use super::Entity;
 
+/// The meta info of Page, declared in the optional front matter.
+///
+/// Here is the format:
+/// ```toml
+/// +++
+/// title = "About"
+/// description = "About this magazine."
+/// slug = "about-us"
+/// cover = "/static/about.png"
+/// template = "templates/about.jinja"
+/// publish = true
+/// menu = 1
+/// +++
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetaPage {
+    /// The page title. Fallback to the first heading of the markdown if missing.
+    pub title: Option<String>,
+    /// The page description. Fallback to the first meaningful line if missing.
+    pub description: Option<String>,
+    /// The slug after this page rendered.
+    /// Default to the relative file path without `.md` if no slug specified.
+    pub slug: Option<String>,
+    pub cover: Option<String>,
+    /// The custom template path to render this page, will be parsed to template content.
+    #[serde(skip_serializing)]
+    pub template: Option<String>,
+    /// Whether publish the page. Default to true.
+    #[serde(default = "MetaPage::default_publish")]
+    pub publish: bool,
+    /// The menu weight. The page will be registered into the site menus
+    /// if specified, ordered by the weight in ascending order.
+    pub menu: Option<i32>,
+}
+
+impl Default for MetaPage {
+    fn default() -> Self {
+        Self {
+            title: None,
+            description: None,
+            slug: None,
+            cover: None,
+            template: None,
+            publish: Self::default_publish(),
+            menu: None,
+        }
+    }
+}
+
+impl MetaPage {
+    fn default_publish() -> bool {
+        true
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Page {
     // The page's markdown content.
     pub markdown: String,
     // Relative path of page file.
     pub file_path: PathBuf,
+    #[serde(flatten, default)]
+    pub meta: MetaPage,
 }
 
 impl Page {
+    pub fn new(markdown: String, file_path: PathBuf) -> Self {
+        Page {
+            markdown,
+            file_path,
+            meta: MetaPage::default(),
+        }
+    }
+
     pub fn slug(&self) -> String {
-        self.file_path.to_str().unwrap().replace(".md", "")
+        self.meta
+            .slug
+            .as_ref()
+            .cloned()
+            .unwrap_or_else(|| self.file_path.to_str().unwrap().replace(".md", ""))
     }
 
-    fn title(&self) -> String {
+    pub fn title(&self) -> String {
+        if let Some(title) = self.meta.title.as_ref() {
+            return title.to_owned();
+        }
+
         let prefix = &['#', ' '];
         self.markdown
             .lines()
@@ -37,32 +118,122 @@ impl Page {
             })
             .unwrap_or_default()
     }
+
+    fn description(&self) -> String {
+        self.meta
+            .description
+            .as_ref()
+            .cloned()
+            .unwrap_or_else(|| markdown::extract_description(&self.markdown))
+    }
+
+    /// Check whether the page need publish.
+    ///
+    /// The page need publish in any of two conditions:
+    /// - the publish property is true
+    /// - in `zine serve` mode
+    pub fn need_publish(&self) -> bool {
+        self.meta.publish || matches!(current_mode(), Mode::Serve)
+    }
+
+    /// The name of custom template registered in tera.
+    pub fn template_name(&self) -> String {
+        format!("page-{}.jinja", self.slug())
+    }
 }
 
 impl Entity for Page {
+    fn parse(&mut self, source: &Path) -> Result<()> {
+        let (content, meta) = split_page_content(&self.markdown).with_context(|| {
+            format!(
+                "Failed to parse front matter of page `{}`",
+                self.file_path.display()
+            )
+        })?;
+
+        if let Some(mut meta) = meta {
+            if let Some(template) = meta.template.as_ref() {
+                // Read custom page template from path to content.
+                meta.template =
+                    Some(fs::read_to_string(source.join(template)).with_context(|| {
+                        format!(
+                            "Failed to parse the page template: `{}`",
+                            source.join(template).display(),
+                        )
+                    })?);
+            }
+            self.meta = meta;
+            self.markdown = content.to_owned();
+        }
+
+        self.markdown = plugin::preprocess_markdown(
+            PluginHook::Page,
+            &Path::new("pages").join(&self.file_path),
+            &self.meta,
+            std::mem::take(&mut self.markdown),
+        )
+        .with_context(|| format!("Failed to preprocess page `{}`", self.file_path.display()))?;
+        Ok(())
+    }
+
     fn render(&self, mut context: Context, dest: &Path) -> Result<()> {
+        if !self.need_publish() {
+            return Ok(());
+        }
+
         context.insert(
             "meta",
             &Meta {
-                title: Cow::Borrowed(&self.title()),
-                description: Cow::Owned(markdown::extract_description(&self.markdown)),
+                title: Cow::Owned(self.title()),
+                description: Cow::Owned(self.description()),
                 url: Some(Cow::Owned(self.slug())),
-                image: None,
+                image: self.meta.cover.as_deref().map(Cow::Borrowed),
             },
         );
+        context.insert("page", &self.meta);
         context.insert("markdown", &self.markdown);
-        engine::render("page.jinja", &context, dest.join(self.slug()))?;
+        context.insert("path", &Path::new("pages").join(&self.file_path));
+
+        let template = if self.meta.template.is_some() {
+            self.template_name()
+        } else {
+            String::from("page.jinja")
+        };
+        engine::render(&template, &context, dest.join(self.slug()))?;
         Ok(())
     }
 }
 
+static FRONT_MATTER_REGEX: Lazy<Regex> = Lazy::new(|| {
+    // The regex is an variant of zola's fronmatter regex.
+    Regex::new(
+        r"^[[:space:]]*\+\+\+(\r?\n(?s).*?(?-s))\+\+\+[[:space:]]*(?:$|(?:\r?\n((?s).*(?-s))$))",
+    )
+    .unwrap()
+});
+
+// Split optional front matter and page content from page markdown.
+fn split_page_content(markdown: &str) -> Result<(&str, Option<MetaPage>)> {
+    if let Some(caps) = FRONT_MATTER_REGEX.captures(markdown) {
+        // caps[0] is the full match
+        // caps[1] => front matter
+        // caps[2] => page content
+        let front_matter = caps.get(1).expect("").as_str().trim();
+        let content = caps.get(2).map(|m| m.as_str().trim()).unwrap_or_default();
+        let meta = toml::from_str::<MetaPage>(front_matter)?;
+        return Ok((content, Some(meta)));
+    }
+
+    Ok((markdown, None))
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
 
     use test_case::test_case;
 
-    use super::Page;
+    use super::{split_page_content, Page};
 
     #[test_case("  # Title
     aaa"; "case0")]
@@ -82,11 +253,77 @@ mod tests {
     ## Subtitle
     aaa"; "case5")]
     fn test_parse_page_title(markdown: &str) {
-        let page = Page {
-            markdown: markdown.to_owned(),
-            file_path: PathBuf::new(),
-        };
+        let page = Page::new(markdown.to_owned(), PathBuf::new());
 
         assert_eq!("Title", page.title());
     }
+
+    #[test_case("# Title
+    aaa"; "No front matter")]
+    #[test_case("# Title
+    +++
+    title = \"Hello\"
+    +++"; "Not leading front matter")]
+    fn test_parse_front_matter_none(markdown: &str) {
+        let (content, meta) = split_page_content(markdown).unwrap();
+        assert_eq!(markdown, content);
+        assert!(meta.is_none());
+    }
+
+    #[test]
+    fn test_parse_front_matter_invalid() {
+        let markdown = r#"
++++
+title = 123
++++
+# Title
+"#;
+        assert!(split_page_content(markdown).is_err());
+    }
+
+    #[test]
+    fn test_parse_front_matter_empty() {
+        let markdown = r#"
++++
++++
+# Title
+"#;
+        let (content, meta) = split_page_content(markdown).unwrap();
+        assert_eq!("# Title", content);
+        let meta = meta.unwrap();
+        assert!(meta.title.is_none());
+        assert!(meta.publish);
+        assert!(meta.menu.is_none());
+    }
+
+    #[test]
+    fn test_parse_front_matter_full() {
+        let markdown = r#"
++++
+title = "About us"
+description = "About this magazine."
+slug = "about-us"
+cover = "/static/about.png"
+template = "templates/about.jinja"
+publish = false
+menu = 2
++++
+
+# About
+
+Hello
+"#;
+        let (content, meta) = split_page_content(markdown).unwrap();
+        assert_eq!("# About\n\nHello", content);
+
+        let mut page = Page::new(content.to_owned(), PathBuf::from("about.md"));
+        page.meta = meta.unwrap();
+        assert_eq!("About us", page.title());
+        assert_eq!("about-us", page.slug());
+        assert_eq!("About this magazine.", page.description());
+        assert_eq!(Some("/static/about.png"), page.meta.cover.as_deref());
+        assert_eq!(Some("templates/about.jinja"), page.meta.template.as_deref());
+        assert!(!page.meta.publish);
+        assert_eq!(Some(2), page.meta.menu);
+    }
 }
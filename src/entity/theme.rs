@@ -1,8 +1,10 @@
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, fs, path::Path};
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::code_blocks::is_builtin_code_block;
+
 use super::Entity;
 
 #[derive(Serialize, Deserialize)]
@@ -30,6 +32,10 @@ pub struct Theme {
     // The extend template path for article page, will be parsed to html.
     // Normally, this template can be a comment widget, such as https://giscus.app.
     pub article_extend_template: Option<String>,
+    // The user-defined code block templates in `templates/blocks` directory,
+    // the key is the code block name, the value is the template content.
+    #[serde(skip)]
+    pub code_block_templates: BTreeMap<String, String>,
 }
 
 impl Default for Theme {
@@ -43,6 +49,7 @@ impl Default for Theme {
             head_template: None,
             footer_template: None,
             article_extend_template: None,
+            code_block_templates: BTreeMap::new(),
         }
     }
 }
@@ -61,6 +68,10 @@ impl std::fmt::Debug for Theme {
                 "article_extend_template",
                 &self.article_extend_template.is_some(),
             )
+            .field(
+                "code_block_templates",
+                &self.code_block_templates.keys().collect::<Vec<_>>(),
+            )
             .finish()
     }
 }
@@ -70,6 +81,7 @@ impl Theme {
     const DEFAULT_MAIN_COLOR: &'static str = "#ffffff";
     const DEFAULT_LINK_COLOR: &'static str = "#2563eb";
     const DEFAULT_SECONDARY_COLOR: &'static str = "#eff3f7";
+    const CODE_BLOCK_TEMPLATE_DIR: &'static str = "templates/blocks";
 
     fn default_primary_color() -> String {
         Self::DEFAULT_PRIMARY_COLOR.to_string()
@@ -86,6 +98,40 @@ impl Theme {
     fn default_secondary_color() -> String {
         Self::DEFAULT_SECONDARY_COLOR.to_string()
     }
+
+    // Read all `*.jinja` templates in the `templates/blocks` directory as code block templates,
+    // the file stem is the code block name.
+    fn parse_code_block_templates(&mut self, source: &Path) -> Result<()> {
+        let block_dir = source.join(Self::CODE_BLOCK_TEMPLATE_DIR);
+        if !block_dir.is_dir() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&block_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("jinja") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                if is_builtin_code_block(name) {
+                    println!(
+                        "Warn: code block template `{}` is ignored, `{}` is a builtin code block.",
+                        path.display(),
+                        name
+                    );
+                    continue;
+                }
+                let template = fs::read_to_string(&path).with_context(|| {
+                    format!(
+                        "Failed to parse the code block template: `{}`",
+                        path.display()
+                    )
+                })?;
+                self.code_block_templates.insert(name.to_owned(), template);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Entity for Theme {
@@ -123,6 +169,7 @@ impl Entity for Theme {
                 })?,
             );
         }
+        self.parse_code_block_templates(source)?;
         Ok(())
     }
 }
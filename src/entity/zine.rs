@@ -5,16 +5,28 @@ use rayon::{
 };
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs,
     path::Path,
 };
 use tera::Context;
 use walkdir::WalkDir;
 
-use crate::{data, engine, error::ZineError, feed::FeedEntry, Entity};
+use crate::{
+    code_blocks::collect_preview_urls,
+    data, engine,
+    error::ZineError,
+    feed::FeedEntry,
+    helpers::{self, is_markdown_file},
+    link::LinkResolver,
+    plugin::{self, Plugin},
+    Entity,
+};
 
-use super::{Author, AuthorList, Issue, MarkdownConfig, MetaArticle, Page, Site, Theme};
+use super::{
+    Author, AuthorList, FetchConfig, ImageConfig, Issue, MarkdownConfig, Menu, MetaArticle, Page,
+    Site, Theme,
+};
 
 /// The root zine entity config.
 ///
@@ -35,6 +47,15 @@ pub struct Zine {
     #[serde(default)]
     #[serde(rename = "markdown")]
     pub markdown_config: MarkdownConfig,
+    #[serde(default)]
+    #[serde(rename = "fetch")]
+    pub fetch_config: FetchConfig,
+    #[serde(default)]
+    #[serde(rename = "image")]
+    pub image_config: ImageConfig,
+    #[serde(default)]
+    #[serde(rename = "plugin")]
+    pub plugins: Vec<Plugin>,
 }
 
 impl std::fmt::Debug for Zine {
@@ -118,8 +139,10 @@ impl Zine {
                         title: &article.meta.title,
                         url: format!("{}/{}/{}", self.site.url, issue.slug, article.slug()),
                         content: &article.markdown,
+                        path: format!("{}/{}", issue.path, article.file),
                         author: &article.meta.author,
                         date: &article.meta.pub_date,
+                        sanitize_html: article.sanitize_html(),
                     })
                     .collect::<Vec<_>>()
             })
@@ -161,14 +184,96 @@ impl Zine {
         entries.par_extend(
             self.pages
                 .par_iter()
+                .filter(|page| page.need_publish())
                 .map(|page| format!("{}/{}/", base_url, page.slug())),
         );
         entries
     }
+
+    /// Collect the URLs of all `urlpreview` code blocks in the markdown,
+    /// including the unpublished articles and pages.
+    pub fn url_preview_urls(&self) -> BTreeSet<String> {
+        let mut urls = BTreeSet::new();
+        for issue in &self.issues {
+            if let Some(intro) = issue.intro.as_ref() {
+                collect_preview_urls(intro, &mut urls);
+            }
+            for article in &issue.articles {
+                collect_preview_urls(&article.markdown, &mut urls);
+            }
+        }
+        for page in &self.pages {
+            collect_preview_urls(&page.markdown, &mut urls);
+        }
+        urls
+    }
+
+    // Build the link resolver to resolve links between markdown files.
+    fn link_resolver(&self, source: &Path) -> LinkResolver {
+        let mut resolver = LinkResolver::new(source);
+        for issue in &self.issues {
+            let issue_url = format!("/{}", issue.slug);
+            resolver.add_dir(&issue.path, &issue_url);
+            for article in issue.articles.iter().filter(|a| a.need_publish()) {
+                resolver.add_markdown(
+                    Path::new(&issue.path).join(&article.file),
+                    format!("{}/{}", issue_url, article.slug()),
+                );
+            }
+            resolver.add_url(issue_url);
+        }
+
+        resolver.add_dir("pages", "");
+        for page in self.pages.iter().filter(|page| page.need_publish()) {
+            resolver.add_markdown(
+                Path::new("pages").join(&page.file_path),
+                format!("/{}", page.slug()),
+            );
+        }
+
+        resolver.add_url("/authors");
+        for id in self.authors.keys() {
+            resolver.add_url(format!("/@{}", id.to_lowercase()));
+        }
+        resolver
+    }
+
+    // Register pages which declared a `menu` weight into the site menus.
+    // The page menus are appended after the menus declared in root `zine.toml`,
+    // sorted by weight in ascending order.
+    fn register_page_menus(&mut self) {
+        let mut page_menus = self
+            .pages
+            .iter()
+            .filter(|page| page.need_publish())
+            .filter_map(|page| {
+                page.meta.menu.map(|weight| {
+                    (
+                        weight,
+                        Menu {
+                            name: page.title(),
+                            url: format!("/{}", page.slug()),
+                        },
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        page_menus.sort_by_key(|(weight, _)| *weight);
+
+        for (_, menu) in page_menus {
+            // Avoid duplicating a menu which already declared.
+            if !self.site.menus.iter().any(|m| m.url == menu.url) {
+                self.site.menus.push(menu);
+            }
+        }
+    }
 }
 
 impl Entity for Zine {
     fn parse(&mut self, source: &Path) -> Result<()> {
+        helpers::set_user_agent(self.fetch_config.user_agent.as_deref());
+        plugin::set_plugins(source, self.plugins.clone());
+
         if self.authors.is_empty() {
             println!("Warn: no author specified in [authors] of root `zine.toml`.");
         } else {
@@ -193,14 +298,16 @@ impl Entity for Zine {
                 .try_fold_with(vec![], |mut pages, entry| {
                     let entry = entry?;
                     let path = entry.path();
-                    if path.is_file() {
+                    // Non-markdown files are assets, which would be copied
+                    // into the dest directory by the engine.
+                    if path.is_file() && is_markdown_file(path) {
                         let markdown = fs::read_to_string(path).with_context(|| {
                             format!("Failed to read markdown file of `{}`", path.display())
                         })?;
-                        pages.push(Page {
+                        pages.push(Page::new(
                             markdown,
-                            file_path: path.strip_prefix(&page_dir)?.to_owned(),
-                        });
+                            path.strip_prefix(&page_dir)?.to_owned(),
+                        ));
                     }
                     anyhow::Ok(pages)
                 })
@@ -210,13 +317,17 @@ impl Entity for Zine {
                 })
                 .transpose()?
                 .unwrap_or_default();
+            self.pages.parse(source)?;
+            self.register_page_menus();
         }
+        data::write().set_link_resolver(self.link_resolver(source));
         Ok(())
     }
 
     fn render(&self, mut context: Context, dest: &Path) -> Result<()> {
         context.insert("theme", &self.theme);
         context.insert("site", &self.site);
+        context.insert("highlight_css", &self.markdown_config.use_highlight_css());
 
         // Render all authors pages.
         let authors = self.authors();
@@ -1,3 +1,4 @@
+use hyper::StatusCode;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,3 +8,48 @@ pub enum ZineError {
     #[error("Not a root `zine.toml`, maybe it a `zine.toml` for issue?")]
     NotRootTomlFile,
 }
+
+/// The error of fetching an URL.
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("Invalid url `{0}`")]
+    InvalidUrl(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] hyper::http::Error),
+    #[error("Request failed: {0}")]
+    Request(#[from] hyper::Error),
+    #[error("Request timeout after {0}s")]
+    Timeout(u64),
+    #[error("Too many redirects, exceed the limit of {0}")]
+    TooManyRedirects(usize),
+    #[error("Redirect without the `Location` header")]
+    MissingLocation,
+    #[error("Unexpected HTTP status: {0}")]
+    Status(StatusCode),
+    #[error("Unsupported content type `{0}`, expect an HTML page")]
+    UnsupportedContentType(String),
+}
+
+/// The error of running a plugin.
+#[derive(Error, Debug)]
+pub enum PluginError {
+    #[error("Failed to run plugin `{name}` with command `{command}`: {source}")]
+    Spawn {
+        name: String,
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("Failed to communicate with plugin `{name}`: {source}")]
+    Io {
+        name: String,
+        source: std::io::Error,
+    },
+    #[error("Plugin `{name}` failed with {status}: {stderr}")]
+    Exit {
+        name: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("Plugin `{0}` returned non UTF-8 output")]
+    InvalidOutput(String),
+}
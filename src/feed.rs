@@ -8,7 +8,10 @@ pub struct FeedEntry<'a> {
     pub title: &'a String,
     pub url: String,
     pub content: &'a String,
+    /// The source path of the markdown file, relative to the root directory.
+    pub path: String,
     pub author: &'a Option<AuthorId>,
     #[serde(with = "crate::helpers::serde_date")]
     pub date: &'a Date,
+    pub sanitize_html: Option<bool>,
 }
@@ -1,11 +1,19 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use hyper::{
-    body::{self, Buf},
-    Client, Uri,
+    body::HttpBody, client::HttpConnector, header, Body, Client, Method, Request, StatusCode, Uri,
 };
 use hyper_tls::HttpsConnector;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use rayon::iter::{ParallelBridge, ParallelIterator};
-use std::{fs, io::Read, path::Path};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{error::FetchError, html};
 
 pub fn capitalize(text: &str) -> String {
     let mut chars = text.chars();
@@ -15,11 +23,157 @@ pub fn capitalize(text: &str) -> String {
     }
 }
 
-pub async fn fetch_url(url: &str) -> Result<impl Read> {
-    let client = Client::builder().build::<_, hyper::Body>(HttpsConnector::new());
-    let resp = client.get(url.parse::<Uri>()?).await?;
-    let bytes = body::to_bytes(resp.into_body()).await?;
-    Ok(bytes.reader())
+/// The max number of redirects to follow when fetching an URL.
+const MAX_REDIRECTS: usize = 5;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+/// The max body size to read. The meta info we need is located in the HTML head,
+/// the rest of a large page is simply dropped.
+const MAX_BODY_SIZE: usize = 2 * 1024 * 1024;
+const DEFAULT_USER_AGENT: &str = concat!(
+    "Mozilla/5.0 (compatible; zine/",
+    env!("CARGO_PKG_VERSION"),
+    "; +https://github.com/zineland/zine)"
+);
+
+// The shared HTTP client, reuse the connection pool between requests.
+static HTTP_CLIENT: Lazy<Client<HttpsConnector<HttpConnector>>> = Lazy::new(|| {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(Some(CONNECT_TIMEOUT));
+    Client::builder().build::<_, Body>(HttpsConnector::new_with_connector(http))
+});
+
+static USER_AGENT: Lazy<RwLock<String>> = Lazy::new(|| RwLock::new(DEFAULT_USER_AGENT.to_owned()));
+
+/// Set the `User-Agent` header of all requests, reset to default if `None`.
+pub fn set_user_agent(user_agent: Option<&str>) {
+    *USER_AGENT.write() = user_agent.unwrap_or(DEFAULT_USER_AGENT).to_owned();
+}
+
+fn user_agent() -> String {
+    USER_AGENT.read().clone()
+}
+
+/// The HTML page fetched by [`fetch_url`].
+#[derive(Debug)]
+pub struct FetchedPage {
+    /// The final URL after redirects.
+    pub url: String,
+    /// The HTML decoded from the charset of the page.
+    pub html: String,
+}
+
+/// Fetch the HTML page of `url`.
+///
+/// The redirects are followed up to [`MAX_REDIRECTS`] times,
+/// the body larger than [`MAX_BODY_SIZE`] is truncated.
+pub async fn fetch_url(url: &str) -> Result<FetchedPage, FetchError> {
+    let mut url = url.to_owned();
+    for _ in 0..=MAX_REDIRECTS {
+        let uri = url
+            .parse::<Uri>()
+            .map_err(|_| FetchError::InvalidUrl(url.clone()))?;
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::USER_AGENT, user_agent())
+            .header(header::ACCEPT, "text/html,application/xhtml+xml")
+            .body(Body::empty())?;
+        let resp = tokio::time::timeout(READ_TIMEOUT, HTTP_CLIENT.request(request))
+            .await
+            .map_err(|_| FetchError::Timeout(READ_TIMEOUT.as_secs()))??;
+
+        let status = resp.status();
+        if status.is_redirection() {
+            let location = resp
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or(FetchError::MissingLocation)?;
+            url = html::resolve_url(&url, location);
+            continue;
+        } else if !status.is_success() {
+            return Err(FetchError::Status(status));
+        }
+
+        let content_type = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned);
+        if let Some(content_type) = content_type.as_deref() {
+            let mime = content_type.split(';').next().unwrap_or_default().trim();
+            if !mime.eq_ignore_ascii_case("text/html")
+                && !mime.eq_ignore_ascii_case("application/xhtml+xml")
+            {
+                return Err(FetchError::UnsupportedContentType(mime.to_owned()));
+            }
+        }
+
+        let bytes = tokio::time::timeout(READ_TIMEOUT, read_body(resp.into_body()))
+            .await
+            .map_err(|_| FetchError::Timeout(READ_TIMEOUT.as_secs()))??;
+        return Ok(FetchedPage {
+            html: html::decode_html(&bytes, content_type.as_deref()),
+            url,
+        });
+    }
+    Err(FetchError::TooManyRedirects(MAX_REDIRECTS))
+}
+
+// Read the body up to `MAX_BODY_SIZE` bytes.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, hyper::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let remaining = MAX_BODY_SIZE - bytes.len();
+        bytes.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        if bytes.len() >= MAX_BODY_SIZE {
+            break;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Request the `url` with `method`, return the status code and URL of the final response.
+///
+/// The redirects are followed up to [`MAX_REDIRECTS`] times,
+/// the response body is ignored, return error if timeout.
+pub async fn fetch_status(
+    method: Method,
+    url: &str,
+    timeout: Duration,
+) -> Result<(StatusCode, String)> {
+    let mut url = url.to_owned();
+    for _ in 0..=MAX_REDIRECTS {
+        let request = Request::builder()
+            .method(method.clone())
+            .uri(url.parse::<Uri>()?)
+            .header(header::USER_AGENT, user_agent())
+            .body(Body::empty())?;
+        let resp = tokio::time::timeout(timeout, HTTP_CLIENT.request(request))
+            .await
+            .map_err(|_| anyhow!("request timeout after {}s", timeout.as_secs_f32()))??;
+
+        let status = resp.status();
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+        match location {
+            Some(location) if status.is_redirection() => url = html::resolve_url(&url, location),
+            _ => return Ok((status, url)),
+        }
+    }
+    Err(FetchError::TooManyRedirects(MAX_REDIRECTS).into())
+}
+
+/// The FNV-1a hash of the content, which is stable across builds and toolchains,
+/// unlike the `DefaultHasher`.
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 /// Copy directory recursively.
@@ -54,6 +208,63 @@ pub fn copy_dir(source: &Path, dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Copy asset files in `source` directory into `dest` directory recursively,
+/// keep the same relative path.
+///
+/// Note: markdown files, `zine.toml` and hidden files are ignored.
+pub fn copy_assets(source: &Path, dest: &Path) -> Result<()> {
+    walkdir::WalkDir::new(source)
+        .into_iter()
+        .par_bridge()
+        .try_for_each(|entry| {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_file() && is_asset_file(path) {
+                let to = dest.join(path.strip_prefix(source)?);
+                if let Some(parent) = to.parent() {
+                    if !parent.exists() {
+                        // Create the same dir concurrently is ok according to the docs.
+                        fs::create_dir_all(parent)?;
+                    }
+                }
+                fs::copy(path, to)?;
+            }
+
+            anyhow::Ok(())
+        })?;
+    Ok(())
+}
+
+/// Check whether the `path` is a markdown file.
+pub fn is_markdown_file(path: &Path) -> bool {
+    path.extension().and_then(OsStr::to_str) == Some("md")
+}
+
+// Check whether the `path` is an asset file, such as images, PDFs.
+fn is_asset_file(path: &Path) -> bool {
+    let is_hidden = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .map(|name| name.starts_with('.'))
+        .unwrap_or_default();
+    !is_hidden && !is_markdown_file(path) && !path.ends_with(crate::ZINE_FILE)
+}
+
+/// Normalize the `path` lexically, resolve the `.` and `..` components.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
 /// A serde module to serialize and deserialize [`time::Date`] type.
 pub mod serde_date {
     use serde::{de, Serialize, Serializer};
@@ -93,3 +304,152 @@ pub mod serde_date {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        env, fs,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+    };
+
+    use hyper::{
+        header,
+        service::{make_service_fn, service_fn},
+        Body, Response, Server, StatusCode,
+    };
+
+    use test_case::test_case;
+
+    use super::{content_hash, copy_assets, fetch_url, is_asset_file, is_markdown_file};
+    use crate::error::FetchError;
+
+    // Spawn a local stand-in HTTP server, return the listening address.
+    fn spawn_server() -> SocketAddr {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|req| async move {
+                let resp = Response::builder();
+                let resp = match req.uri().path() {
+                    "/page" => resp
+                        .header(header::CONTENT_TYPE, "text/html; charset=gbk")
+                        .body(Body::from(
+                            encoding_rs::GBK
+                                .encode("<title>你好</title>")
+                                .0
+                                .into_owned(),
+                        )),
+                    "/moved" => resp
+                        .status(StatusCode::MOVED_PERMANENTLY)
+                        .header(header::LOCATION, "/page")
+                        .body(Body::empty()),
+                    "/loop" => resp
+                        .status(StatusCode::FOUND)
+                        .header(header::LOCATION, "/loop")
+                        .body(Body::empty()),
+                    "/image" => resp
+                        .header(header::CONTENT_TYPE, "image/png")
+                        .body(Body::empty()),
+                    _ => resp.status(StatusCode::NOT_FOUND).body(Body::empty()),
+                };
+                Ok::<_, Infallible>(resp.unwrap())
+            }))
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_fetch_url() {
+        let addr = spawn_server();
+        let url = |path: &str| format!("http://{}{}", addr, path);
+
+        let page = fetch_url(&url("/moved")).await.unwrap();
+        assert_eq!(url("/page"), page.url);
+        assert_eq!("<title>你好</title>", page.html);
+
+        assert!(matches!(
+            fetch_url(&url("/loop")).await,
+            Err(FetchError::TooManyRedirects(_))
+        ));
+        assert!(matches!(
+            fetch_url(&url("/image")).await,
+            Err(FetchError::UnsupportedContentType(mime)) if mime == "image/png"
+        ));
+        assert!(matches!(
+            fetch_url(&url("/missing")).await,
+            Err(FetchError::Status(StatusCode::NOT_FOUND))
+        ));
+        assert!(matches!(
+            fetch_url("not a url").await,
+            Err(FetchError::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(content_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(content_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(content_hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test_case("figure.png", true; "image")]
+    #[test_case("docs/paper.pdf", true; "nested pdf")]
+    #[test_case("Makefile", true; "without extension")]
+    #[test_case("first.md", false; "markdown")]
+    #[test_case(".DS_Store", false; "hidden")]
+    #[test_case("content/zine.toml", false; "zine file")]
+    fn test_is_asset_file(path: &str, expected: bool) {
+        assert_eq!(is_asset_file(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn test_is_markdown_file() {
+        assert!(is_markdown_file(Path::new("issue-1/first.md")));
+        assert!(!is_markdown_file(Path::new("issue-1/first.markdown")));
+        assert!(!is_markdown_file(Path::new("issue-1/md")));
+    }
+
+    #[test]
+    fn test_copy_assets() {
+        let root = env::temp_dir().join("__zine_copy_assets");
+        let _ = fs::remove_dir_all(&root);
+        let (source, dest) = (root.join("source"), root.join("dest"));
+        for file in [
+            "first.md",
+            "figure.png",
+            "images/nested/photo.jpg",
+            "images/second.md",
+            ".hidden",
+            "zine.toml",
+        ] {
+            let path = source.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+
+        copy_assets(&source, &dest).unwrap();
+        let mut copied = walkdir::WalkDir::new(&dest)
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.path().strip_prefix(&dest).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        copied.sort();
+        assert_eq!(
+            copied,
+            vec![
+                PathBuf::from("figure.png"),
+                PathBuf::from("images/nested/photo.jpg")
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dest.join("images/nested/photo.jpg")).unwrap(),
+            "images/nested/photo.jpg"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
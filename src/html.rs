@@ -1,13 +1,303 @@
 use anyhow::Result;
-use std::{borrow::Cow, io::Read};
+use std::{borrow::Cow, cell::RefCell, collections::HashSet, io::Read};
 
+use encoding_rs::{Encoding, UTF_8};
 use html5ever::{
     parse_document, tendril::TendrilSink, tree_builder::TreeBuilderOpts, Attribute, ParseOpts,
 };
-use lol_html::{element, html_content::Element, HtmlRewriter, Settings};
+use hyper::Uri;
+use lol_html::{
+    doc_comments, element,
+    html_content::{ContentType, Element},
+    HtmlRewriter, Settings,
+};
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{data::UrlPreviewInfo, image::ResponsiveImage};
+
+/// Escape the special characters of `text`, which is safe to
+/// put in both HTML text and double or single quoted attribute value.
+pub fn escape_html(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 16);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Escape the `url` to put in the `href` or `src` attribute.
+///
+/// Return `None` if the URL has a dangerous scheme, such as `javascript:`.
+/// Only `http`, `https`, `mailto`, `data:image/` and relative URLs are allowed.
+pub fn escape_url(url: &str) -> Option<Cow<'_, str>> {
+    let url = url.trim();
+    let scheme = url
+        .find([':', '/', '?', '#'])
+        .filter(|&index| url[index..].starts_with(':'))
+        .map(|index| url[..index].to_ascii_lowercase());
+    match scheme.as_deref() {
+        None | Some("http" | "https" | "mailto") => Some(escape_html(url)),
+        Some("data") if url[5..].trim_start().starts_with("image/") => Some(escape_html(url)),
+        _ => None,
+    }
+}
+
+/// The tags allowed in the sanitized HTML.
+const ALLOWED_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "u",
+    "ul",
+    "var",
+    "wbr",
+];
+/// The tags removed along with their content in the sanitized HTML,
+/// other disallowed tags are unwrapped to keep the content.
+const REMOVED_TAGS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet", "noscript",
+    "noembed", "template", "textarea", "select", "title", "xmp", "svg", "math",
+];
+/// The attributes allowed on all tags.
+const ALLOWED_ATTRIBUTES: &[&str] = &["class", "id", "title", "lang", "dir"];
+/// The attributes allowed on specific tags.
+const ALLOWED_TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "name", "rel", "target"]),
+    ("img", &["src", "alt", "width", "height", "loading"]),
+    ("ol", &["start", "type", "reversed"]),
+    ("li", &["value"]),
+    ("td", &["colspan", "rowspan", "align"]),
+    ("th", &["colspan", "rowspan", "align", "scope"]),
+    ("col", &["span"]),
+    ("colgroup", &["span"]),
+    ("time", &["datetime"]),
+    ("details", &["open"]),
+    ("del", &["cite", "datetime"]),
+    ("ins", &["cite", "datetime"]),
+    ("blockquote", &["cite"]),
+    ("q", &["cite"]),
+];
+
+/// The tag of the trusted HTML placeholder, see [`sanitize_html_with`].
+const TRUSTED_HTML_TAG: &str = "zine-trusted";
+// The only inline style allowed, which is the table cell alignment of markdown.
+static TEXT_ALIGN_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^text-align: (left|center|right)$").unwrap());
+
+/// The placeholder of the `index`th trusted HTML, see [`sanitize_html_with`].
+pub fn trusted_html_placeholder(index: usize) -> String {
+    format!(
+        "<{} index=\"{}\"></{}>",
+        TRUSTED_HTML_TAG, index, TRUSTED_HTML_TAG
+    )
+}
+
+/// Sanitize the `raw_html` against the allowlist of tags and attributes.
+///
+/// The scripts, iframes and other dangerous elements are removed along with their content,
+/// the event handlers, inline styles and URLs with dangerous scheme are removed too.
+pub fn sanitize_html(raw_html: &str) -> Result<String> {
+    sanitize_html_with(raw_html, &[])
+}
+
+/// Sanitize the `raw_html` like [`sanitize_html`], the placeholders of [`trusted_html_placeholder`]
+/// are replaced with the `trusted` HTML as is, such as the rendered code blocks.
+///
+/// The placeholders are only replaced as elements, the ones inside attribute values or
+/// the removed elements are never replaced.
+pub fn sanitize_html_with(raw_html: &str, trusted: &[String]) -> Result<String> {
+    let mut html = vec![];
+    let mut html_rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!("*", |el| {
+                let tag_name = el.tag_name().to_ascii_lowercase();
+                if tag_name == TRUSTED_HTML_TAG {
+                    match el
+                        .get_attribute("index")
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .and_then(|index| trusted.get(index))
+                    {
+                        Some(trusted) => el.replace(trusted, ContentType::Html),
+                        None => el.remove(),
+                    }
+                    return Ok(());
+                } else if tag_name == "input" {
+                    // The task list checkbox of markdown.
+                    if el.get_attribute("type").as_deref() == Some("checkbox") {
+                        let checked = el.has_attribute("checked");
+                        el.replace(
+                            &format!(
+                                "<input disabled=\"\" type=\"checkbox\"{}/>",
+                                if checked { " checked=\"\"" } else { "" }
+                            ),
+                            ContentType::Html,
+                        );
+                    } else {
+                        el.remove();
+                    }
+                    return Ok(());
+                } else if REMOVED_TAGS.contains(&tag_name.as_str()) {
+                    el.remove();
+                    return Ok(());
+                } else if !ALLOWED_TAGS.contains(&tag_name.as_str()) {
+                    el.remove_and_keep_content();
+                    return Ok(());
+                }
 
-use crate::meta::Meta;
+                let tag_attributes = ALLOWED_TAG_ATTRIBUTES
+                    .iter()
+                    .find_map(|(tag, attributes)| (*tag == tag_name).then_some(*attributes))
+                    .unwrap_or_default();
+                let disallowed_attributes = el
+                    .attributes()
+                    .iter()
+                    .filter(|attr| {
+                        let name = attr.name();
+                        let allowed = ALLOWED_ATTRIBUTES.contains(&name.as_str())
+                            || tag_attributes.contains(&name.as_str());
+                        let is_url = matches!(name.as_str(), "href" | "src" | "cite");
+                        let is_text_align = name == "style"
+                            && matches!(tag_name.as_str(), "td" | "th")
+                            && TEXT_ALIGN_REGEX.is_match(&attr.value());
+                        !(allowed || is_text_align)
+                            || (is_url && escape_url(&attr.value()).is_none())
+                    })
+                    .map(|attr| attr.name())
+                    .collect::<Vec<_>>();
+                for name in disallowed_attributes {
+                    el.remove_attribute(&name);
+                }
+                Ok(())
+            })],
+            document_content_handlers: vec![doc_comments!(|comment| {
+                comment.remove();
+                Ok(())
+            })],
+            ..Default::default()
+        },
+        |c: &[u8]| {
+            html.extend_from_slice(c);
+        },
+    );
+    html_rewriter.write(raw_html.as_bytes())?;
+    html_rewriter.end()?;
+
+    Ok(String::from_utf8(html)?)
+}
+
+/// Split the highlighted HTML of code into lines. The `<span>` tags across lines
+/// are closed at the end of the line and reopened at the start of the next line,
+/// so that every line is a well-formed HTML fragment.
+pub fn split_highlighted_lines(html: &str) -> Vec<String> {
+    let mut open_tags: Vec<&str> = vec![];
+    let mut lines = vec![];
+    for segment in html.split('\n') {
+        let mut line = open_tags.concat();
+        line.push_str(segment);
+
+        let mut has_text = false;
+        let mut rest = segment;
+        while !rest.is_empty() {
+            match rest.find('<') {
+                Some(0) => {
+                    let end = match rest.find('>') {
+                        Some(end) => end + 1,
+                        None => break,
+                    };
+                    if rest.starts_with("</") {
+                        open_tags.pop();
+                    } else {
+                        open_tags.push(&rest[..end]);
+                    }
+                    rest = &rest[end..];
+                }
+                Some(start) => {
+                    has_text = true;
+                    rest = &rest[start..];
+                }
+                None => {
+                    has_text = true;
+                    break;
+                }
+            }
+        }
+
+        line.push_str(&"</span>".repeat(open_tags.len()));
+        lines.push((line, has_text));
+    }
+    // The trailing segment only closes the tags after the last line ending.
+    if matches!(lines.last(), Some((_, false))) {
+        lines.pop();
+    }
+    lines.into_iter().map(|(line, _)| line).collect()
+}
 
 /// Rewrite root path URL in `raw_html` with `base_url`.
 pub fn rewrite_html_base_url(raw_html: &[u8], base_url: &str) -> Result<Vec<u8>> {
@@ -35,6 +325,13 @@ pub fn rewrite_html_base_url(raw_html: &[u8], base_url: &str) -> Result<Vec<u8>>
                         Ok(())
                     }
                 ),
+                element!("img[srcset], source[srcset]", |el| {
+                    if let Some(srcset) = el.get_attribute("srcset") {
+                        el.set_attribute("srcset", &rewrite_srcset_base_url(&srcset, base_url))
+                            .expect("Set attribute failed");
+                    }
+                    Ok(())
+                }),
                 // Rewrite background image url.
                 element!("body>div.bg-primary.text-main", |el| {
                     if let Some(attr) = el.get_attribute("style") {
@@ -63,8 +360,170 @@ pub fn rewrite_html_base_url(raw_html: &[u8], base_url: &str) -> Result<Vec<u8>>
     Ok(html)
 }
 
-/// Parse HTML [`Meta`] from `html`.
-pub fn parse_html_meta<'a, R: Read>(mut html: R) -> Meta<'a> {
+// Rewrite the root path URLs of image candidates in `srcset`.
+fn rewrite_srcset_base_url(srcset: &str, base_url: &str) -> String {
+    srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            if candidate.starts_with('/') {
+                format!("{}{}", base_url, candidate)
+            } else {
+                candidate.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rewrite the `<img>` with root path `src` into responsive image,
+/// `responsive` returns the `srcset` of the image if it's a processed local image.
+/// The image with WebP version is wrapped into `<picture>`.
+///
+/// The images with `srcset` already are kept as is.
+pub fn rewrite_html_images(
+    raw_html: &[u8],
+    sizes: &str,
+    responsive: impl Fn(&str) -> Option<ResponsiveImage>,
+) -> Result<Vec<u8>> {
+    let mut html = vec![];
+    let mut html_rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!("img[src]", |el| {
+                let src = el.get_attribute("src").unwrap_or_default();
+                if el.has_attribute("srcset") || !src.starts_with('/') || src.starts_with("//") {
+                    return Ok(());
+                }
+                if let Some(image) = responsive(&src) {
+                    let sizes = el
+                        .get_attribute("sizes")
+                        .unwrap_or_else(|| sizes.to_owned());
+                    el.set_attribute("srcset", &image.srcset)?;
+                    el.set_attribute("sizes", &sizes)?;
+                    if let Some(webp_srcset) = image.webp_srcset {
+                        el.before(
+                            &format!(
+                                r#"<picture><source type="image/webp" srcset="{}" sizes="{}">"#,
+                                escape_html(&webp_srcset),
+                                escape_html(&sizes)
+                            ),
+                            ContentType::Html,
+                        );
+                        el.after("</picture>", ContentType::Html);
+                    }
+                }
+                Ok(())
+            })],
+            ..Default::default()
+        },
+        |c: &[u8]| {
+            html.extend_from_slice(c);
+        },
+    );
+    html_rewriter.write(raw_html)?;
+    html_rewriter.end()?;
+
+    Ok(html)
+}
+
+/// The links and element ids of an HTML page.
+#[derive(Debug, Default)]
+pub struct HtmlLinks {
+    /// The `href` or `src` attribute values, in document order.
+    pub links: Vec<String>,
+    /// The element ids, which are the targets of anchor links.
+    pub ids: HashSet<String>,
+}
+
+/// Parse all links and element ids from `raw_html`.
+pub fn parse_html_links(raw_html: &[u8]) -> Result<HtmlLinks> {
+    let links = RefCell::new(Vec::new());
+    let ids = RefCell::new(HashSet::new());
+    let collect_attr = |el: &mut Element, attr_name: &str| {
+        if let Some(attr) = el.get_attribute(attr_name) {
+            links.borrow_mut().push(attr);
+        }
+    };
+
+    let mut html_rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("a[href], link[rel=stylesheet][href]", |el| {
+                    collect_attr(el, "href");
+                    Ok(())
+                }),
+                element!(
+                    "script[src], iframe[src], img[src], audio[src], video[src], source[src]",
+                    |el| {
+                        collect_attr(el, "src");
+                        Ok(())
+                    }
+                ),
+                element!("[id]", |el| {
+                    if let Some(id) = el.get_attribute("id") {
+                        ids.borrow_mut().insert(id);
+                    }
+                    Ok(())
+                }),
+                element!("a[name]", |el| {
+                    if let Some(name) = el.get_attribute("name") {
+                        ids.borrow_mut().insert(name);
+                    }
+                    Ok(())
+                }),
+            ],
+            ..Default::default()
+        },
+        |_: &[u8]| {},
+    );
+    html_rewriter.write(raw_html)?;
+    html_rewriter.end()?;
+
+    Ok(HtmlLinks {
+        links: links.into_inner(),
+        ids: ids.into_inner(),
+    })
+}
+
+// The charset declared in `Content-Type` header, e.g. `text/html; charset=gbk`.
+static CONTENT_TYPE_CHARSET_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)charset\s*=\s*["']?([\w:.-]+)"#).unwrap());
+// The charset declared in `<meta charset="gbk">` or
+// `<meta http-equiv="Content-Type" content="text/html; charset=gbk">`.
+static META_CHARSET_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([\w:.-]+)"#).unwrap());
+/// The number of leading bytes to prescan the `<meta charset>`, according to the HTML spec.
+const CHARSET_PRESCAN_SIZE: usize = 1024;
+
+/// Decode the HTML `bytes` into string.
+///
+/// The encoding is detected from the BOM, the charset of `content_type`
+/// or the `<meta charset>` tag in order, fallback to UTF-8.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>) -> String {
+    let label_of = |regex: &Regex, text: &str| {
+        regex
+            .captures(text)
+            .and_then(|caps| Encoding::for_label(caps[1].as_bytes()))
+    };
+    let encoding = content_type
+        .and_then(|content_type| label_of(&CONTENT_TYPE_CHARSET_REGEX, content_type))
+        .or_else(|| {
+            let head = &bytes[..bytes.len().min(CHARSET_PRESCAN_SIZE)];
+            label_of(&META_CHARSET_REGEX, &String::from_utf8_lossy(head))
+        })
+        .unwrap_or(UTF_8);
+    // The BOM takes precedence over the detected encoding.
+    let (html, _, _) = encoding.decode(bytes);
+    html.into_owned()
+}
+
+/// The max length of the title and description of the URL preview.
+const MAX_PREVIEW_TEXT_LENGTH: usize = 200;
+
+/// Parse [`UrlPreviewInfo`] from `html` of the page `url`.
+///
+/// The relative URLs of image, favicon and canonical URL are resolved against `url`.
+pub fn parse_html_meta<R: Read>(url: &str, mut html: R) -> UrlPreviewInfo {
     let parse_opts = ParseOpts {
         tree_builder: TreeBuilderOpts {
             scripting_enabled: false,
@@ -79,14 +538,38 @@ pub fn parse_html_meta<'a, R: Read>(mut html: R) -> Meta<'a> {
         .read_from(&mut html)
         .unwrap();
 
-    let mut meta = Meta::default();
+    let mut meta = HtmlMeta::default();
     walk(&rc_dom.document, &mut meta);
-    meta.truncate();
-    meta
+
+    let truncate = |text: String| text.chars().take(MAX_PREVIEW_TEXT_LENGTH).collect();
+    let resolve = |link: Option<String>| link.map(|link| resolve_url(url, &link));
+    UrlPreviewInfo {
+        title: truncate(meta.og_title.or(meta.title).unwrap_or_default()),
+        description: truncate(meta.description.unwrap_or_default()),
+        image: resolve(meta.image),
+        site_name: meta.site_name,
+        favicon: resolve(meta.favicon),
+        canonical_url: resolve(meta.canonical_url.or(meta.og_url)),
+        fetched_at: None,
+    }
+}
+
+// The meta info collected from the HTML head.
+#[derive(Debug, Default)]
+struct HtmlMeta {
+    // The text of <title> tag.
+    title: Option<String>,
+    og_title: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    site_name: Option<String>,
+    favicon: Option<String>,
+    canonical_url: Option<String>,
+    og_url: Option<String>,
 }
 
-// Walk html tree to parse [`Meta`].
-fn walk(handle: &Handle, meta: &mut Meta) {
+// Walk html tree to parse [`HtmlMeta`].
+fn walk(handle: &Handle, meta: &mut HtmlMeta) {
     fn get_attribute<'a>(attrs: &'a [Attribute], name: &'a str) -> Option<&'a str> {
         attrs.iter().find_map(|attr| {
             if attr.name.local.as_ref() == name {
@@ -104,6 +587,13 @@ fn walk(handle: &Handle, meta: &mut Meta) {
         })
     }
 
+    // Set the field with `value` if the field is empty.
+    fn fill(field: &mut Option<String>, value: Option<&str>) {
+        if field.is_none() {
+            *field = value.map(|value| value.trim().to_owned());
+        }
+    }
+
     if let NodeData::Element {
         ref name,
         ref attrs,
@@ -111,29 +601,39 @@ fn walk(handle: &Handle, meta: &mut Meta) {
     } = handle.data
     {
         match name.local.as_ref() {
+            // All meta info we need is located in <head>.
+            "body" => return,
             "meta" => {
                 // <meta name="description" content="xxx"/>
                 // get description value from attribute.
                 let attrs = attrs.borrow();
-                match get_attribute(&*attrs, "name").or_else(|| get_attribute(&*attrs, "property"))
-                {
-                    Some("description" | "og:description" | "twitter:description")
-                        if meta.description.is_empty() =>
-                    {
-                        if let Some(description) = get_attribute(&*attrs, "content") {
-                            meta.description = Cow::Owned(description.trim().to_owned());
-                        }
+                let content = get_attribute(&attrs, "content");
+                match get_attribute(&attrs, "name").or_else(|| get_attribute(&attrs, "property")) {
+                    Some("description" | "og:description" | "twitter:description") => {
+                        fill(&mut meta.description, content);
                     }
-                    Some("og:title" | "twitter:title") if meta.title.is_empty() => {
-                        if let Some(title) = get_attribute(&*attrs, "content") {
-                            meta.title = Cow::Owned(title.trim().to_owned());
-                        }
+                    Some("og:title" | "twitter:title") => fill(&mut meta.og_title, content),
+                    Some("og:image" | "og:image:url" | "twitter:image") => {
+                        fill(&mut meta.image, content);
                     }
+                    Some("og:site_name") => fill(&mut meta.site_name, content),
+                    Some("og:url") => fill(&mut meta.og_url, content),
                     _ => {}
                 }
             }
             "link" => {
-                // TODO: Extract favicon from <link> tag
+                // <link rel="icon" href="/favicon.ico">
+                // <link rel="canonical" href="https://example.com/page">
+                let attrs = attrs.borrow();
+                let href = get_attribute(&attrs, "href");
+                let rel = get_attribute(&attrs, "rel").unwrap_or_default();
+                for rel in rel.split_ascii_whitespace() {
+                    match rel.to_ascii_lowercase().as_str() {
+                        "icon" | "apple-touch-icon" => fill(&mut meta.favicon, href),
+                        "canonical" => fill(&mut meta.canonical_url, href),
+                        _ => {}
+                    }
+                }
             }
             "title" => {
                 // Extract <title> tag.
@@ -156,7 +656,9 @@ fn walk(handle: &Handle, meta: &mut Meta) {
                         _ => None,
                     })
                     .collect::<String>();
-                meta.title = Cow::Owned(title.trim().to_owned());
+                if !title.trim().is_empty() {
+                    fill(&mut meta.title, Some(&title));
+                }
             }
             _ => {}
         }
@@ -164,21 +666,64 @@ fn walk(handle: &Handle, meta: &mut Meta) {
     let children = handle.children.borrow();
     for child in children.iter() {
         walk(child, meta);
+    }
+}
 
-        // If meta is filled, no need to walk.
-        if meta.is_filled() {
-            break;
-        }
+/// Resolve the `link` against the `base` URL into an absolute URL.
+pub fn resolve_url(base: &str, link: &str) -> String {
+    if link.starts_with("http://") || link.starts_with("https://") || link.starts_with("data:") {
+        return link.to_owned();
+    }
+
+    let base = match base.parse::<Uri>() {
+        Ok(base) => base,
+        Err(_) => return link.to_owned(),
+    };
+    let (scheme, authority) = match (base.scheme_str(), base.authority()) {
+        (Some(scheme), Some(authority)) => (scheme, authority.as_str()),
+        _ => return link.to_owned(),
+    };
+
+    if let Some(link) = link.strip_prefix("//") {
+        format!("{}://{}", scheme, link)
+    } else if link.starts_with('/') {
+        format!("{}://{}{}", scheme, authority, link)
+    } else {
+        // Relative to the directory of base path.
+        let path = base.path();
+        let dir = &path[..path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+        let dir = if dir.is_empty() { "/" } else { dir };
+        format!("{}://{}{}{}", scheme, authority, dir, link)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::rewrite_html_base_url;
+    use super::{
+        decode_html, escape_html, escape_url, parse_html_links, parse_html_meta, resolve_url,
+        rewrite_html_base_url, rewrite_html_images, sanitize_html, sanitize_html_with,
+        split_highlighted_lines, trusted_html_placeholder,
+    };
+    use crate::image::ResponsiveImage;
     use test_case::test_case;
 
     const BASE_URL: &str = "https://github.com";
 
+    #[test]
+    fn test_split_highlighted_lines() {
+        let html = "<span class=\"a\">fn main() {\n  <span class=\"b\">1</span>\n\n}\n</span>";
+        assert_eq!(
+            split_highlighted_lines(html),
+            vec![
+                "<span class=\"a\">fn main() {</span>",
+                "<span class=\"a\">  <span class=\"b\">1</span></span>",
+                "<span class=\"a\"></span>",
+                "<span class=\"a\">}</span>",
+            ]
+        );
+        assert_eq!(split_highlighted_lines("a\nb"), vec!["a", "b"]);
+    }
+
     #[test_case(
         r#"
         <body class="h-full bg-secondary">
@@ -246,4 +791,213 @@ mod tests {
             html.replace("{}", &path)
         );
     }
+
+    #[test]
+    fn test_rewrite_srcset_base_url() {
+        let html = r#"<img srcset="/a-480.png 480w, /a.png 960w"><source srcset="a.webp, https://example.com/b.webp 2x">"#;
+        assert_eq!(
+            String::from_utf8_lossy(&rewrite_html_base_url(html.as_bytes(), BASE_URL).unwrap()),
+            r#"<img srcset="https://github.com/a-480.png 480w, https://github.com/a.png 960w"><source srcset="a.webp, https://example.com/b.webp 2x">"#
+        );
+    }
+
+    #[test]
+    fn test_rewrite_html_images() {
+        let html = r#"<img src="/a.png" alt="A"><img src="/b.png" sizes="50vw"><img src="/c.png" srcset="/c@2x.png 2x"><img src="https://example.com/a.png">"#;
+        let responsive = |src: &str| match src {
+            "/a.png" => Some(ResponsiveImage {
+                srcset: String::from("/a-480.png 480w, /a.png 960w"),
+                webp_srcset: Some(String::from("/a-480.webp 480w, /a-960.webp 960w")),
+            }),
+            "/b.png" => Some(ResponsiveImage {
+                srcset: String::from("/b-480.png 480w, /b.png 960w"),
+                webp_srcset: None,
+            }),
+            _ => Some(ResponsiveImage {
+                srcset: String::from("/unexpected.png 1w"),
+                webp_srcset: None,
+            }),
+        };
+        assert_eq!(
+            String::from_utf8_lossy(
+                &rewrite_html_images(html.as_bytes(), "100vw", responsive).unwrap()
+            ),
+            concat!(
+                r#"<picture><source type="image/webp" srcset="/a-480.webp 480w, /a-960.webp 960w" sizes="100vw">"#,
+                r#"<img src="/a.png" alt="A" srcset="/a-480.png 480w, /a.png 960w" sizes="100vw"></picture>"#,
+                r#"<img src="/b.png" sizes="50vw" srcset="/b-480.png 480w, /b.png 960w">"#,
+                r#"<img src="/c.png" srcset="/c@2x.png 2x"><img src="https://example.com/a.png">"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_html_links() {
+        let html = r##"
+        <link rel="stylesheet" href="/static/zine.css">
+        <link rel="icon" href="/favicon.ico">
+        <h2 id="title"><a href="#title">Title</a></h2>
+        <a name="legacy"></a>
+        <p><a href="/cloud/first">First</a> <img src="./figure.png"></p>
+        <video><source src="/static/demo.mp4"></video>
+        <script src="/static/zine.js"></script>
+        "##;
+        let html_links = parse_html_links(html.as_bytes()).unwrap();
+        assert_eq!(
+            vec![
+                "/static/zine.css",
+                "#title",
+                "/cloud/first",
+                "./figure.png",
+                "/static/demo.mp4",
+                "/static/zine.js"
+            ],
+            html_links.links
+        );
+        assert_eq!(2, html_links.ids.len());
+        assert!(html_links.ids.contains("title"));
+        assert!(html_links.ids.contains("legacy"));
+    }
+
+    #[test]
+    fn test_parse_html_meta() {
+        let html = r#"
+        <html>
+        <head>
+            <title>
+                Zine
+            </title>
+            <meta property="og:title" content="zineland/zine">
+            <meta property="og:description" content="">
+            <meta name="description" content="A simple and opinionated tool.">
+            <meta property="og:image" content="/cover.png">
+            <meta property="og:site_name" content="GitHub">
+            <meta property="og:url" content="https://github.com/zineland/zine/">
+            <link rel="shortcut icon" href="//github.githubassets.com/favicon.ico">
+            <link rel="canonical" href="https://github.com/zineland/zine">
+        </head>
+        <body><meta name="description" content="Ignored"></body>
+        </html>
+        "#;
+        let info = parse_html_meta("https://github.com/zineland/zine", html.as_bytes());
+        assert_eq!("zineland/zine", info.title);
+        assert_eq!("A simple and opinionated tool.", info.description);
+        assert_eq!(Some("https://github.com/cover.png"), info.image.as_deref());
+        assert_eq!(Some("GitHub"), info.site_name.as_deref());
+        assert_eq!(
+            Some("https://github.githubassets.com/favicon.ico"),
+            info.favicon.as_deref()
+        );
+        assert_eq!(
+            Some("https://github.com/zineland/zine"),
+            info.canonical_url.as_deref()
+        );
+    }
+
+    #[test]
+    fn test_parse_html_meta_fallback() {
+        let long_title = "长".repeat(300);
+        let html = format!("<title>{}</title><p>Hello</p>", long_title);
+        let info = parse_html_meta("https://example.com", html.as_bytes());
+        assert_eq!(200, info.title.chars().count());
+        assert!(info.description.is_empty());
+        assert!(info.image.is_none());
+        assert!(info.favicon.is_none());
+        assert!(info.canonical_url.is_none());
+    }
+
+    #[test_case("https://a.com/b/c", "https://d.com/e.png", "https://d.com/e.png"; "absolute")]
+    #[test_case("https://a.com/b/c", "//d.com/e.png", "https://d.com/e.png"; "scheme relative")]
+    #[test_case("https://a.com/b/c", "/e.png", "https://a.com/e.png"; "root relative")]
+    #[test_case("https://a.com/b/c", "e.png", "https://a.com/b/e.png"; "path relative")]
+    #[test_case("https://a.com", "e.png", "https://a.com/e.png"; "no path")]
+    fn test_resolve_url(base: &str, link: &str, expected: &str) {
+        assert_eq!(expected, resolve_url(base, link));
+    }
+
+    #[test_case(Some("text/html; charset=GBK"), "<title>你好</title>"; "content type")]
+    #[test_case(Some("text/html"), "<meta charset=\"gbk\"><title>你好</title>"; "meta charset")]
+    #[test_case(None, "<meta http-equiv=\"Content-Type\" content=\"text/html; charset=gb2312\"><title>你好</title>"; "meta http equiv")]
+    fn test_decode_html_gbk(content_type: Option<&str>, html: &str) {
+        let (bytes, _, _) = encoding_rs::GBK.encode(html);
+        assert_eq!(html, decode_html(&bytes, content_type));
+    }
+
+    #[test]
+    fn test_decode_html_fallback_utf8() {
+        assert_eq!("<p>你好</p>", decode_html("<p>你好</p>".as_bytes(), None));
+        assert_eq!(
+            "<p>你好</p>",
+            decode_html("<p>你好</p>".as_bytes(), Some("text/html; charset=unknown"))
+        );
+    }
+
+    #[test_case("Zine", "Zine"; "plain")]
+    #[test_case("<script>alert(1)</script>", "&lt;script&gt;alert(1)&lt;/script&gt;"; "script")]
+    #[test_case("\" onerror=\"alert(1)", "&quot; onerror=&quot;alert(1)"; "double quote")]
+    #[test_case("' onclick='alert(1)", "&#39; onclick=&#39;alert(1)"; "single quote")]
+    #[test_case("Tom & Jerry", "Tom &amp; Jerry"; "ampersand")]
+    fn test_escape_html(text: &str, expected: &str) {
+        assert_eq!(expected, escape_html(text));
+    }
+
+    #[test_case("https://github.com/?a=1&b=2", Some("https://github.com/?a=1&amp;b=2"); "https")]
+    #[test_case("/static/zine.png", Some("/static/zine.png"); "absolute path")]
+    #[test_case("./a:b.png", Some("./a:b.png"); "relative path with colon")]
+    #[test_case("mailto:zine@example.com", Some("mailto:zine@example.com"); "mailto")]
+    #[test_case("data:image/png;base64,AAAA", Some("data:image/png;base64,AAAA"); "data image")]
+    #[test_case("https://a.com/\"><script>", Some("https://a.com/&quot;&gt;&lt;script&gt;"); "break attribute")]
+    #[test_case("javascript:alert(1)", None; "javascript")]
+    #[test_case(" JavaScript:alert(1)", None; "javascript uppercase")]
+    #[test_case("data:text/html,<script>alert(1)</script>", None; "data html")]
+    #[test_case("vbscript:msgbox", None; "vbscript")]
+    fn test_escape_url(url: &str, expected: Option<&str>) {
+        assert_eq!(expected, escape_url(url).as_deref());
+    }
+
+    #[test_case("<p>Hello <b>Zine</b></p>", "<p>Hello <b>Zine</b></p>"; "allowed")]
+    #[test_case("<script>alert(1)</script><p>Hi</p>", "<p>Hi</p>"; "script")]
+    #[test_case("<SCRIPT SRC=https://evil.com/x.js></SCRIPT>", ""; "uppercase script")]
+    #[test_case("<iframe src=\"https://evil.com\"></iframe>", ""; "iframe")]
+    #[test_case("<style>body { display: none }</style>", ""; "style")]
+    #[test_case("<svg><script>alert(1)</script></svg>", ""; "svg")]
+    #[test_case("<img src=x onerror=\"alert(1)\">", "<img src=x>"; "event handler")]
+    #[test_case("<a href=\"javascript:alert(1)\">x</a>", "<a>x</a>"; "javascript href")]
+    #[test_case("<a href=\" JaVaScRiPt:alert(1)\">x</a>", "<a>x</a>"; "javascript href mixed case")]
+    #[test_case("<a href=\"https://zine.land\" target=\"_blank\">x</a>", "<a href=\"https://zine.land\" target=\"_blank\">x</a>"; "safe href")]
+    #[test_case("<div style=\"position: fixed\" class=\"note\">x</div>", "<div class=\"note\">x</div>"; "inline style")]
+    #[test_case("<form action=\"https://evil.com\"><input name=\"q\"></form>", ""; "form")]
+    #[test_case("<blink>Hello</blink>", "Hello"; "unknown tag")]
+    #[test_case("<!-- <script>alert(1)</script> -->Hi", "Hi"; "comment")]
+    #[test_case("<td style=\"text-align: center\">x</td>", "<td style=\"text-align: center\">x</td>"; "text align")]
+    #[test_case("<td style=\"text-align: center; color: red\">x</td>", "<td>x</td>"; "other style")]
+    #[test_case("<input type=\"checkbox\" checked onclick=\"alert(1)\">", "<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"; "checkbox")]
+    #[test_case("<input type=\"text\" value=\"x\">", ""; "text input")]
+    fn test_sanitize_html(html: &str, expected: &str) {
+        assert_eq!(expected, sanitize_html(html).unwrap());
+    }
+
+    #[test]
+    fn test_sanitize_html_with() {
+        let trusted = vec![String::from("<svg class=\"icon\"></svg>")];
+        let placeholder = trusted_html_placeholder(0);
+        assert_eq!(
+            sanitize_html_with(
+                &format!("<p>{}<script>x</script></p>", placeholder),
+                &trusted
+            )
+            .unwrap(),
+            "<p><svg class=\"icon\"></svg></p>"
+        );
+        // The placeholder inside attribute value or removed element is never replaced.
+        let html = format!("<div title='{}'>x</div>", placeholder);
+        assert_eq!(sanitize_html_with(&html, &trusted).unwrap(), html);
+        let html = format!("<textarea>{}</textarea>", placeholder);
+        assert_eq!(sanitize_html_with(&html, &trusted).unwrap(), "");
+        // The unknown placeholder is removed.
+        assert_eq!(
+            sanitize_html_with(&trusted_html_placeholder(1), &trusted).unwrap(),
+            ""
+        );
+    }
 }
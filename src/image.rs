@@ -0,0 +1,435 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufWriter, Read},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use image::{
+    codecs::webp::WebPEncoder, imageops::FilterType, ColorType, DynamicImage, ImageFormat,
+};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{data, entity::ImageConfig, helpers::content_hash, html::rewrite_html_images};
+
+// The directory of the generated images, relative to the build directory.
+const IMAGES_DIR: &str = "static/zine-images";
+// The length of file prefix read to parse the image size.
+const HEADER_PREFIX_LEN: u64 = 16 * 1024;
+
+static PIPELINE: Lazy<RwLock<Option<ImagePipeline>>> = Lazy::new(Default::default);
+// The processed images keyed by the source file. The entry is locked while processing,
+// so that the image rendered in multiple pages concurrently is only processed once.
+static PROCESSED: Lazy<Mutex<HashMap<PathBuf, ProcessedEntry>>> = Lazy::new(Default::default);
+
+// The modified time of the source file, and the processed result.
+type ProcessedImage = (SystemTime, Option<ResponsiveImage>);
+// The lockable entry of a source file, it's `None` until the first processing.
+type ProcessedEntry = Arc<Mutex<Option<ProcessedImage>>>;
+
+/// The `srcset` of the resized images and their WebP versions.
+#[derive(Debug, Clone)]
+pub struct ResponsiveImage {
+    pub srcset: String,
+    pub webp_srcset: Option<String>,
+}
+
+/// The image pipeline to generate the responsive images into the build directory.
+///
+/// The generated images are named by the content hash of the source image,
+/// so they are only generated once until the source image changes.
+struct ImagePipeline {
+    source: PathBuf,
+    dest: PathBuf,
+    config: ImageConfig,
+}
+
+/// Enable the image pipeline if the `responsive` option is on, otherwise disable it.
+pub fn init_pipeline(source: &Path, dest: &Path, config: &ImageConfig) {
+    *PIPELINE.write() = if config.responsive {
+        Some(ImagePipeline {
+            source: source.to_owned(),
+            dest: dest.to_owned(),
+            config: config.clone(),
+        })
+    } else {
+        None
+    };
+}
+
+/// Rewrite the local images in `raw_html` into responsive images.
+///
+/// Return `None` if the image pipeline is disabled.
+pub fn rewrite_images(raw_html: &[u8]) -> Result<Option<Vec<u8>>> {
+    let pipeline = PIPELINE.read();
+    match pipeline.as_ref() {
+        Some(pipeline) => Ok(Some(rewrite_html_images(
+            raw_html,
+            &pipeline.config.sizes,
+            |src| pipeline.responsive_image(src),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+impl ImagePipeline {
+    fn responsive_image(&self, url: &str) -> Option<ResponsiveImage> {
+        let file = self.source_file(url)?;
+        let modified = fs::metadata(&file).and_then(|meta| meta.modified()).ok()?;
+        let entry = PROCESSED.lock().entry(file.clone()).or_default().clone();
+        let mut entry = entry.lock();
+        if let Some((time, image)) = entry.as_ref() {
+            if *time == modified {
+                return image.clone();
+            }
+        }
+
+        let image = match self.process(&file, url) {
+            Ok(image) => image,
+            Err(err) => {
+                println!(
+                    "Warn: failed to process image `{}`: {:#}",
+                    file.display(),
+                    err
+                );
+                None
+            }
+        };
+        *entry = Some((modified, image.clone()));
+        image
+    }
+
+    // The source file of local image URL, under the `static` directory or copied from
+    // the `pages` and issue directories.
+    fn source_file(&self, url: &str) -> Option<PathBuf> {
+        let path = match url.strip_prefix("/static/") {
+            Some(rest) => {
+                let rest = rest.split(['#', '?']).next()?;
+                if has_parent_dir(rest) {
+                    return None;
+                }
+                Path::new("static").join(rest)
+            }
+            None => data::read().link_resolver().resolve_asset(url)?,
+        };
+        let file = self.source.join(path);
+        if file.is_file() {
+            Some(file)
+        } else {
+            None
+        }
+    }
+
+    fn process(&self, file: &Path, url: &str) -> Result<Option<ResponsiveImage>> {
+        let bytes = fs::read(file)?;
+        // Only the PNG and JPEG images are processed, the GIF may be animated.
+        let ext = match image::guess_format(&bytes) {
+            Ok(ImageFormat::Png) => "png",
+            Ok(ImageFormat::Jpeg) => "jpg",
+            _ => return Ok(None),
+        };
+        let (width, height) = parse_image_size(&bytes).context("Invalid image header")?;
+        let mut widths = self
+            .config
+            .widths
+            .iter()
+            .copied()
+            .filter(|w| *w > 0 && *w < width)
+            .collect::<Vec<_>>();
+        widths.sort_unstable();
+        widths.dedup();
+        // The lossless WebP of JPEG photos is always larger than the original.
+        let mut webp = self.config.webp && ext == "png";
+        if widths.is_empty() && !webp {
+            return Ok(None);
+        }
+
+        let hash = content_hash(&bytes);
+        let stem = file.file_stem().and_then(OsStr::to_str).unwrap_or("image");
+        let name = |w: u32, ext: &str| format!("{}-{:016x}-{}.{}", stem, hash, w, ext);
+        let dir = self.dest.join(IMAGES_DIR);
+        fs::create_dir_all(&dir)?;
+
+        // Decode the image lazily, only if some images aren't generated yet.
+        let mut decoded = None;
+        let mut resize = |w: u32| -> Result<DynamicImage> {
+            let image = match decoded.as_mut() {
+                Some(image) => image,
+                None => decoded.insert(image::load_from_memory(&bytes)?),
+            };
+            if w == width {
+                return Ok(image.clone());
+            }
+            let h = (height as u64 * w as u64 / width as u64).max(1) as u32;
+            Ok(image.resize_exact(w, h, FilterType::Lanczos3))
+        };
+
+        let encode_webp = |image: DynamicImage, path: &Path| -> Result<()> {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_lossless(BufWriter::new(File::create(path)?)).encode(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                ColorType::Rgba8,
+            )?;
+            Ok(())
+        };
+        if webp {
+            // The lossless WebP isn't always smaller, only keep it if the original one is.
+            let path = dir.join(name(width, "webp"));
+            if !path.exists() {
+                encode_webp(resize(width)?, &path)?;
+            }
+            if fs::metadata(&path)?.len() >= bytes.len() as u64 {
+                fs::remove_file(&path)?;
+                webp = false;
+            }
+        }
+
+        let mut srcset = vec![];
+        let mut webp_srcset = vec![];
+        for w in widths.iter().copied().chain(Some(width)) {
+            if w == width {
+                // The original image is the largest one.
+                srcset.push(format!("{} {}w", url, w));
+            } else {
+                let path = dir.join(name(w, ext));
+                if !path.exists() {
+                    resize(w)?.save(&path)?;
+                }
+                srcset.push(format!("/{}/{} {}w", IMAGES_DIR, name(w, ext), w));
+            }
+            if webp {
+                let path = dir.join(name(w, "webp"));
+                if !path.exists() {
+                    encode_webp(resize(w)?, &path)?;
+                }
+                webp_srcset.push(format!("/{}/{} {}w", IMAGES_DIR, name(w, "webp"), w));
+            }
+        }
+
+        let webp_srcset = (!webp_srcset.is_empty()).then(|| webp_srcset.join(", "));
+        if srcset.len() == 1 && webp_srcset.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(ResponsiveImage {
+            srcset: srcset.join(", "),
+            webp_srcset,
+        }))
+    }
+}
+
+/// Read the width and height of the image from its header,
+/// the PNG, JPEG, GIF and WebP formats are supported.
+pub fn image_size(path: &Path) -> Option<(u32, u32)> {
+    // Only read the header, the JPEG metadata such as EXIF may precede the
+    // frame header, so read more if the header isn't in the prefix.
+    let mut file = File::open(path).ok()?;
+    let mut bytes = vec![];
+    let mut limit = HEADER_PREFIX_LEN;
+    loop {
+        (&mut file)
+            .take(limit - bytes.len() as u64)
+            .read_to_end(&mut bytes)
+            .ok()?;
+        if let Some(size) = parse_image_size(&bytes) {
+            return Some(size);
+        }
+        if (bytes.len() as u64) < limit || !bytes.starts_with(b"\xff\xd8") {
+            return None;
+        }
+        limit *= 4;
+    }
+}
+
+fn parse_image_size(bytes: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 = |i: usize| Some(u16::from_be_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le_u16 = |i: usize| Some(u16::from_le_bytes(bytes.get(i..i + 2)?.try_into().ok()?) as u32);
+    let le_u24 = |i: usize| {
+        let b = bytes.get(i..i + 3)?;
+        Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    };
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(bytes.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(bytes.get(20..24)?.try_into().ok()?);
+        Some((width, height))
+    } else if bytes.starts_with(b"GIF8") {
+        Some((le_u16(6)?, le_u16(8)?))
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        match bytes.get(12..16)? {
+            b"VP8 " => Some((le_u16(26)? & 0x3fff, le_u16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le_u24(24)? + 1, le_u24(27)? + 1)),
+            _ => None,
+        }
+    } else if bytes.starts_with(b"\xff\xd8") {
+        // Walk through the JPEG segments until the start of frame.
+        let mut i = 2;
+        loop {
+            while *bytes.get(i)? != 0xff {
+                i += 1;
+            }
+            while *bytes.get(i)? == 0xff {
+                i += 1;
+            }
+            let marker = *bytes.get(i)?;
+            i += 1;
+            match marker {
+                // The markers without payload.
+                0xd0..=0xd9 | 0x01 => continue,
+                // The start of frame markers, except DHT, JPG and DAC.
+                0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                    return Some((be_u16(i + 5)?, be_u16(i + 3)?));
+                }
+                _ => i += be_u16(i)? as usize,
+            }
+        }
+    } else {
+        None
+    }
+}
+
+/// Find the high-density variants of the image alongside it,
+/// such as `cover@2x.png` and `cover@3x.png` of `cover.png`.
+///
+/// Return the variant file names and their pixel densities.
+pub fn density_variants(path: &Path) -> Vec<(String, u32)> {
+    let (stem, ext) = match (
+        path.file_stem().and_then(|s| s.to_str()),
+        path.extension().and_then(|s| s.to_str()),
+    ) {
+        (Some(stem), Some(ext)) => (stem, ext),
+        _ => return vec![],
+    };
+    [2, 3]
+        .into_iter()
+        .filter_map(|density| {
+            let name = format!("{}@{}x.{}", stem, density, ext);
+            if path.with_file_name(&name).exists() {
+                Some((name, density))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Whether the `path` has the `..` component, which may escape the `static` directory.
+fn has_parent_dir(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .any(|component| component == Component::ParentDir)
+}
+
+/// Resolve the local image file of `src`, which is either under the `static`
+/// directory such as `/static/cover.png`, or relative to the markdown file `path`.
+pub fn local_image_path(root: &Path, path: Option<&Path>, src: &str) -> Option<PathBuf> {
+    let src = src.split(['#', '?']).next().unwrap_or_default();
+    if src.is_empty() || src.contains(':') {
+        return None;
+    }
+    let file = match src.strip_prefix('/') {
+        Some(src) if src.starts_with("static/") && !has_parent_dir(src) => root.join(src),
+        Some(_) => return None,
+        None => root.join(path?.parent()?).join(src),
+    };
+    if file.is_file() {
+        Some(file)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use test_case::test_case;
+
+    use image::{DynamicImage, ImageFormat};
+
+    use crate::entity::ImageConfig;
+
+    use super::{image_size, local_image_path, parse_image_size, ImagePipeline, IMAGES_DIR};
+
+    #[test_case(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x02\x80\0\0\x01\xe0", Some((640, 480)); "png")]
+    #[test_case(b"GIF89a\x80\x02\xe0\x01", Some((640, 480)); "gif")]
+    #[test_case(b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0\x7f\x02\0\xdf\x01\0", Some((640, 480)); "webp extended")]
+    #[test_case(b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f\x7f\xc2\x77\0", Some((640, 480)); "webp lossless")]
+    #[test_case(b"\xff\xd8\xff\xe0\0\x04JF\xff\xc0\0\x11\x08\x01\xe0\x02\x80\x03", Some((640, 480)); "jpeg")]
+    #[test_case(b"<svg></svg>", None; "unsupported")]
+    #[test_case(b"\xff\xd8\xff\xe0\0\x10", None; "truncated")]
+    fn test_parse_image_size(bytes: &[u8], expected: Option<(u32, u32)>) {
+        assert_eq!(parse_image_size(bytes), expected);
+    }
+
+    #[test]
+    fn test_image_size() {
+        // The frame header is after a large EXIF segment, beyond the prefix read first.
+        let mut jpeg = b"\xff\xd8\xff\xe1\xc0\x00".to_vec();
+        jpeg.resize(jpeg.len() + 0xc000 - 2, 0);
+        jpeg.extend_from_slice(b"\xff\xc0\0\x11\x08\x01\xe0\x02\x80\x03");
+        jpeg.resize(jpeg.len() + 1024, 0);
+        let path = env::temp_dir().join("__zine_image_size.jpg");
+        fs::write(&path, jpeg).unwrap();
+        assert_eq!(image_size(&path), Some((640, 480)));
+
+        fs::write(&path, b"\xff\xd8\xff\xe0\0\x10").unwrap();
+        assert_eq!(image_size(&path), None);
+    }
+
+    #[test]
+    fn test_process_image() {
+        let root = env::temp_dir().join(format!("__zine_process_image_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (source, dest) = (root.join("source"), root.join("dest"));
+        fs::create_dir_all(source.join("static")).unwrap();
+        let image = DynamicImage::new_rgb8(64, 32);
+        image
+            .save_with_format(source.join("static/photo.jpg"), ImageFormat::Jpeg)
+            .unwrap();
+        image
+            .save_with_format(source.join("static/chart.png"), ImageFormat::Png)
+            .unwrap();
+        fs::write(root.join("secret.png"), "").unwrap();
+
+        let pipeline = ImagePipeline {
+            source: source.clone(),
+            dest: dest.clone(),
+            config: ImageConfig {
+                responsive: true,
+                widths: vec![32],
+                ..Default::default()
+            },
+        };
+        // The JPEG is only resized, never encoded to WebP.
+        let image = pipeline.responsive_image("/static/photo.jpg").unwrap();
+        assert!(image.srcset.ends_with("/static/photo.jpg 64w"));
+        assert!(image.webp_srcset.is_none());
+        // The PNG has the smaller WebP versions.
+        let image = pipeline.responsive_image("/static/chart.png").unwrap();
+        assert_eq!(image.webp_srcset.unwrap().matches(".webp").count(), 2);
+        let webp_files = fs::read_dir(dest.join(IMAGES_DIR))
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension() == Some("webp".as_ref()))
+            .count();
+        assert_eq!(webp_files, 2);
+
+        // The path escaping the static directory is rejected.
+        assert!(pipeline.source_file("/static/../../secret.png").is_none());
+        assert!(local_image_path(&source, None, "/static/../../secret.png").is_none());
+        assert!(local_image_path(&source, None, "/static/chart.png").is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::helpers::{is_markdown_file, normalize_path};
+
+/// The scheme to reference an issue, article, page or author by its URL path.
+///
+/// For example, `zine://cloud/first` references the `first` article of `cloud` issue,
+/// `zine://@alice` references the profile page of author `alice`.
+const ZINE_SCHEME: &str = "zine://";
+
+/// The resolver to resolve links between markdown files into the final URL at build time.
+///
+/// Two kinds of link are supported:
+/// - the source path relative to current markdown file, e.g. `../issue-1/first.md`.
+/// - the `zine://` reference, e.g. `zine://cloud/first`.
+#[derive(Debug, Default)]
+pub struct LinkResolver {
+    // The root directory, which the source paths are relative to.
+    root: PathBuf,
+    // The source path of markdown file => URL.
+    markdown_urls: HashMap<PathBuf, String>,
+    // The source directory => URL prefix, used to resolve asset links.
+    dirs: Vec<(PathBuf, String)>,
+    // All rendered URLs of the site.
+    urls: HashSet<String>,
+}
+
+impl LinkResolver {
+    /// Create a resolver of the `root` directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LinkResolver {
+            root: root.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Record a rendered `url`.
+    pub fn add_url(&mut self, url: impl Into<String>) {
+        self.urls.insert(url.into());
+    }
+
+    /// Record a markdown file with its source path relative to the root directory.
+    pub fn add_markdown(&mut self, path: impl AsRef<Path>, url: impl Into<String>) {
+        let url = url.into();
+        self.markdown_urls
+            .insert(normalize_path(path.as_ref()), url.clone());
+        self.urls.insert(url);
+    }
+
+    /// Record a source directory whose assets are copied into `url_prefix`.
+    pub fn add_dir(&mut self, path: impl AsRef<Path>, url_prefix: impl Into<String>) {
+        self.dirs
+            .push((normalize_path(path.as_ref()), url_prefix.into()));
+    }
+
+    /// Resolve the source path of the asset `url`, relative to the root directory.
+    /// It's the reverse of resolving the asset link.
+    pub fn resolve_asset(&self, url: &str) -> Option<PathBuf> {
+        let url = url.split(['#', '?']).next().unwrap_or_default();
+        self.dirs
+            .iter()
+            .filter_map(|(dir, prefix)| {
+                let rest = url.strip_prefix(prefix.as_str())?.strip_prefix('/')?;
+                Some((prefix.len(), dir, rest))
+            })
+            .filter(|(_, _, rest)| !rest.is_empty() && !rest.split('/').any(|c| c == ".."))
+            .max_by_key(|(len, ..)| *len)
+            .map(|(_, dir, rest)| dir.join(rest))
+    }
+
+    /// Resolve the `link` in the markdown file of `current` path.
+    ///
+    /// Return `Ok(None)` if the link needn't to be resolved, such as external links.
+    /// Return error if the link target doesn't exist.
+    pub fn resolve(&self, link: &str, current: Option<&Path>) -> Result<Option<String>> {
+        let (link_path, fragment) = match link.find(['#', '?']) {
+            Some(index) => link.split_at(index),
+            None => (link, ""),
+        };
+
+        if let Some(path) = link_path.strip_prefix(ZINE_SCHEME) {
+            let url = format!("/{}", path.trim_matches('/'));
+            if self.urls.contains(&url) {
+                return Ok(Some(format!("{}{}", url, fragment)));
+            }
+            bail!("the link target `{}` doesn't exist", link);
+        }
+
+        // Ignore empty, absolute and external links.
+        if link_path.is_empty() || link_path.starts_with('/') || link_path.contains(':') {
+            return Ok(None);
+        }
+
+        let current_dir = match current.and_then(Path::parent) {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let path = normalize_path(&current_dir.join(link_path));
+        if is_markdown_file(&path) {
+            match self.markdown_urls.get(&path) {
+                Some(url) => Ok(Some(format!("{}{}", url, fragment))),
+                None => bail!("the link target `{}` doesn't exist", link),
+            }
+        } else {
+            // Resolve the asset link into the URL where it copied to.
+            // The longest directory is preferred if directories are nested.
+            let url = self
+                .dirs
+                .iter()
+                .filter_map(|(dir, prefix)| {
+                    let rest = path.strip_prefix(dir).ok()?;
+                    Some((dir.components().count(), prefix, rest))
+                })
+                .max_by_key(|(depth, ..)| *depth)
+                .map(|(_, prefix, rest)| {
+                    let rest = rest
+                        .components()
+                        .filter_map(|component| match component {
+                            Component::Normal(name) => name.to_str(),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    format!("{}/{}{}", prefix, rest, fragment)
+                });
+            match url {
+                Some(_) if !self.root.join(&path).exists() => {
+                    bail!("the link target `{}` doesn't exist", link)
+                }
+                url => Ok(url),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        sync::Once,
+    };
+
+    use test_case::test_case;
+
+    use super::LinkResolver;
+
+    // The root directory with the asset files.
+    fn root() -> PathBuf {
+        static INIT: Once = Once::new();
+        let root = env::temp_dir().join("__zine_link");
+        INIT.call_once(|| {
+            let _ = fs::remove_dir_all(&root);
+            for asset in [
+                "content/issue-1/figure.png",
+                "content/issue-1/images/figure.png",
+                "pages/logo.png",
+            ] {
+                let path = root.join(asset);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+        });
+        root
+    }
+
+    fn resolver() -> LinkResolver {
+        let mut resolver = LinkResolver::new(root());
+        resolver.add_dir("content/issue-1", "/cloud");
+        resolver.add_dir("pages", "");
+        resolver.add_url("/cloud");
+        resolver.add_url("/@alice");
+        resolver.add_markdown("content/issue-1/first.md", "/cloud/first");
+        resolver.add_markdown("./content/issue-2/second.md", "/dev/second");
+        resolver.add_markdown("pages/about.md", "/about");
+        resolver
+    }
+
+    #[test_case("zine://cloud", "/cloud"; "issue")]
+    #[test_case("zine://cloud/first", "/cloud/first"; "article")]
+    #[test_case("zine://cloud/first/", "/cloud/first"; "trailing slash")]
+    #[test_case("zine://cloud/first#title", "/cloud/first#title"; "fragment")]
+    #[test_case("zine://@alice", "/@alice"; "author")]
+    #[test_case("zine://about", "/about"; "page")]
+    #[test_case("first.md", "/cloud/first"; "sibling")]
+    #[test_case("./first.md#title", "/cloud/first#title"; "sibling fragment")]
+    #[test_case("../issue-2/second.md", "/dev/second"; "other issue")]
+    #[test_case("../../pages/about.md", "/about"; "page path")]
+    #[test_case("./figure.png", "/cloud/figure.png"; "asset")]
+    #[test_case("images/figure.png", "/cloud/images/figure.png"; "nested asset")]
+    #[test_case("../../pages/logo.png", "/logo.png"; "page asset")]
+    fn test_resolve_link(link: &str, expected: &str) {
+        let current = Path::new("content/issue-1/second.md");
+        assert_eq!(
+            Some(expected.to_owned()),
+            resolver().resolve(link, Some(current)).unwrap()
+        );
+    }
+
+    #[test_case("https://github.com"; "external")]
+    #[test_case("mailto:zine@example.com"; "mailto")]
+    #[test_case("/static/zine.png"; "absolute")]
+    #[test_case("#title"; "anchor")]
+    #[test_case("../../outside.png"; "outside asset")]
+    fn test_resolve_link_ignored(link: &str) {
+        let current = Path::new("content/issue-1/second.md");
+        assert_eq!(None, resolver().resolve(link, Some(current)).unwrap());
+    }
+
+    #[test_case("/cloud/figure.png", Some("content/issue-1/figure.png"); "issue asset")]
+    #[test_case("/cloud/images/figure.png?v=1", Some("content/issue-1/images/figure.png"); "query")]
+    #[test_case("/logo.png", Some("pages/logo.png"); "page asset")]
+    #[test_case("/cloud/../secret.png", None; "parent dir")]
+    #[test_case("https://example.com/logo.png", None; "external")]
+    fn test_resolve_asset(url: &str, expected: Option<&str>) {
+        assert_eq!(resolver().resolve_asset(url), expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_resolve_link_without_current() {
+        assert_eq!(None, resolver().resolve("first.md", None).unwrap());
+        assert_eq!(
+            Some("/cloud/first".to_owned()),
+            resolver().resolve("zine://cloud/first", None).unwrap()
+        );
+    }
+
+    #[test_case("zine://cloud/third"; "missing article")]
+    #[test_case("zine://@bob"; "missing author")]
+    #[test_case("third.md"; "missing markdown")]
+    #[test_case("./missing.png"; "missing asset")]
+    #[test_case("../../pages/missing.png#top"; "missing page asset")]
+    fn test_resolve_link_not_found(link: &str) {
+        let current = Path::new("content/issue-1/second.md");
+        assert!(resolver().resolve(link, Some(current)).is_err());
+    }
+}
@@ -13,6 +13,8 @@ static FLUENT_ZH_CN: &str = include_str!("../locales/zh.ftl");
 /// so it can be register as a tera function.
 pub struct FluentLoader {
     bundle: FluentBundle<FluentResource, IntlLangMemoizer>,
+    // The default `en` bundle, used if the message is missing in the user translation.
+    fallback: Option<FluentBundle<FluentResource, IntlLangMemoizer>>,
 }
 
 impl FluentLoader {
@@ -39,13 +41,31 @@ impl FluentLoader {
         }
         .expect("Load translation failed.");
 
-        let lang_id = locale.parse().expect("Invalid locale string.");
-        let mut bundle = FluentBundle::new_concurrent(vec![lang_id]);
-        bundle.add_resource(resource).unwrap();
-        FluentLoader { bundle }
+        let fallback = match locale {
+            "en" | "zh" => None,
+            _ => {
+                let resource = FluentResource::try_new(FLUENT_EN.to_owned())
+                    .expect("Load translation failed.");
+                Some(new_bundle("en", resource))
+            }
+        };
+        FluentLoader {
+            bundle: new_bundle(locale, resource),
+            fallback,
+        }
     }
 }
 
+fn new_bundle(
+    locale: &str,
+    resource: FluentResource,
+) -> FluentBundle<FluentResource, IntlLangMemoizer> {
+    let lang_id = locale.parse().expect("Invalid locale string.");
+    let mut bundle = FluentBundle::new_concurrent(vec![lang_id]);
+    bundle.add_resource(resource).unwrap();
+    bundle
+}
+
 fn json_to_fluent(json: &Value) -> FluentValue {
     match json {
         Value::Number(n) if n.is_u64() => FluentValue::from(n.as_u64().unwrap()),
@@ -66,12 +86,17 @@ impl tera::Function for FluentLoader {
             .and_then(Value::as_str)
             .expect("Missing `key` argument.");
 
-        let pattern = self
-            .bundle
-            .get_message(key)
-            .unwrap_or_else(|| panic!("Invalid fluent key: `{}`", key))
-            .value()
-            .expect("Missing Value.");
+        // Fallback to the default `en` translation if the user translation
+        // doesn't have this message, such as the messages added in newer version.
+        let (bundle, message) = match self.bundle.get_message(key) {
+            Some(message) => (&self.bundle, message),
+            None => self
+                .fallback
+                .as_ref()
+                .and_then(|bundle| Some((bundle, bundle.get_message(key)?)))
+                .unwrap_or_else(|| panic!("Invalid fluent key: `{}`", key)),
+        };
+        let pattern = message.value().expect("Missing Value.");
 
         let mut fluent_args = FluentArgs::new();
         for (key, value) in args.iter().filter(|(key, _)| &**key != "key") {
@@ -79,7 +104,7 @@ impl tera::Function for FluentLoader {
         }
 
         Ok(Value::String(
-            self.bundle
+            bundle
                 .format_pattern(pattern, Some(fluent_args).as_ref(), &mut vec![])
                 .into_owned(),
         ))
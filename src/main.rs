@@ -1,11 +1,19 @@
 use anyhow::Result;
-use build::watch_build;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use build::{check_build_links, check_links, clean_build_dir, watch_build};
+use check::ExternalCheckOptions;
 use clap::StructOpt;
 use new::new_zine_project;
 use parking_lot::RwLock;
+use preview::PreviewEdit;
 use serve::run_serve;
 
 mod build;
+mod check;
 mod code_blocks;
 mod data;
 mod engine;
@@ -14,10 +22,15 @@ mod error;
 mod feed;
 mod helpers;
 mod html;
+mod image;
+mod link;
 mod locales;
 mod markdown;
+mod math;
 mod meta;
 mod new;
+mod plugin;
+mod preview;
 mod serve;
 
 pub use self::engine::ZineEngine;
@@ -53,6 +66,17 @@ fn set_current_mode(mode: Mode) {
     *MODE.write() = mode;
 }
 
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Whether in offline mode, which never fetches the network.
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
 #[derive(Debug, clap::Parser)]
 #[clap(name = "zine")]
 #[clap(about = "A simple and opinionated tool to build your own magazine.", long_about = None)]
@@ -72,6 +96,14 @@ enum Commands {
         /// Enable watching.
         #[clap(short, long)]
         watch: bool,
+        /// Check the internal links of the built site,
+        /// the build directory is cleaned before building.
+        #[clap(long)]
+        check_links: bool,
+        /// Build without network access, the uncached URL previews
+        /// are rendered as plain links.
+        #[clap(long)]
+        offline: bool,
     },
     /// Serve the Zine site.
     Serve {
@@ -81,6 +113,31 @@ enum Commands {
         #[clap(short, default_value_t = 3000)]
         port: u16,
     },
+    /// Check the links of Zine site.
+    CheckLinks {
+        /// The source directory of zine site.
+        source: Option<String>,
+        /// Also check the external links.
+        #[clap(long)]
+        external: bool,
+        /// The max number of concurrent requests for external links.
+        #[clap(long, default_value_t = 8)]
+        concurrency: usize,
+        /// The timeout seconds of each request for external links.
+        #[clap(long, default_value_t = 10)]
+        timeout: u64,
+        /// The min interval milliseconds between two requests to the same host.
+        #[clap(long, default_value_t = 500)]
+        host_interval: u64,
+        /// Reuse the cached results of external links checked within these days.
+        #[clap(long, default_value_t = 7)]
+        max_age: u64,
+    },
+    /// Manage the URL preview cache in `zine-data.json`.
+    Previews {
+        #[clap(subcommand)]
+        command: PreviewsCommands,
+    },
     /// New a Zine project.
     New {
         /// The project name.
@@ -90,6 +147,43 @@ enum Commands {
     Version,
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum PreviewsCommands {
+    /// List all cached URL previews.
+    List {
+        /// The source directory of zine site.
+        source: Option<String>,
+    },
+    /// Fetch the cached URL previews again.
+    Refresh {
+        /// The source directory of zine site.
+        source: Option<String>,
+        /// Only refresh the previews fetched more than these days ago.
+        #[clap(long)]
+        older_than: Option<u64>,
+        /// Only refresh the previews of these URLs.
+        #[clap(long = "url")]
+        urls: Vec<String>,
+        /// The max number of concurrent requests.
+        #[clap(long, default_value_t = 8)]
+        concurrency: usize,
+    },
+    /// Remove the cached URL previews no longer referenced by any markdown.
+    Prune {
+        /// The source directory of zine site.
+        source: Option<String>,
+    },
+    /// Edit the cached URL preview manually, create one if it doesn't exist.
+    Edit {
+        /// The URL of the preview.
+        url: String,
+        /// The source directory of zine site.
+        source: Option<String>,
+        #[clap(flatten)]
+        edit: PreviewEdit,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     match Cli::parse().command {
@@ -97,16 +191,71 @@ async fn main() -> Result<()> {
             source,
             dest,
             watch,
+            check_links,
+            offline,
         } => {
             set_current_mode(Mode::Build);
+            set_offline(offline);
+            let source = source.unwrap_or_else(|| ".".into());
             let dest = dest.unwrap_or_else(|| "build".into());
-            watch_build(&source.unwrap_or_else(|| ".".into()), &dest, watch).await?;
+            if check_links {
+                // Only check the files of this build.
+                clean_build_dir(&source, &dest)?;
+            }
+            watch_build(&source, &dest, watch).await?;
             println!("Build success! The build directory is `{}`.", dest);
+
+            if check_links {
+                check_build_links(&source, &dest)?;
+            }
         }
         Commands::Serve { source, port } => {
             set_current_mode(Mode::Serve);
             run_serve(source.unwrap_or_else(|| ".".into()), port).await?;
         }
+        Commands::CheckLinks {
+            source,
+            external,
+            concurrency,
+            timeout,
+            host_interval,
+            max_age,
+        } => {
+            set_current_mode(Mode::Build);
+            let external = external.then(|| ExternalCheckOptions {
+                concurrency,
+                timeout: Duration::from_secs(timeout),
+                host_interval: Duration::from_millis(host_interval),
+                max_age: Duration::from_secs(max_age * 24 * 60 * 60),
+            });
+            check_links(source.unwrap_or_else(|| ".".into()), external).await?;
+        }
+        Commands::Previews { command } => match command {
+            PreviewsCommands::List { source } => {
+                preview::list_previews(source.unwrap_or_else(|| ".".into()))?
+            }
+            PreviewsCommands::Refresh {
+                source,
+                older_than,
+                urls,
+                concurrency,
+            } => {
+                let older_than = older_than.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+                preview::refresh_previews(
+                    source.unwrap_or_else(|| ".".into()),
+                    older_than,
+                    urls,
+                    concurrency,
+                )
+                .await?
+            }
+            PreviewsCommands::Prune { source } => {
+                preview::prune_previews(source.unwrap_or_else(|| ".".into()))?
+            }
+            PreviewsCommands::Edit { url, source, edit } => {
+                preview::edit_preview(source.unwrap_or_else(|| ".".into()), &url, edit)?
+            }
+        },
         Commands::New { name } => new_zine_project(name)?,
         Commands::Version => {
             let version =
@@ -1,5 +1,45 @@
+use std::{borrow::Cow, cell::RefCell, collections::HashMap};
+
+use once_cell::sync::Lazy;
 use pulldown_cmark::Event::{self, Code, End, HardBreak, Rule, SoftBreak, Start, Text};
 use pulldown_cmark::{html, CowStr, Options, Parser, Tag};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    code_blocks,
+    html::{escape_html, sanitize_html_with, trusted_html_placeholder},
+};
+
+// The first line of GitHub-style alert, such as `> [!NOTE]`, and the
+// Obsidian-style extensions: `> [!TIP]- Custom title` for collapsible callout.
+static GITHUB_ALERT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)^(\s*)>\s?\[!(note|tip|important|warning|caution|danger|quote)\]([+-]?)\s*(.*)$",
+    )
+    .unwrap()
+});
+// The opening or closing line of fenced code block.
+static CODE_FENCE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(`{3,}|~{3,})").unwrap());
+// The opening line of HTML block, which starts with a tag or comment.
+static HTML_BLOCK_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*(<!--|</?[A-Za-z][A-Za-z0-9-]*)(\s|/?>|$)").unwrap());
+// The inline HTML tag or comment, the attribute values may be quoted.
+static HTML_TAG_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^(?:<!--(?s:.*?)-->|</?[A-Za-z][A-Za-z0-9-]*(?:\s+[A-Za-z_:][\w.:-]*(?:\s*=\s*(?:"[^"]*"|'[^']*'|[^\s"'=<>`]+))?)*\s*/?>)"#,
+    )
+    .unwrap()
+});
+
+// The prefixes of the inline code converted from the inline and display math.
+// The private use characters never conflict with the ordinary inline code.
+const INLINE_MATH_PREFIX: char = '\u{E000}';
+const DISPLAY_MATH_PREFIX: char = '\u{E001}';
+// The markers around the label of footnote reference, which are replaced with
+// the numbered links after the whole markdown (including the nested one) is rendered.
+const FOOTNOTE_REF_START: char = '\u{E002}';
+const FOOTNOTE_REF_END: char = '\u{E003}';
 
 /// The visitor trait to allow customize html rendering.
 ///
@@ -21,6 +61,51 @@ pub trait MarkdownVisitor<'a> {
     fn visit_code(&mut self, code: &CowStr<'a>) -> Visiting {
         Visiting::NotChanged
     }
+
+    /// Visit the raw HTML, the consecutive raw HTML events are merged into one.
+    fn visit_html(&mut self, html: &CowStr<'a>) -> Visiting {
+        Visiting::NotChanged
+    }
+
+    /// Whether to sanitize the raw HTML. The fully rendered HTML is sanitized once,
+    /// the HTML events returned by the visitor are trusted and kept as is.
+    fn sanitize_raw_html(&self) -> bool {
+        false
+    }
+
+    /// The prefix of the footnote ids, which keeps the ids unique when
+    /// several markdown fragments are rendered into one page.
+    fn footnote_prefix(&self) -> &str {
+        ""
+    }
+
+    /// Render the footnotes gathered at the end of markdown, see [`Footnote`].
+    fn render_footnotes(&mut self, footnotes: &[Footnote]) -> String {
+        let mut html = String::from("<section class=\"footnotes\">\n<ol>\n");
+        for footnote in footnotes {
+            html.push_str(&format!(
+                "<li id=\"{}\">\n{}</li>\n",
+                footnote.id, footnote.content
+            ));
+        }
+        html.push_str("</ol>\n</section>\n");
+        html
+    }
+}
+
+/// The footnote definition, which is numbered by the order of its first reference.
+/// The unreferenced footnotes are numbered after the referenced ones.
+///
+/// The references are rendered as `<sup class="footnote-ref">` links to `#fn-{prefix}{number}`,
+/// with the ids `fnref-{prefix}{number}`, `fnref-{prefix}{number}-2` and so on,
+/// see [`MarkdownVisitor::footnote_prefix`].
+#[derive(Debug, Serialize)]
+pub struct Footnote {
+    pub number: usize,
+    /// The element id of the footnote, such as `fn-1`.
+    pub id: String,
+    /// The rendered HTML, with the back-reference links at the end.
+    pub content: String,
 }
 
 /// The markdown visit result.
@@ -47,25 +132,626 @@ impl Visiting {
 }
 
 /// Render markdown to HTML.
+///
+/// The footnote definitions are gathered and rendered at the end by
+/// [`MarkdownVisitor::render_footnotes`]. The nested markdown rendered during
+/// the rendering, such as the callout content, shares the footnotes of the
+/// top-level markdown, so that they are numbered and rendered only once.
 pub fn markdown_to_html<'a>(markdown: &'a str, mut v: impl MarkdownVisitor<'a>) -> String {
-    let parser_events_iter = Parser::new_ext(markdown, Options::all()).into_offset_iter();
-    let events = parser_events_iter
-        .into_iter()
-        .filter_map(|(event, _)| match event {
-            Event::Start(tag) => v.visit_start_tag(&tag).resolve(|| Event::Start(tag)),
-            Event::End(tag) => v.visit_end_tag(&tag).resolve(|| Event::End(tag)),
-            Event::Code(code) => v.visit_code(&code).resolve(|| Event::Code(code)),
-            Event::Text(text) => v
-                .visit_text(&text)
-                // Not a code block inside text, or the code block's fenced is unsupported.
-                // We still need record this text event.
-                .resolve(|| Event::Text(text)),
-            _ => Some(event),
-        });
+    let scope = FootnoteScope::enter();
+    let sanitize = v.sanitize_raw_html();
+    // The HTML returned by the visitor, which is kept out of the sanitization.
+    let mut trusted = vec![];
+    let parser_events_iter =
+        unwrap_figure_paragraphs(merge_html_events(Parser::new_ext(markdown, Options::all())));
+    let events = parser_events_iter.filter_map(|event| {
+        let visiting = match &event {
+            Event::Start(tag) => v.visit_start_tag(tag),
+            Event::End(tag) => v.visit_end_tag(tag),
+            Event::Code(code) => v.visit_code(code),
+            // Not a code block inside text, or the code block's fenced is unsupported.
+            // We still need record this text event.
+            Event::Text(text) => v.visit_text(text),
+            Event::Html(html) => v.visit_html(html),
+            _ => Visiting::NotChanged,
+        };
+        match visiting {
+            Visiting::Event(Event::Html(html)) if sanitize => {
+                trusted.push(html.into_string());
+                Some(Event::Html(
+                    trusted_html_placeholder(trusted.len() - 1).into(),
+                ))
+            }
+            visiting => visiting.resolve(|| event),
+        }
+    });
+    let (events, definitions) = extract_footnotes(events);
 
+    let sanitize_html = |html: String| {
+        if !sanitize {
+            return html;
+        }
+        sanitize_html_with(&html, &trusted).unwrap_or_else(|err| {
+            println!("Warn: failed to sanitize html, it is dropped: {}", err);
+            String::new()
+        })
+    };
     let mut html = String::new();
-    html::push_html(&mut html, events);
-    html
+    html::push_html(&mut html, events.into_iter());
+    let html = sanitize_html(html);
+    FOOTNOTE_DEFINITIONS.with(|scope_definitions| {
+        if let Some(scope_definitions) = scope_definitions.borrow_mut().as_mut() {
+            scope_definitions.extend(
+                definitions
+                    .into_iter()
+                    .map(|(label, content)| (label, sanitize_html(content))),
+            );
+        }
+    });
+    match scope {
+        Some(scope) => {
+            let (html, footnotes) =
+                number_footnotes(&html, &scope.definitions(), v.footnote_prefix());
+            if footnotes.is_empty() {
+                html
+            } else {
+                html + &v.render_footnotes(&footnotes)
+            }
+        }
+        // The footnotes are left to the top-level markdown.
+        None => html,
+    }
+}
+
+thread_local! {
+    // The footnote definitions (label and rendered HTML) of the top-level markdown
+    // being rendered in current thread, `None` if nothing is rendering.
+    static FOOTNOTE_DEFINITIONS: RefCell<Option<Vec<(String, String)>>> = const { RefCell::new(None) };
+}
+
+// The scope of the top-level markdown rendering, which gathers the footnote
+// definitions of the nested markdown too. The definitions are cleared on drop.
+struct FootnoteScope;
+
+impl FootnoteScope {
+    // Enter the scope, return `None` if already inside one, which is nested markdown.
+    fn enter() -> Option<Self> {
+        FOOTNOTE_DEFINITIONS.with(|definitions| {
+            let mut definitions = definitions.borrow_mut();
+            if definitions.is_some() {
+                return None;
+            }
+            *definitions = Some(vec![]);
+            Some(FootnoteScope)
+        })
+    }
+
+    fn definitions(&self) -> Vec<(String, String)> {
+        FOOTNOTE_DEFINITIONS.with(|definitions| definitions.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+impl Drop for FootnoteScope {
+    fn drop(&mut self) {
+        FOOTNOTE_DEFINITIONS.with(|definitions| definitions.borrow_mut().take());
+    }
+}
+
+// Take the footnote definitions (label and rendered HTML) out of events, and replace
+// the references with the markers, which are numbered by [`number_footnotes`].
+fn extract_footnotes<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+) -> (Vec<Event<'a>>, Vec<(String, String)>) {
+    let mut definitions = vec![];
+    let mut body = vec![];
+    let mut definition: Option<(CowStr<'a>, Vec<Event<'a>>)> = None;
+    for event in events {
+        let event = match event {
+            Start(Tag::FootnoteDefinition(label)) => {
+                definition = Some((label, vec![]));
+                continue;
+            }
+            End(Tag::FootnoteDefinition(_)) => {
+                if let Some((label, events)) = definition.take() {
+                    let mut content = String::new();
+                    html::push_html(&mut content, events.into_iter());
+                    definitions.push((label.into_string(), content));
+                }
+                continue;
+            }
+            Event::FootnoteReference(label) => {
+                Event::Html(format!("{}{}{}", FOOTNOTE_REF_START, label, FOOTNOTE_REF_END).into())
+            }
+            event => event,
+        };
+        match definition.as_mut() {
+            Some((_, events)) => events.push(event),
+            None => body.push(event),
+        }
+    }
+    (body, definitions)
+}
+
+// Replace the reference markers in `html` with the numbered links, the references
+// without definition are kept as text. Return the HTML and the numbered footnotes.
+fn number_footnotes<'d>(
+    html: &str,
+    definitions: &'d [(String, String)],
+    prefix: &str,
+) -> (String, Vec<Footnote>) {
+    if definitions.is_empty() && !html.contains(FOOTNOTE_REF_START) {
+        return (html.to_owned(), vec![]);
+    }
+
+    // The labels in number order, and the reference count of each label.
+    let mut labels: Vec<&'d str> = vec![];
+    let mut ref_counts: HashMap<&'d str, usize> = HashMap::new();
+    let mut number_references = |html: &str, labels: &mut Vec<&'d str>| {
+        let mut output = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find(FOOTNOTE_REF_START) {
+            output.push_str(&rest[..start]);
+            let marker = &rest[start + FOOTNOTE_REF_START.len_utf8()..];
+            let (label, remaining) = marker.split_once(FOOTNOTE_REF_END).unwrap_or((marker, ""));
+            rest = remaining;
+
+            let label = match definitions.iter().find(|(defined, _)| defined == label) {
+                Some((defined, _)) => defined.as_str(),
+                None => {
+                    output.push_str(&format!("[^{}]", escape_html(label)));
+                    continue;
+                }
+            };
+            let number = match labels.iter().position(|l| *l == label) {
+                Some(index) => index + 1,
+                None => {
+                    labels.push(label);
+                    labels.len()
+                }
+            };
+            let count = ref_counts.entry(label).or_default();
+            *count += 1;
+            let id = match *count {
+                1 => format!("fnref-{}{}", prefix, number),
+                count => format!("fnref-{}{}-{}", prefix, number, count),
+            };
+            output.push_str(&format!(
+                r##"<sup class="footnote-ref"><a href="#fn-{}{}" id="{}">{}</a></sup>"##,
+                prefix, number, id, number
+            ));
+        }
+        output.push_str(rest);
+        output
+    };
+    let body = number_references(html, &mut labels);
+
+    // The definitions may reference other footnotes, which are numbered in turn.
+    let mut contents = vec![];
+    let mut index = 0;
+    loop {
+        if index == labels.len() {
+            // Number the unreferenced definitions.
+            match definitions
+                .iter()
+                .find(|(label, _)| !labels.contains(&label.as_str()))
+            {
+                Some((label, _)) => labels.push(label),
+                None => break,
+            }
+        }
+        let label = labels[index];
+        let content = definitions
+            .iter()
+            .find(|(defined, _)| defined == label)
+            .map(|(_, content)| content.as_str())
+            .unwrap_or_default();
+        contents.push(number_references(content, &mut labels));
+        index += 1;
+    }
+
+    let footnotes = labels
+        .iter()
+        .zip(contents)
+        .enumerate()
+        .map(|(index, (label, mut content))| {
+            let number = index + 1;
+            let backrefs = (1..=ref_counts.get(label).copied().unwrap_or_default())
+                .map(|count| match count {
+                    1 => format!(
+                        r##"<a href="#fnref-{}{}" class="footnote-backref" aria-label="Back to reference {}">↩</a>"##,
+                        prefix, number, number
+                    ),
+                    count => format!(
+                        r##"<a href="#fnref-{}{}-{}" class="footnote-backref" aria-label="Back to reference {}-{}">↩<sup>{}</sup></a>"##,
+                        prefix, number, count, number, count, count
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !backrefs.is_empty() {
+                // Put the back-references at the end of the last paragraph.
+                match content.strip_suffix("</p>\n") {
+                    Some(stripped) => {
+                        content = format!("{} {}</p>\n", stripped.trim_end(), backrefs)
+                    }
+                    None => content.push_str(&format!("<p>{}</p>\n", backrefs)),
+                }
+            }
+            Footnote {
+                number,
+                id: format!("fn-{}{}", prefix, number),
+                content,
+            }
+        })
+        .collect();
+    (body, footnotes)
+}
+
+// Merge the consecutive raw HTML events into one event.
+// For example, each line of an HTML block is a separate event,
+// a tag may be split into multiple events.
+fn merge_html_events<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+) -> impl Iterator<Item = Event<'a>> {
+    let mut events = events.peekable();
+    std::iter::from_fn(move || match events.next()? {
+        Event::Html(html) if matches!(events.peek(), Some(Event::Html(_))) => {
+            let mut html = html.into_string();
+            while let Some(Event::Html(next)) = events.next_if(|e| matches!(e, Event::Html(_))) {
+                html.push_str(&next);
+            }
+            Some(Event::Html(html.into()))
+        }
+        event => Some(event),
+    })
+}
+
+// Remove the paragraph around an image with title, which is the only content
+// of the paragraph, so that the image can be rendered as a figure.
+fn unwrap_figure_paragraphs<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+) -> impl Iterator<Item = Event<'a>> {
+    let mut events = events.peekable();
+    let mut buffer = std::collections::VecDeque::new();
+    std::iter::from_fn(move || {
+        if let Some(event) = buffer.pop_front() {
+            return Some(event);
+        }
+        match events.next()? {
+            Start(Tag::Paragraph) if matches!(events.peek(), Some(Start(Tag::Image(_, _, title))) if !title.is_empty()) =>
+            {
+                for event in events.by_ref() {
+                    let end = matches!(event, End(Tag::Image(..)));
+                    buffer.push_back(event);
+                    if end {
+                        break;
+                    }
+                }
+                if events
+                    .next_if(|e| matches!(e, End(Tag::Paragraph)))
+                    .is_some()
+                {
+                    buffer.pop_front()
+                } else {
+                    Some(Start(Tag::Paragraph))
+                }
+            }
+            event => Some(event),
+        }
+    })
+}
+
+/// Convert the GitHub-style alerts into callout code blocks:
+///
+/// ```markdown
+/// > [!NOTE]
+/// > Useful information.
+/// ```
+///
+/// The `IMPORTANT` and `CAUTION` alerts are mapped to `warning` and `danger` callouts.
+/// A `-` or `+` after the alert type makes the callout collapsible (collapsed or expanded),
+/// and the text after the alert type is the title.
+pub fn convert_github_alerts(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains("[!") {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut output = String::with_capacity(markdown.len());
+    let mut lines = markdown.lines().peekable();
+    let mut converted = false;
+    // The fence of current code block, the alerts inside code blocks are not converted.
+    let mut code_fence: Option<String> = None;
+    while let Some(line) = lines.next() {
+        if let Some(fence) = CODE_FENCE_REGEX
+            .captures(line)
+            .map(|caps| caps[1].to_owned())
+        {
+            match code_fence.as_ref() {
+                Some(opening) if fence.starts_with(opening.as_str()) => code_fence = None,
+                Some(_) => {}
+                None => code_fence = Some(fence),
+            }
+        }
+        let caps = match GITHUB_ALERT_REGEX.captures(line) {
+            Some(caps) if code_fence.is_none() => caps,
+            _ => {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+        };
+
+        let indent = caps.get(1).map_or("", |m| m.as_str());
+        let callout_type = match caps[2].to_ascii_lowercase().as_str() {
+            "important" => String::from("warning"),
+            "caution" => String::from("danger"),
+            callout_type => callout_type.to_owned(),
+        };
+        let mut body = vec![];
+        while let Some(next) = lines.next_if(|next| {
+            next.strip_prefix(indent)
+                .is_some_and(|next| next.starts_with('>'))
+        }) {
+            let next = &next[indent.len() + 1..];
+            body.push(next.strip_prefix(' ').unwrap_or(next));
+        }
+
+        // The fence should be longer than any fence in the body.
+        let fence_len = body
+            .iter()
+            .filter_map(|line| {
+                let tildes = line.trim_start().chars().take_while(|c| *c == '~').count();
+                (tildes >= 3).then(|| tildes + 1)
+            })
+            .max()
+            .unwrap_or(3);
+        let fence = "~".repeat(fence_len);
+
+        output.push_str(&format!(
+            "{}{}callout, type: {}",
+            indent, fence, callout_type
+        ));
+        let title = caps[4].trim();
+        if !title.is_empty() {
+            output.push_str(&format!(", title: {}", code_blocks::quote_option(title)));
+        }
+        match &caps[3] {
+            "-" => output.push_str(", collapsible: true"),
+            "+" => output.push_str(", collapsible: true, open: true"),
+            _ => {}
+        }
+        output.push('\n');
+        for line in body {
+            output.push_str(indent);
+            output.push_str(line);
+            output.push('\n');
+        }
+        output.push_str(indent);
+        output.push_str(&fence);
+        output.push('\n');
+        converted = true;
+    }
+
+    if converted {
+        Cow::Owned(output)
+    } else {
+        Cow::Borrowed(markdown)
+    }
+}
+
+/// Convert the markdown extensions before parsing, the GitHub-style alerts
+/// and the math if `math` is enabled.
+pub fn convert_extensions(markdown: &str, math: bool) -> Cow<'_, str> {
+    let markdown = convert_github_alerts(markdown);
+    if !math {
+        return markdown;
+    }
+    match convert_math(&markdown) {
+        Cow::Owned(converted) => Cow::Owned(converted),
+        Cow::Borrowed(_) => markdown,
+    }
+}
+
+/// Convert the `$...$` inline math and `$$...$$` display math into the inline code
+/// with special prefixes, see [`parse_math_code`]. Otherwise, the markdown parser would
+/// treat the `_`, `*` and `\\` in math as emphasis and escapes.
+///
+/// The inline math can't start or end with whitespace, and the closing `$` can't be
+/// followed by a digit, so that `$5 and $10` is not math. Use `\$` for a literal dollar sign.
+/// The math in code blocks, inline code and raw HTML is not converted.
+pub fn convert_math(markdown: &str) -> Cow<'_, str> {
+    if !markdown.contains('$') {
+        return Cow::Borrowed(markdown);
+    }
+
+    let mut output = String::with_capacity(markdown.len());
+    // The text outside code blocks and HTML blocks, which is converted in batch,
+    // since the display math may span multiple lines.
+    let mut text = String::new();
+    let mut code_fence: Option<String> = None;
+    // The end of current HTML block, the empty string means a blank line.
+    let mut html_end: Option<String> = None;
+    for line in markdown.lines() {
+        if let Some(end) = html_end.as_deref() {
+            output.push_str(line);
+            output.push('\n');
+            if (end.is_empty() && line.trim().is_empty())
+                || (!end.is_empty() && line.to_ascii_lowercase().contains(end))
+            {
+                html_end = None;
+            }
+            continue;
+        }
+        if let Some(fence) = CODE_FENCE_REGEX
+            .captures(line)
+            .map(|caps| caps[1].to_owned())
+        {
+            match code_fence.as_ref() {
+                Some(opening) if fence.starts_with(opening.as_str()) => {
+                    code_fence = None;
+                    output.push_str(line);
+                    output.push('\n');
+                    continue;
+                }
+                Some(_) => {}
+                None => {
+                    output.push_str(&convert_math_spans(&text));
+                    text.clear();
+                    code_fence = Some(fence);
+                }
+            }
+        } else if let Some(caps) = HTML_BLOCK_REGEX
+            .captures(line)
+            .filter(|_| code_fence.is_none())
+        {
+            output.push_str(&convert_math_spans(&text));
+            text.clear();
+            output.push_str(line);
+            output.push('\n');
+            let tag = caps[1].to_ascii_lowercase();
+            let end = match tag.as_str() {
+                "<!--" => String::from("-->"),
+                "<script" | "<pre" | "<style" | "<textarea" => format!("</{}>", &tag[1..]),
+                _ => String::new(),
+            };
+            // The comment or tag may end in the same line.
+            if end.is_empty() || !line.to_ascii_lowercase()[tag.len()..].contains(&end) {
+                html_end = Some(end);
+            }
+            continue;
+        }
+        let buf = if code_fence.is_some() {
+            &mut output
+        } else {
+            &mut text
+        };
+        buf.push_str(line);
+        buf.push('\n');
+    }
+    output.push_str(&convert_math_spans(&text));
+    Cow::Owned(output)
+}
+
+// Convert the math spans in the text without code blocks.
+fn convert_math_spans(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(['$', '`', '\\', '<']) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let len = if let Some(escaped) = rest.strip_prefix('\\') {
+            // Keep the escaped character, such as `\$`.
+            let len = 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            output.push_str(&rest[..len]);
+            len
+        } else if rest.starts_with('<') {
+            // Keep the raw HTML tag, the `$` in attributes isn't math.
+            let len = HTML_TAG_REGEX.find(rest).map_or(1, |tag| tag.end());
+            output.push_str(&rest[..len]);
+            len
+        } else if rest.starts_with('`') {
+            // Keep the inline code.
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let len = find_code_span_end(rest, ticks).unwrap_or(ticks);
+            output.push_str(&rest[..len]);
+            len
+        } else if let Some(math) = rest.strip_prefix("$$") {
+            match math.find("$$") {
+                Some(end) if !math[..end].trim().is_empty() => {
+                    output.push_str(&math_code_span(DISPLAY_MATH_PREFIX, &math[..end]));
+                    end + 4
+                }
+                _ => {
+                    output.push_str("$$");
+                    2
+                }
+            }
+        } else {
+            match find_inline_math_end(&rest[1..]) {
+                Some(end) => {
+                    output.push_str(&math_code_span(INLINE_MATH_PREFIX, &rest[1..end + 1]));
+                    end + 2
+                }
+                None => {
+                    output.push('$');
+                    1
+                }
+            }
+        };
+        rest = &rest[len..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// Find the end of inline code, which is closed by the same length of backticks.
+fn find_code_span_end(text: &str, ticks: usize) -> Option<usize> {
+    let mut pos = ticks;
+    while let Some(start) = text[pos..].find('`') {
+        let start = pos + start;
+        let len = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if len == ticks {
+            return Some(start + len);
+        }
+        pos = start + len;
+    }
+    None
+}
+
+// Find the closing `$` of the inline math `text` after the opening `$`.
+fn find_inline_math_end(text: &str) -> Option<usize> {
+    if text.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            // The inline math can't span paragraphs.
+            '\n' if text[i + 1..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('\n') =>
+            {
+                return None
+            }
+            '$' if i > 0 => {
+                let before = text[..i].ends_with(char::is_whitespace);
+                let after = chars.peek().is_some_and(|(_, c)| c.is_ascii_digit());
+                if !before && !after {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Wrap the math into inline code with the prefix, the backticks
+// of inline code are longer than any backticks in the math.
+fn math_code_span(prefix: char, math: &str) -> String {
+    let ticks = math
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(ticks + 1);
+    // The line breaks in math are converted into spaces.
+    let math = math.replace(['\r', '\n'], " ");
+    format!("{} {}{} {}", fence, prefix, math, fence)
+}
+
+/// Parse the inline code converted from math by [`convert_math`],
+/// return the math and whether it is display math.
+pub fn parse_math_code(code: &str) -> Option<(&str, bool)> {
+    if let Some(math) = code.strip_prefix(INLINE_MATH_PREFIX) {
+        Some((math, false))
+    } else {
+        code.strip_prefix(DISPLAY_MATH_PREFIX)
+            .map(|math| (math, true))
+    }
 }
 
 /// Extract the description from markdown content.
@@ -101,13 +787,19 @@ pub fn strip_markdown(markdown: &str) -> String {
     // GFM tables and tasks lists are not enabled.
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_FOOTNOTES);
 
     let parser = Parser::new_ext(markdown, options);
     let mut buffer = String::new();
+    let mut in_footnote = false;
 
     // For each event we push into the buffer to produce the 'stripped' version.
     for event in parser {
         match event {
+            // Skip the footnote definitions, the references are skipped by the last arm.
+            Start(Tag::FootnoteDefinition(_)) => in_footnote = true,
+            End(Tag::FootnoteDefinition(_)) => in_footnote = false,
+            _ if in_footnote => (),
             // The start and end events don't contain the text inside the tag.
             // That's handled by the `Event::Text` arm.
             Start(tag) => start_tag(&tag, &mut buffer),
@@ -155,11 +847,72 @@ fn fresh_line(buffer: &mut String) {
 
 #[cfg(test)]
 mod tests {
-    use std::iter;
+    use std::{cell::RefCell, iter};
 
     use super::*;
     use test_case::test_case;
 
+    #[test_case("> [!NOTE]\n> Hello **Zine**\n\nEnd", "~~~callout, type: note\nHello **Zine**\n~~~\n\nEnd\n"; "note")]
+    #[test_case("> [!caution] Be careful, please\n>\n> Hello", "~~~callout, type: danger, title: \"Be careful, please\"\n\nHello\n~~~\n"; "title")]
+    #[test_case("> [!NOTE] Say \"Hi\", C:\\\n> Hello", "~~~callout, type: note, title: \"Say \\\"Hi\\\", C:\\\\\"\nHello\n~~~\n"; "quoted title")]
+    #[test_case("> [!TIP]-\n> Hidden", "~~~callout, type: tip, collapsible: true\nHidden\n~~~\n"; "collapsed")]
+    #[test_case("- item\n  > [!WARNING]+\n  > ~~~rust\n  > fn main() {}\n  > ~~~", "- item\n  ~~~~callout, type: warning, collapsible: true, open: true\n  ~~~rust\n  fn main() {}\n  ~~~\n  ~~~~\n"; "nested")]
+    fn test_convert_github_alerts(markdown: &str, expected: &str) {
+        assert_eq!(convert_github_alerts(markdown), expected);
+    }
+
+    #[test_case("$x_1$ and $$\n\\frac{a}{b}\n$$", "` \u{E000}x_1 ` and ` \u{E001} \\frac{a}{b}  `\n"; "math")]
+    #[test_case("It costs $5 and $10, or \\$5.", "It costs $5 and $10, or \\$5.\n"; "dollars")]
+    #[test_case("`$x$` and ``a ` $y$``", "`$x$` and ``a ` $y$``\n"; "inline code")]
+    #[test_case("```\n$x$\n```\n$`$", "```\n$x$\n```\n`` \u{E000}` ``\n"; "code block")]
+    #[test_case("$x\n\ny$", "$x\n\ny$\n"; "paragraphs")]
+    #[test_case("<div data-a=\"$a$\">\n$b$\n\n</div> $c$", "<div data-a=\"$a$\">\n$b$\n\n</div> $c$\n"; "html block")]
+    #[test_case("<!--\n$a$\n\n-->\n$b$", "<!--\n$a$\n\n-->\n` \u{E000}b `\n"; "html comment")]
+    #[test_case("Hi <span title='$a$'>$b$</span> 1 < $c$", "Hi <span title='$a$'>` \u{E000}b `</span> 1 < ` \u{E000}c `\n"; "inline html")]
+    fn test_convert_math(markdown: &str, expected: &str) {
+        assert_eq!(convert_math(markdown), expected);
+    }
+
+    #[test]
+    fn test_parse_math_code() {
+        assert_eq!(parse_math_code("\u{E000}x^2"), Some(("x^2", false)));
+        assert_eq!(parse_math_code("\u{E001}x^2"), Some(("x^2", true)));
+        assert_eq!(parse_math_code("x^2"), None);
+    }
+
+    #[test_case("> [!UNKNOWN]\n> Hello"; "unknown type")]
+    #[test_case("> Hello [!NOTE]"; "not first")]
+    #[test_case("```markdown\n> [!NOTE]\n> Hello\n```"; "in code block")]
+    fn test_not_convert_github_alerts(markdown: &str) {
+        assert!(matches!(convert_github_alerts(markdown), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_markdown_visit_merged_html() {
+        struct HtmlVisitor<'v>(&'v RefCell<Vec<String>>);
+        impl<'a, 'v> MarkdownVisitor<'a> for HtmlVisitor<'v> {
+            fn visit_html(&mut self, html: &CowStr<'a>) -> Visiting {
+                self.0.borrow_mut().push(html.to_string());
+                Visiting::Ignore
+            }
+        }
+
+        let visited = RefCell::new(vec![]);
+        let html = markdown_to_html(
+            "<div\nclass=\"note\">\n<script>alert(1)</script>\n</div>\n\nHello <b>Zine</b>",
+            HtmlVisitor(&visited),
+        );
+        assert_eq!("<p>Hello Zine</p>\n", html);
+        assert_eq!(
+            vec![
+                "<div\nclass=\"note\">\n<script>alert(1)</script>\n</div>\n",
+                "<b>",
+                "</b>"
+            ],
+            visited.into_inner()
+        );
+    }
+
     #[test]
     fn test_markdown_visitor() {
         struct NopVisitor;
@@ -209,6 +962,56 @@ mod tests {
         assert_eq!("<p>DummyVisitor</p>\n", html);
     }
 
+    #[test]
+    fn test_unwrap_figure_paragraphs() {
+        struct NopVisitor;
+        impl<'a> MarkdownVisitor<'a> for NopVisitor {}
+
+        let html = markdown_to_html(
+            "![alt](a.png \"Title\")\n\n![alt](b.png \"Title\") text\n\n![alt](c.png)",
+            NopVisitor,
+        );
+        assert_eq!(
+            html,
+            r#"<img src="a.png" alt="alt" title="Title" />
+<p><img src="b.png" alt="alt" title="Title" /> text</p>
+<p><img src="c.png" alt="alt" /></p>
+"#
+        );
+    }
+
+    #[test]
+    fn test_markdown_footnotes() {
+        struct NopVisitor;
+        impl<'a> MarkdownVisitor<'a> for NopVisitor {}
+
+        let html = markdown_to_html(
+            "Hello[^b] Zine[^a][^b] and[^none].\n\n[^a]: First[^c].\n[^b]: Second.\n[^c]: Third.\n[^d]: Unused.",
+            NopVisitor,
+        );
+        assert_eq!(
+            html,
+            r##"<p>Hello<sup class="footnote-ref"><a href="#fn-1" id="fnref-1">1</a></sup> Zine<sup class="footnote-ref"><a href="#fn-2" id="fnref-2">2</a></sup><sup class="footnote-ref"><a href="#fn-1" id="fnref-1-2">1</a></sup> and[^none].</p>
+<section class="footnotes">
+<ol>
+<li id="fn-1">
+<p>Second. <a href="#fnref-1" class="footnote-backref" aria-label="Back to reference 1">↩</a> <a href="#fnref-1-2" class="footnote-backref" aria-label="Back to reference 1-2">↩<sup>2</sup></a></p>
+</li>
+<li id="fn-2">
+<p>First<sup class="footnote-ref"><a href="#fn-3" id="fnref-3">3</a></sup>. <a href="#fnref-2" class="footnote-backref" aria-label="Back to reference 2">↩</a></p>
+</li>
+<li id="fn-3">
+<p>Third. <a href="#fnref-3" class="footnote-backref" aria-label="Back to reference 3">↩</a></p>
+</li>
+<li id="fn-4">
+<p>Unused.</p>
+</li>
+</ol>
+</section>
+"##
+        );
+    }
+
     #[test_case("aaaa"; "case1")]
     fn test_extract_decription1(markdown: &str) {
         assert_eq!("aaaa", extract_description(markdown));
@@ -349,6 +1152,14 @@ beta
         assert_eq!(strip_markdown(markdown), expected)
     }
 
+    #[test]
+    fn footnote() {
+        let markdown = "Hello[^1] Zine.\n\n[^1]: The footnote.";
+        let expected = "Hello Zine.";
+        assert_eq!(strip_markdown(markdown), expected);
+        assert_eq!(extract_description("[^1]: The footnote.\nHello"), "Hello");
+    }
+
     #[ignore]
     #[test]
     fn link_with_itself() {
@@ -0,0 +1,927 @@
+use std::fmt::Write;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::html::escape_html;
+
+/// Render the LaTeX `math` into MathML, so that the math is displayed
+/// without any client-side JavaScript.
+///
+/// Only the commonly used subset of LaTeX math is supported, such as
+/// fractions, roots, scripts, accents, fonts, matrices and most symbols.
+/// The LaTeX source is kept in the `<annotation>` element.
+pub fn latex_to_mathml(math: &str, display: bool) -> Result<String> {
+    let mut html = String::new();
+    if display {
+        html.push_str(r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="block">"#);
+    } else {
+        html.push_str(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#);
+    }
+    html.push_str("<semantics>");
+    html.push_str(&render_mrow(math, display)?);
+    write!(
+        &mut html,
+        r#"<annotation encoding="application/x-tex">{}</annotation>"#,
+        escape_html(math.trim())
+    )?;
+    html.push_str("</semantics></math>");
+    Ok(html)
+}
+
+fn render_mrow(math: &str, display: bool) -> Result<String> {
+    let mut parser = Parser {
+        chars: math.chars().collect(),
+        pos: 0,
+        display,
+    };
+    let (nodes, ending) = parser.parse_list(false)?;
+    match ending {
+        Ending::Eof => {}
+        Ending::Brace => bail!("Unexpected `}}`"),
+        Ending::Right => bail!("Unexpected `\\right`"),
+        Ending::End(env) => bail!("Unexpected `\\end{{{}}}`", env),
+        Ending::Cell | Ending::Row => unreachable!("Only in table"),
+    }
+    let mut html = String::new();
+    Node::Row(nodes).write(&mut html);
+    Ok(html)
+}
+
+enum Node {
+    // An identifier in italic if it is a single letter, such as `x` and `\alpha`.
+    Ident(String),
+    // An upright identifier, such as `\Gamma` and `\mathrm{d}`.
+    Upright(String),
+    Number(String),
+    Operator(String),
+    // A stretchy delimiter, such as `\left(`.
+    Fence(String),
+    // A large operator, whether it places the scripts under and over in display mode.
+    LargeOp(String, bool),
+    // A function name, whether it places the scripts under in display mode, such as `\lim`.
+    Function(String, bool),
+    Text(String),
+    Space(&'static str),
+    Row(Vec<Node>),
+    Frac(Box<Node>, Box<Node>, bool),
+    Sqrt(Box<Node>),
+    Root(Box<Node>, Box<Node>),
+    Scripts {
+        base: Box<Node>,
+        sub: Option<Box<Node>>,
+        sup: Option<Box<Node>>,
+        limits: bool,
+    },
+    // The accent or line over the base, whether it is an accent.
+    Over(Box<Node>, Box<Node>, bool),
+    Under(Box<Node>, Box<Node>),
+    Table(Vec<Vec<Node>>, &'static str),
+}
+
+impl Node {
+    fn op(op: &str) -> Node {
+        Node::Operator(op.to_owned())
+    }
+
+    // Whether the scripts are placed under and over the base in display mode.
+    fn takes_limits(&self) -> bool {
+        matches!(self, Node::LargeOp(_, true) | Node::Function(_, true))
+    }
+
+    fn write(&self, html: &mut String) {
+        match self {
+            Node::Ident(ident) => write_element(html, "mi", "", ident),
+            Node::Upright(ident) => write_element(html, "mi", r#" mathvariant="normal""#, ident),
+            Node::Number(number) => write_element(html, "mn", "", number),
+            Node::Operator(op) => write_element(html, "mo", "", op),
+            Node::Fence(op) => write_element(html, "mo", r#" fence="true" stretchy="true""#, op),
+            Node::LargeOp(op, _) => write_element(html, "mo", r#" largeop="true""#, op),
+            Node::Function(name, _) => write_element(html, "mi", "", name),
+            Node::Text(text) => write_element(html, "mtext", "", text),
+            Node::Space(width) => {
+                let _ = write!(html, r#"<mspace width="{}"/>"#, width);
+            }
+            Node::Row(nodes) => {
+                // A single node needn't to be wrapped.
+                if let [node] = nodes.as_slice() {
+                    return node.write(html);
+                }
+                html.push_str("<mrow>");
+                nodes.iter().for_each(|node| node.write(html));
+                html.push_str("</mrow>");
+            }
+            Node::Frac(numerator, denominator, line) => {
+                html.push_str(if *line {
+                    "<mfrac>"
+                } else {
+                    r#"<mfrac linethickness="0">"#
+                });
+                numerator.write(html);
+                denominator.write(html);
+                html.push_str("</mfrac>");
+            }
+            Node::Sqrt(node) => {
+                html.push_str("<msqrt>");
+                node.write(html);
+                html.push_str("</msqrt>");
+            }
+            Node::Root(index, node) => {
+                html.push_str("<mroot>");
+                node.write(html);
+                index.write(html);
+                html.push_str("</mroot>");
+            }
+            Node::Scripts {
+                base,
+                sub,
+                sup,
+                limits,
+            } => {
+                let tag = match (sub, sup, limits) {
+                    (Some(_), Some(_), true) => "munderover",
+                    (Some(_), None, true) => "munder",
+                    (None, _, true) => "mover",
+                    (Some(_), Some(_), false) => "msubsup",
+                    (Some(_), None, false) => "msub",
+                    (None, _, false) => "msup",
+                };
+                let _ = write!(html, "<{}>", tag);
+                base.write(html);
+                sub.iter().chain(sup).for_each(|node| node.write(html));
+                let _ = write!(html, "</{}>", tag);
+            }
+            Node::Over(base, over, accent) => {
+                html.push_str(if *accent {
+                    r#"<mover accent="true">"#
+                } else {
+                    "<mover>"
+                });
+                base.write(html);
+                over.write(html);
+                html.push_str("</mover>");
+            }
+            Node::Under(base, under) => {
+                html.push_str("<munder>");
+                base.write(html);
+                under.write(html);
+                html.push_str("</munder>");
+            }
+            Node::Table(rows, columnalign) => {
+                let _ = write!(html, r#"<mtable columnalign="{}">"#, columnalign);
+                for row in rows {
+                    html.push_str("<mtr>");
+                    for cell in row {
+                        html.push_str("<mtd>");
+                        cell.write(html);
+                        html.push_str("</mtd>");
+                    }
+                    html.push_str("</mtr>");
+                }
+                html.push_str("</mtable>");
+            }
+        }
+    }
+
+    // Apply the font variant, such as `\mathbf` and `\mathbb`.
+    fn with_variant(self, variant: Variant) -> Node {
+        let map = |text: String| text.chars().map(|c| variant.map(c)).collect::<String>();
+        match self {
+            Node::Ident(ident) | Node::Upright(ident) if variant == Variant::Normal => {
+                Node::Upright(ident)
+            }
+            Node::Ident(ident) | Node::Upright(ident) => Node::Ident(map(ident)),
+            Node::Number(number) => Node::Number(map(number)),
+            Node::Row(nodes) => Node::Row(
+                nodes
+                    .into_iter()
+                    .map(|node| node.with_variant(variant))
+                    .collect(),
+            ),
+            node => node,
+        }
+    }
+}
+
+fn write_element(html: &mut String, tag: &str, attrs: &str, text: &str) {
+    let _ = write!(html, "<{}{}>{}</{}>", tag, attrs, escape_html(text), tag);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Normal,
+    Bold,
+    BoldItalic,
+    DoubleStruck,
+    Script,
+    Fraktur,
+    SansSerif,
+    Monospace,
+}
+
+impl Variant {
+    fn parse(command: &str) -> Option<Self> {
+        match command {
+            "mathrm" => Some(Variant::Normal),
+            "mathbf" => Some(Variant::Bold),
+            "boldsymbol" | "bm" => Some(Variant::BoldItalic),
+            "mathbb" => Some(Variant::DoubleStruck),
+            "mathcal" | "mathscr" => Some(Variant::Script),
+            "mathfrak" => Some(Variant::Fraktur),
+            "mathsf" => Some(Variant::SansSerif),
+            "mathtt" => Some(Variant::Monospace),
+            _ => None,
+        }
+    }
+
+    // Map the ASCII letters and digits into the Mathematical Alphanumeric Symbols.
+    fn map(self, c: char) -> char {
+        // The letters of the variant in the Letterlike Symbols block.
+        let exception = match (self, c) {
+            (Variant::DoubleStruck, 'C') => Some('ℂ'),
+            (Variant::DoubleStruck, 'H') => Some('ℍ'),
+            (Variant::DoubleStruck, 'N') => Some('ℕ'),
+            (Variant::DoubleStruck, 'P') => Some('ℙ'),
+            (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+            (Variant::DoubleStruck, 'R') => Some('ℝ'),
+            (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+            (Variant::Script, 'B') => Some('ℬ'),
+            (Variant::Script, 'E') => Some('ℰ'),
+            (Variant::Script, 'F') => Some('ℱ'),
+            (Variant::Script, 'H') => Some('ℋ'),
+            (Variant::Script, 'I') => Some('ℐ'),
+            (Variant::Script, 'L') => Some('ℒ'),
+            (Variant::Script, 'M') => Some('ℳ'),
+            (Variant::Script, 'R') => Some('ℛ'),
+            (Variant::Script, 'e') => Some('ℯ'),
+            (Variant::Script, 'g') => Some('ℊ'),
+            (Variant::Script, 'o') => Some('ℴ'),
+            (Variant::Fraktur, 'C') => Some('ℭ'),
+            (Variant::Fraktur, 'H') => Some('ℌ'),
+            (Variant::Fraktur, 'I') => Some('ℑ'),
+            (Variant::Fraktur, 'R') => Some('ℜ'),
+            (Variant::Fraktur, 'Z') => Some('ℨ'),
+            _ => None,
+        };
+        if let Some(exception) = exception {
+            return exception;
+        }
+
+        let (upper, lower, digit) = match self {
+            Variant::Normal => return c,
+            Variant::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+            Variant::BoldItalic => (0x1D468, 0x1D482, Some(0x1D7CE)),
+            Variant::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+            Variant::Script => (0x1D49C, 0x1D4B6, None),
+            Variant::Fraktur => (0x1D504, 0x1D51E, None),
+            Variant::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+            Variant::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+        };
+        let code = match c {
+            'A'..='Z' => upper + (c as u32 - 'A' as u32),
+            'a'..='z' => lower + (c as u32 - 'a' as u32),
+            '0'..='9' => match digit {
+                Some(digit) => digit + (c as u32 - '0' as u32),
+                None => return c,
+            },
+            _ => return c,
+        };
+        char::from_u32(code).unwrap_or(c)
+    }
+}
+
+// How a list of nodes ends.
+enum Ending {
+    Eof,
+    Brace,
+    Right,
+    End(String),
+    // The `&` column separator in table.
+    Cell,
+    // The `\\` row separator in table.
+    Row,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    display: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => bail!("Expect `{}`", expected),
+        }
+    }
+
+    // Read the command name after the backslash, which is either
+    // the ASCII letters or a single non-letter character.
+    fn command_name(&mut self) -> Result<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            self.next()
+                .map(String::from)
+                .ok_or_else(|| anyhow!("Unexpected end after `\\`"))
+        } else {
+            Ok(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    // Read the raw text in braces, such as the argument of `\text`.
+    fn raw_group(&mut self) -> Result<String> {
+        self.expect('{')?;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => self.pos += 1,
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(self.chars[start..self.pos - 1].iter().collect()),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        bail!("Missing `}}`")
+    }
+
+    fn parse_list(&mut self, in_table: bool) -> Result<(Vec<Node>, Ending)> {
+        let mut nodes = vec![];
+        loop {
+            self.skip_whitespace();
+            let ending = match self.peek() {
+                None => Some(Ending::Eof),
+                Some('}') => {
+                    self.pos += 1;
+                    Some(Ending::Brace)
+                }
+                Some('&') => {
+                    self.pos += 1;
+                    // The alignment outside table is ignored.
+                    in_table.then_some(Ending::Cell)
+                }
+                Some('\\') => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    match self.command_name()?.as_str() {
+                        // The line break outside table is ignored.
+                        "\\" | "cr" => in_table.then_some(Ending::Row),
+                        "right" => Some(Ending::Right),
+                        "end" => Some(Ending::End(self.raw_group()?)),
+                        _ => {
+                            self.pos = start;
+                            if let Some(node) = self.parse_scripts()? {
+                                nodes.push(node);
+                            }
+                            None
+                        }
+                    }
+                }
+                Some(_) => {
+                    if let Some(node) = self.parse_scripts()? {
+                        nodes.push(node);
+                    }
+                    continue;
+                }
+            };
+            if let Some(ending) = ending {
+                return Ok((nodes, ending));
+            }
+        }
+    }
+
+    // Parse an atom with its subscript, superscript and primes.
+    fn parse_scripts(&mut self) -> Result<Option<Node>> {
+        let base = match self.parse_atom()? {
+            Some(base) => base,
+            None => return Ok(None),
+        };
+        let mut sub = None;
+        let mut sup: Option<Node> = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if sub.is_none() => {
+                    self.pos += 1;
+                    sub = Some(self.parse_argument()?);
+                }
+                Some('^') if sup.is_none() => {
+                    self.pos += 1;
+                    sup = Some(self.parse_argument()?);
+                }
+                Some('\'') if sup.is_none() => {
+                    let mut primes = String::new();
+                    while self.peek() == Some('\'') {
+                        self.pos += 1;
+                        primes.push('′');
+                    }
+                    sup = Some(Node::Operator(primes));
+                }
+                Some('\\') => {
+                    // The `\limits` and `\nolimits` after the base are ignored.
+                    let start = self.pos;
+                    self.pos += 1;
+                    if !matches!(self.command_name()?.as_str(), "limits" | "nolimits") {
+                        self.pos = start;
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+        if sub.is_none() && sup.is_none() {
+            return Ok(Some(base));
+        }
+        let limits = self.display && base.takes_limits();
+        Ok(Some(Node::Scripts {
+            base: Box::new(base),
+            sub: sub.map(Box::new),
+            sup: sup.map(Box::new),
+            limits,
+        }))
+    }
+
+    // Parse the argument of commands and scripts, which is a group or a single token.
+    fn parse_argument(&mut self) -> Result<Node> {
+        self.skip_whitespace();
+        match self.peek() {
+            // Only a single digit is the argument, such as `\frac12`.
+            Some(c) if c.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(Node::Number(c.to_string()))
+            }
+            Some(_) => self
+                .parse_atom()?
+                .ok_or_else(|| anyhow!("Missing argument")),
+            None => bail!("Missing argument"),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Option<Node>> {
+        self.skip_whitespace();
+        let c = match self.next() {
+            Some(c) => c,
+            None => bail!("Unexpected end"),
+        };
+        let node = match c {
+            '{' => match self.parse_list(false)? {
+                (nodes, Ending::Brace) => Node::Row(nodes),
+                _ => bail!("Missing `}}`"),
+            },
+            '\\' => {
+                let name = self.command_name()?;
+                return self.parse_command(&name);
+            }
+            '0'..='9' | '.' if c != '.' || self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                let mut number = c.to_string();
+                while let Some(c) = self.peek() {
+                    let is_decimal_point = c == '.'
+                        && !number.contains('.')
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(char::is_ascii_digit);
+                    if !c.is_ascii_digit() && !is_decimal_point {
+                        break;
+                    }
+                    number.push(c);
+                    self.pos += 1;
+                }
+                Node::Number(number)
+            }
+            '-' => Node::op("−"),
+            '*' => Node::op("∗"),
+            '~' => Node::Space("0.333em"),
+            '}' => bail!("Unexpected `}}`"),
+            '^' | '_' => bail!("Unexpected `{}`", c),
+            c if c.is_alphabetic() => Node::Ident(c.to_string()),
+            c => Node::Operator(c.to_string()),
+        };
+        Ok(Some(node))
+    }
+
+    fn parse_command(&mut self, name: &str) -> Result<Option<Node>> {
+        if let Some(node) = symbol(name) {
+            return Ok(Some(node));
+        }
+        if let Some(variant) = Variant::parse(name) {
+            return Ok(Some(self.parse_argument()?.with_variant(variant)));
+        }
+        if let Some((accent, is_accent)) = accent(name) {
+            let base = self.parse_argument()?;
+            return Ok(Some(Node::Over(
+                Box::new(base),
+                Box::new(Node::op(accent)),
+                is_accent,
+            )));
+        }
+
+        let node = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                Node::Frac(Box::new(numerator), Box::new(denominator), true)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.parse_argument()?;
+                let k = self.parse_argument()?;
+                Node::Row(vec![
+                    Node::op("("),
+                    Node::Frac(Box::new(n), Box::new(k), false),
+                    Node::op(")"),
+                ])
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let start = self.pos;
+                    while self.peek().is_some_and(|c| c != ']') {
+                        self.pos += 1;
+                    }
+                    self.expect(']')?;
+                    let index: String = self.chars[start..self.pos - 1].iter().collect();
+                    let index = parse_nodes(&index, self.display)?;
+                    Node::Root(Box::new(index), Box::new(self.parse_argument()?))
+                } else {
+                    Node::Sqrt(Box::new(self.parse_argument()?))
+                }
+            }
+            "left" => {
+                let open = self.parse_delimiter()?;
+                let (mut nodes, ending) = self.parse_list(false)?;
+                if !matches!(ending, Ending::Right) {
+                    bail!("Missing `\\right`");
+                }
+                let close = self.parse_delimiter()?;
+                nodes.insert(0, Node::Fence(open));
+                nodes.push(Node::Fence(close));
+                Node::Row(nodes)
+            }
+            "underline" => {
+                let base = self.parse_argument()?;
+                Node::Under(Box::new(base), Box::new(Node::op("_")))
+            }
+            "underbrace" => {
+                let base = self.parse_argument()?;
+                Node::Under(Box::new(base), Box::new(Node::op("⏟")))
+            }
+            // The letters are italic by default.
+            "mathit" | "mathnormal" => self.parse_argument()?,
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => Node::Text(self.raw_group()?),
+            "operatorname" => Node::Function(self.raw_group()?, false),
+            "begin" => {
+                let env = self.raw_group()?;
+                self.parse_environment(&env)?
+            }
+            "," | "thinspace" => Node::Space("0.1667em"),
+            ":" | ">" | "medspace" => Node::Space("0.2222em"),
+            ";" | "thickspace" => Node::Space("0.2778em"),
+            "!" | "negthinspace" => Node::Space("-0.1667em"),
+            " " => Node::Space("0.25em"),
+            "quad" => Node::Space("1em"),
+            "qquad" => Node::Space("2em"),
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => {
+                return Ok(None)
+            }
+            "$" | "%" | "&" | "#" | "_" => Node::op(name),
+            _ => bail!("Unsupported command `\\{}`", name),
+        };
+        Ok(Some(node))
+    }
+
+    // Parse the delimiter after `\left` and `\right`, `.` is the empty delimiter.
+    fn parse_delimiter(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some('.') => Ok(String::new()),
+            Some('\\') => match symbol(&self.command_name()?) {
+                Some(Node::Operator(op)) => Ok(op),
+                _ => bail!("Invalid delimiter"),
+            },
+            Some(c) if !c.is_alphanumeric() => Ok(c.to_string()),
+            _ => bail!("Invalid delimiter"),
+        }
+    }
+
+    fn parse_environment(&mut self, env: &str) -> Result<Node> {
+        let (open, close, columnalign) = match env {
+            "matrix" | "smallmatrix" => ("", "", "center"),
+            "pmatrix" => ("(", ")", "center"),
+            "bmatrix" => ("[", "]", "center"),
+            "Bmatrix" => ("{", "}", "center"),
+            "vmatrix" => ("|", "|", "center"),
+            "Vmatrix" => ("‖", "‖", "center"),
+            "cases" => ("{", "", "left left"),
+            "aligned" | "align" | "align*" | "split" | "alignedat" => ("", "", "right left"),
+            "gathered" | "gather" | "gather*" => ("", "", "center"),
+            "array" => {
+                // The column specification is ignored.
+                self.raw_group()?;
+                ("", "", "center")
+            }
+            _ => bail!("Unsupported environment `{}`", env),
+        };
+
+        let mut rows = vec![];
+        let mut row = vec![];
+        loop {
+            let (nodes, ending) = self.parse_list(true)?;
+            row.push(Node::Row(nodes));
+            match ending {
+                Ending::Cell => {}
+                Ending::Row => rows.push(std::mem::take(&mut row)),
+                Ending::End(end) if end == env => break,
+                _ => bail!("Missing `\\end{{{}}}`", env),
+            }
+        }
+        // Skip the empty last row after the trailing `\\`.
+        if !matches!(row.as_slice(), [Node::Row(nodes)] if nodes.is_empty()) {
+            rows.push(row);
+        }
+
+        let table = Node::Table(rows, columnalign);
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+        let mut nodes = vec![Node::Fence(open.to_owned()), table];
+        if !close.is_empty() {
+            nodes.push(Node::Fence(close.to_owned()));
+        }
+        Ok(Node::Row(nodes))
+    }
+}
+
+// Parse the standalone math, such as the index of `\sqrt[3]{x}`.
+fn parse_nodes(math: &str, display: bool) -> Result<Node> {
+    let mut parser = Parser {
+        chars: math.chars().collect(),
+        pos: 0,
+        display,
+    };
+    match parser.parse_list(false)? {
+        (nodes, Ending::Eof) => Ok(Node::Row(nodes)),
+        _ => bail!("Invalid math `{}`", math),
+    }
+}
+
+// The accent commands, return the accent and whether it is an accent.
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    let accent = match name {
+        "hat" | "widehat" => ("^", true),
+        "bar" => ("¯", true),
+        "overline" => ("‾", false),
+        "vec" => ("→", true),
+        "overrightarrow" => ("→", false),
+        "overleftarrow" => ("←", false),
+        "dot" => ("˙", true),
+        "ddot" => ("¨", true),
+        "tilde" | "widetilde" => ("~", true),
+        "check" => ("ˇ", true),
+        "breve" => ("˘", true),
+        "acute" => ("´", true),
+        "grave" => ("`", true),
+        "overbrace" => ("⏞", false),
+        _ => return None,
+    };
+    Some(accent)
+}
+
+// The symbol commands, such as Greek letters, operators and relations.
+fn symbol(name: &str) -> Option<Node> {
+    let ident = |ident: &str| Some(Node::Ident(ident.to_owned()));
+    let upright = |ident: &str| Some(Node::Upright(ident.to_owned()));
+    let op = |op: &str| Some(Node::op(op));
+    let large_op = |op: &str, limits| Some(Node::LargeOp(op.to_owned(), limits));
+    match name {
+        // Greek letters
+        "alpha" => ident("α"),
+        "beta" => ident("β"),
+        "gamma" => ident("γ"),
+        "delta" => ident("δ"),
+        "epsilon" => ident("ϵ"),
+        "varepsilon" => ident("ε"),
+        "zeta" => ident("ζ"),
+        "eta" => ident("η"),
+        "theta" => ident("θ"),
+        "vartheta" => ident("ϑ"),
+        "iota" => ident("ι"),
+        "kappa" => ident("κ"),
+        "lambda" => ident("λ"),
+        "mu" => ident("μ"),
+        "nu" => ident("ν"),
+        "xi" => ident("ξ"),
+        "pi" => ident("π"),
+        "varpi" => ident("ϖ"),
+        "rho" => ident("ρ"),
+        "varrho" => ident("ϱ"),
+        "sigma" => ident("σ"),
+        "varsigma" => ident("ς"),
+        "tau" => ident("τ"),
+        "upsilon" => ident("υ"),
+        "phi" => ident("ϕ"),
+        "varphi" => ident("φ"),
+        "chi" => ident("χ"),
+        "psi" => ident("ψ"),
+        "omega" => ident("ω"),
+        "Gamma" => upright("Γ"),
+        "Delta" => upright("Δ"),
+        "Theta" => upright("Θ"),
+        "Lambda" => upright("Λ"),
+        "Xi" => upright("Ξ"),
+        "Pi" => upright("Π"),
+        "Sigma" => upright("Σ"),
+        "Upsilon" => upright("Υ"),
+        "Phi" => upright("Φ"),
+        "Psi" => upright("Ψ"),
+        "Omega" => upright("Ω"),
+        // Other letters
+        "infty" => ident("∞"),
+        "partial" => ident("∂"),
+        "nabla" => ident("∇"),
+        "ell" => ident("ℓ"),
+        "hbar" => ident("ℏ"),
+        "imath" => ident("ı"),
+        "jmath" => ident("ȷ"),
+        "Re" => ident("ℜ"),
+        "Im" => ident("ℑ"),
+        "aleph" => ident("ℵ"),
+        "wp" => ident("℘"),
+        "emptyset" | "varnothing" => ident("∅"),
+        "top" => ident("⊤"),
+        "bot" => ident("⊥"),
+        "angle" => ident("∠"),
+        "triangle" => ident("△"),
+        // Binary operators
+        "pm" => op("±"),
+        "mp" => op("∓"),
+        "times" => op("×"),
+        "div" => op("÷"),
+        "cdot" => op("⋅"),
+        "ast" => op("∗"),
+        "star" => op("⋆"),
+        "circ" => op("∘"),
+        "bullet" => op("∙"),
+        "oplus" => op("⊕"),
+        "ominus" => op("⊖"),
+        "otimes" => op("⊗"),
+        "odot" => op("⊙"),
+        "cap" => op("∩"),
+        "cup" => op("∪"),
+        "wedge" | "land" => op("∧"),
+        "vee" | "lor" => op("∨"),
+        "setminus" => op("∖"),
+        "neg" | "lnot" => op("¬"),
+        "forall" => op("∀"),
+        "exists" => op("∃"),
+        "nexists" => op("∄"),
+        "prime" => op("′"),
+        // Relations
+        "leq" | "le" => op("≤"),
+        "geq" | "ge" => op("≥"),
+        "neq" | "ne" => op("≠"),
+        "approx" => op("≈"),
+        "equiv" => op("≡"),
+        "sim" => op("∼"),
+        "simeq" => op("≃"),
+        "cong" => op("≅"),
+        "propto" => op("∝"),
+        "ll" => op("≪"),
+        "gg" => op("≫"),
+        "prec" => op("≺"),
+        "succ" => op("≻"),
+        "subset" => op("⊂"),
+        "supset" => op("⊃"),
+        "subseteq" => op("⊆"),
+        "supseteq" => op("⊇"),
+        "in" => op("∈"),
+        "notin" => op("∉"),
+        "ni" => op("∋"),
+        "perp" => op("⊥"),
+        "parallel" => op("∥"),
+        "mid" => op("∣"),
+        "vdash" => op("⊢"),
+        "models" => op("⊨"),
+        "coloneqq" => op("≔"),
+        // Arrows
+        "to" | "rightarrow" => op("→"),
+        "gets" | "leftarrow" => op("←"),
+        "leftrightarrow" => op("↔"),
+        "Rightarrow" => op("⇒"),
+        "Leftarrow" => op("⇐"),
+        "Leftrightarrow" => op("⇔"),
+        "implies" => op("⟹"),
+        "impliedby" => op("⟸"),
+        "iff" => op("⟺"),
+        "mapsto" => op("↦"),
+        "uparrow" => op("↑"),
+        "downarrow" => op("↓"),
+        "longrightarrow" => op("⟶"),
+        "longleftarrow" => op("⟵"),
+        // Dots
+        "ldots" | "dots" => op("…"),
+        "cdots" => op("⋯"),
+        "vdots" => op("⋮"),
+        "ddots" => op("⋱"),
+        // Delimiters
+        "langle" => op("⟨"),
+        "rangle" => op("⟩"),
+        "lfloor" => op("⌊"),
+        "rfloor" => op("⌋"),
+        "lceil" => op("⌈"),
+        "rceil" => op("⌉"),
+        "lvert" | "rvert" | "vert" => op("|"),
+        "lVert" | "rVert" | "Vert" | "|" => op("‖"),
+        "backslash" => op("\\"),
+        "{" | "lbrace" => op("{"),
+        "}" | "rbrace" => op("}"),
+        // Large operators
+        "sum" => large_op("∑", true),
+        "prod" => large_op("∏", true),
+        "coprod" => large_op("∐", true),
+        "bigcup" => large_op("⋃", true),
+        "bigcap" => large_op("⋂", true),
+        "bigoplus" => large_op("⨁", true),
+        "bigotimes" => large_op("⨂", true),
+        "bigvee" => large_op("⋁", true),
+        "bigwedge" => large_op("⋀", true),
+        "int" => large_op("∫", false),
+        "iint" => large_op("∬", false),
+        "iiint" => large_op("∭", false),
+        "oint" => large_op("∮", false),
+        // Functions
+        "lim" | "limsup" | "liminf" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr"
+        | "argmax" | "argmin" => Some(Node::Function(
+            match name {
+                "limsup" => String::from("lim sup"),
+                "liminf" => String::from("lim inf"),
+                "argmax" => String::from("arg max"),
+                "argmin" => String::from("arg min"),
+                name => name.to_owned(),
+            },
+            true,
+        )),
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "dim" | "ker" | "deg"
+        | "arg" | "hom" | "mod" => Some(Node::Function(name.to_owned(), false)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{latex_to_mathml, render_mrow};
+
+    #[test_case("x^2 + 1", "<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mn>1</mn></mrow>"; "scripts")]
+    #[test_case(r"\frac{a}{2b}", "<mfrac><mi>a</mi><mrow><mn>2</mn><mi>b</mi></mrow></mfrac>"; "frac")]
+    #[test_case(r"\frac12", "<mfrac><mn>1</mn><mn>2</mn></mfrac>"; "frac digits")]
+    #[test_case(r"\sqrt[3]{x}", "<mroot><mi>x</mi><mn>3</mn></mroot>"; "root")]
+    #[test_case(r"x_i'", "<msubsup><mi>x</mi><mi>i</mi><mo>′</mo></msubsup>"; "prime")]
+    #[test_case(r"\sum_{i=1}^n i", "<mrow><munderover><mo largeop=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><mi>i</mi></mrow>"; "large operator")]
+    #[test_case(r"\mathbb{R} \mathrm{d}x", "<mrow><mi>ℝ</mi><mi mathvariant=\"normal\">d</mi><mi>x</mi></mrow>"; "variant")]
+    #[test_case(r"\left( \alpha \right]", "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>α</mi><mo fence=\"true\" stretchy=\"true\">]</mo></mrow>"; "left right")]
+    #[test_case(r"\begin{pmatrix} 1 & 0 \\ 0 & 1 \\ \end{pmatrix}", "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable columnalign=\"center\"><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"; "matrix")]
+    #[test_case(r"a < \text{b & c}", "<mrow><mi>a</mi><mo>&lt;</mo><mtext>b &amp; c</mtext></mrow>"; "escape")]
+    fn test_render_mrow(math: &str, expected: &str) {
+        assert_eq!(render_mrow(math, true).unwrap(), expected);
+    }
+
+    #[test_case(r"\unknown"; "unknown command")]
+    #[test_case(r"\frac{a}{b"; "missing brace")]
+    #[test_case(r"a}"; "unexpected brace")]
+    #[test_case(r"\left( a"; "missing right")]
+    #[test_case(r"\begin{matrix} a"; "missing end")]
+    fn test_render_invalid_math(math: &str) {
+        assert!(render_mrow(math, false).is_err());
+    }
+
+    #[test]
+    fn test_latex_to_mathml() {
+        assert_eq!(
+            latex_to_mathml(" \\lim_{x \\to 0} x ", false).unwrap(),
+            r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><semantics><mrow><msub><mi>lim</mi><mrow><mi>x</mi><mo>→</mo><mn>0</mn></mrow></msub><mi>x</mi></mrow><annotation encoding="application/x-tex">\lim_{x \to 0} x</annotation></semantics></math>"#
+        );
+    }
+}
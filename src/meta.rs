@@ -10,14 +10,3 @@ pub struct Meta<'a> {
     pub url: Option<Cow<'a, str>>,
     pub image: Option<Cow<'a, str>>,
 }
-
-impl<'a> Meta<'a> {
-    pub fn is_filled(&self) -> bool {
-        !self.title.is_empty() && !self.description.is_empty()
-    }
-
-    pub fn truncate(&mut self) {
-        self.title.to_mut().truncate(200);
-        self.description.to_mut().truncate(200);
-    }
-}
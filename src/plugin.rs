@@ -0,0 +1,333 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{data, error::PluginError, helpers::content_hash};
+
+/// The external plugin declared in the `[[plugin]]` table of root `zine.toml`.
+///
+/// Zine sends the JSON input to the plugin's stdin, then takes its stdout as output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plugin {
+    /// The plugin name, used in the error messages.
+    pub name: String,
+    /// The executable of the plugin, relative to the root directory if it is a path.
+    pub command: String,
+    /// The arguments of the command.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The code block names handled by this plugin, the plugin returns HTML.
+    #[serde(default)]
+    pub code_blocks: Vec<String>,
+    /// The hooks to preprocess the markdown, the plugin returns markdown.
+    #[serde(default)]
+    pub hooks: Vec<PluginHook>,
+}
+
+/// The hook to preprocess the markdown before rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginHook {
+    /// Preprocess the markdown of each article.
+    Article,
+    /// Preprocess the markdown of each page.
+    Page,
+}
+
+/// The JSON input sent to the plugin.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginInput<'a> {
+    CodeBlock {
+        name: &'a str,
+        options: &'a HashMap<String, Cow<'a, str>>,
+        body: &'a str,
+        /// The source path of the markdown file, relative to the root directory.
+        path: Option<&'a Path>,
+        /// The metadata of current article or page, such as title and url.
+        meta: Option<&'a Value>,
+    },
+    Article {
+        path: &'a Path,
+        meta: Value,
+        markdown: &'a str,
+    },
+    Page {
+        path: &'a Path,
+        meta: Value,
+        markdown: &'a str,
+    },
+}
+
+#[derive(Default)]
+struct Registry {
+    // The root directory, which is the working directory of plugins.
+    root: PathBuf,
+    plugins: Vec<Plugin>,
+}
+
+static REGISTRY: Lazy<RwLock<Registry>> = Lazy::new(Default::default);
+
+/// Register the plugins declared in root `zine.toml`.
+pub fn set_plugins(root: &Path, plugins: Vec<Plugin>) {
+    *REGISTRY.write() = Registry {
+        root: root.to_owned(),
+        plugins,
+    };
+}
+
+/// Get the plugin which handles the code block `name`.
+pub fn find_code_block_plugin(name: &str) -> Option<Plugin> {
+    REGISTRY
+        .read()
+        .plugins
+        .iter()
+        .find(|plugin| plugin.code_blocks.iter().any(|block| block == name))
+        .cloned()
+}
+
+/// Get the root directory of plugins.
+pub fn plugin_root() -> PathBuf {
+    REGISTRY.read().root.clone()
+}
+
+/// Preprocess the `markdown` by all plugins registered the `hook` in order.
+///
+/// The `path` of markdown file is relative to the root directory.
+pub fn preprocess_markdown<M: Serialize>(
+    hook: PluginHook,
+    path: &Path,
+    meta: &M,
+    markdown: String,
+) -> Result<String, PluginError> {
+    let plugins = REGISTRY
+        .read()
+        .plugins
+        .iter()
+        .filter(|plugin| plugin.hooks.contains(&hook))
+        .cloned()
+        .collect::<Vec<_>>();
+    if plugins.is_empty() {
+        return Ok(markdown);
+    }
+
+    let meta = serde_json::to_value(meta).unwrap_or_default();
+    plugins.iter().try_fold(markdown, |markdown, plugin| {
+        let input = match hook {
+            PluginHook::Article => PluginInput::Article {
+                path,
+                meta: meta.clone(),
+                markdown: &markdown,
+            },
+            PluginHook::Page => PluginInput::Page {
+                path,
+                meta: meta.clone(),
+                markdown: &markdown,
+            },
+        };
+        plugin.run(&input)
+    })
+}
+
+impl Plugin {
+    /// Run the plugin with `input`, return the stdout of the plugin.
+    ///
+    /// The output is cached in zine data by the hash of the input, the plugin command
+    /// and the modified time of its executable, so it's reused across builds.
+    pub fn run(&self, input: &PluginInput) -> Result<String, PluginError> {
+        let root = plugin_root();
+        let input = serde_json::to_string(input).expect("Serialize plugin input failed.");
+        let key = self.cache_key(&root, &input);
+        if let Some(output) = data::write().use_plugin_output(&key) {
+            return Ok(output);
+        }
+
+        let output = self.execute(&root, input)?;
+        data::write().insert_plugin_output(key, output.clone());
+        Ok(output)
+    }
+
+    // The stable hash of the plugin and input, in hex.
+    fn cache_key(&self, root: &Path, input: &str) -> String {
+        let command = self.command_path(root);
+        // Search the command in `PATH` if it isn't a path.
+        let executable = if command.components().count() > 1 {
+            Some(command)
+        } else {
+            env::var_os("PATH").and_then(|paths| {
+                env::split_paths(&paths)
+                    .map(|dir| dir.join(&command))
+                    .find(|path| path.is_file())
+            })
+        };
+        let modified = executable
+            .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos());
+        let key = serde_json::to_string(&(&self.command, &self.args, modified, input))
+            .expect("Serialize plugin cache key failed.");
+        format!("{:016x}", content_hash(key.as_bytes()))
+    }
+
+    // Resolve the relative executable path against the root directory,
+    // otherwise the command is searched in `PATH`.
+    fn command_path(&self, root: &Path) -> PathBuf {
+        if self.command.contains(['/', '\\']) {
+            root.join(&self.command)
+        } else {
+            PathBuf::from(&self.command)
+        }
+    }
+
+    fn execute(&self, root: &Path, input: String) -> Result<String, PluginError> {
+        let command = self.command_path(root);
+        let mut child = Command::new(&command)
+            .args(&self.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|source| PluginError::Spawn {
+                name: self.name.clone(),
+                command: self.command.clone(),
+                source,
+            })?;
+
+        // Write the input in another thread, avoid deadlock if the plugin
+        // writes large output before reading all input.
+        let mut stdin = child.stdin.take().expect("Plugin stdin is piped.");
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output().map_err(|source| PluginError::Io {
+            name: self.name.clone(),
+            source,
+        })?;
+        // The plugin may exit without reading the input, which is not an error.
+        let _ = writer.join();
+
+        if !output.status.success() {
+            return Err(PluginError::Exit {
+                name: self.name.clone(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            });
+        }
+        String::from_utf8(output.stdout).map_err(|_| PluginError::InvalidOutput(self.name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashMap, env, fs, path::Path};
+
+    use crate::{data, error::PluginError};
+
+    use super::{set_plugins, Plugin, PluginInput};
+
+    fn shell_plugin(script: &str) -> Plugin {
+        Plugin {
+            name: String::from("test"),
+            command: String::from("sh"),
+            args: vec![String::from("-c"), script.to_owned()],
+            code_blocks: vec![String::from("test")],
+            hooks: vec![],
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_plugin() {
+        let options = HashMap::from([(String::from("color"), Cow::Borrowed("red"))]);
+        let input = PluginInput::CodeBlock {
+            name: "test",
+            options: &options,
+            body: "hello",
+            path: Some(Path::new("issue-1/first.md")),
+            meta: None,
+        };
+        let root = env::temp_dir();
+
+        let output = shell_plugin("cat")
+            .execute(&root, serde_json::to_string(&input).unwrap())
+            .unwrap();
+        assert_eq!(
+            output,
+            r#"{"type":"code_block","name":"test","options":{"color":"red"},"body":"hello","path":"issue-1/first.md","meta":null}"#
+        );
+
+        let err = shell_plugin("echo oops >&2; exit 3")
+            .execute(&root, String::new())
+            .unwrap_err();
+        assert!(matches!(err, PluginError::Exit { ref stderr, .. } if stderr == "oops"));
+
+        let err = Plugin {
+            command: String::from("./not-exists"),
+            ..shell_plugin("")
+        }
+        .execute(&root, String::new())
+        .unwrap_err();
+        assert!(matches!(err, PluginError::Spawn { .. }));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_plugin_cache() {
+        use std::{
+            fs::{File, Permissions},
+            os::unix::fs::PermissionsExt,
+            time::{Duration, SystemTime},
+        };
+
+        let root = env::temp_dir().join(format!("__zine_plugin_cache_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        data::load(&root);
+        set_plugins(&root, vec![]);
+        let script = root.join("plugin.sh");
+        fs::write(&script, "#!/bin/sh\ncat version").unwrap();
+        fs::set_permissions(&script, Permissions::from_mode(0o755)).unwrap();
+        fs::write(root.join("version"), "1").unwrap();
+
+        let options = HashMap::new();
+        let input = PluginInput::CodeBlock {
+            name: "cache",
+            options: &options,
+            body: "",
+            path: None,
+            meta: None,
+        };
+        let plugin = Plugin {
+            command: String::from("./plugin.sh"),
+            args: vec![],
+            ..shell_plugin("")
+        };
+        assert_eq!(plugin.run(&input).unwrap(), "1");
+        fs::write(root.join("version"), "2").unwrap();
+        assert_eq!(plugin.run(&input).unwrap(), "1");
+        // The cache is kept on rebuild.
+        set_plugins(&root, vec![]);
+        assert_eq!(plugin.run(&input).unwrap(), "1");
+
+        // The cache is invalidated once the plugin executable is modified.
+        File::options()
+            .write(true)
+            .open(&script)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(plugin.run(&input).unwrap(), "2");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use time::OffsetDateTime;
+
+use crate::{
+    build::locate_root_zine_folder, code_blocks::fetch_url_previews, data, entity::Zine, Entity,
+};
+
+/// The fields to edit a cached URL preview, the missing fields keep unchanged.
+#[derive(Debug, clap::Args)]
+pub struct PreviewEdit {
+    #[clap(long)]
+    title: Option<String>,
+    #[clap(long)]
+    description: Option<String>,
+    #[clap(long)]
+    image: Option<String>,
+    #[clap(long)]
+    site_name: Option<String>,
+    #[clap(long)]
+    favicon: Option<String>,
+    #[clap(long)]
+    canonical_url: Option<String>,
+    /// Remove the cached preview.
+    #[clap(long, conflicts_with_all = &["title", "description", "image", "site-name", "favicon", "canonical-url"])]
+    remove: bool,
+}
+
+/// List all cached URL previews, the URLs no longer referenced are marked as unused.
+pub fn list_previews(source: String) -> Result<()> {
+    let (source, zine) = load_zine(source)?;
+    let used_urls = zine.url_preview_urls();
+
+    let data = data::read();
+    for (url, info) in data.url_previews() {
+        let unused = if used_urls.contains(url) {
+            ""
+        } else {
+            " (unused)"
+        };
+        println!("{}{}", url, unused);
+        println!("  title: {}", info.title);
+        match info.fetched_at {
+            Some(fetched_at) => println!("  fetched at: {}", fetched_at.date()),
+            None => println!("  fetched at: unknown"),
+        }
+    }
+    println!(
+        "Total {} cached URL previews in `{}`.",
+        data.url_previews().len(),
+        source.join(data::DATA_FILE).display()
+    );
+    Ok(())
+}
+
+/// Fetch the cached URL previews again, only the previews fetched more than
+/// `older_than` ago if specified. Only refresh the given `urls` if not empty.
+pub async fn refresh_previews(
+    source: String,
+    older_than: Option<Duration>,
+    urls: Vec<String>,
+    concurrency: usize,
+) -> Result<()> {
+    let (source, _) = load_zine(source)?;
+
+    let urls = if urls.is_empty() {
+        let now = OffsetDateTime::now_utc();
+        data::read()
+            .url_previews()
+            .iter()
+            .filter(|(_, info)| match (older_than, info.fetched_at) {
+                (Some(older_than), Some(fetched_at)) => now - fetched_at > older_than,
+                _ => true,
+            })
+            .map(|(url, _)| url.to_owned())
+            .collect()
+    } else {
+        urls
+    };
+
+    let total = urls.len();
+    let fetched = fetch_url_previews(urls, concurrency).await;
+    data::export(&source)?;
+    println!("Refreshed {}/{} URL previews.", fetched, total);
+    Ok(())
+}
+
+/// Remove the cached URL previews no longer referenced by any markdown.
+pub fn prune_previews(source: String) -> Result<()> {
+    let (source, zine) = load_zine(source)?;
+    let used_urls = zine.url_preview_urls();
+
+    let unused_urls = data::read()
+        .url_previews()
+        .keys()
+        .filter(|url| !used_urls.contains(*url))
+        .cloned()
+        .collect::<Vec<_>>();
+    {
+        let mut data = data::write();
+        for url in &unused_urls {
+            data.remove_url_preview(url);
+            println!("Removed: {}", url);
+        }
+    }
+    data::export(&source)?;
+    println!("Pruned {} unused URL previews.", unused_urls.len());
+    Ok(())
+}
+
+/// Edit the cached URL preview of `url`, create one if it doesn't exist.
+pub fn edit_preview(source: String, url: &str, edit: PreviewEdit) -> Result<()> {
+    let (source, _) = load_zine(source)?;
+    {
+        let mut data = data::write();
+        if edit.remove {
+            if data.remove_url_preview(url).is_none() {
+                println!("Warn: no cached preview of `{}`.", url);
+            }
+        } else {
+            let mut info = data.url_previews().get(url).cloned().unwrap_or_default();
+            let PreviewEdit {
+                title,
+                description,
+                image,
+                site_name,
+                favicon,
+                canonical_url,
+                ..
+            } = edit;
+            if let Some(title) = title {
+                info.title = title;
+            }
+            if let Some(description) = description {
+                info.description = description;
+            }
+            info.image = image.or(info.image);
+            info.site_name = site_name.or(info.site_name);
+            info.favicon = favicon.or(info.favicon);
+            info.canonical_url = canonical_url.or(info.canonical_url);
+            data.insert_url_preview(url, info);
+        }
+    }
+    data::export(&source)?;
+    Ok(())
+}
+
+// Locate and parse the zine site of `source`, and load its data.
+fn load_zine(source: String) -> Result<(PathBuf, Zine)> {
+    let (source, mut zine) = locate_root_zine_folder(fs::canonicalize(source)?)?
+        .with_context(|| "Failed to find the root zine.toml file".to_string())?;
+    data::load(&source);
+    zine.parse(&source)?;
+    Ok((source, zine))
+}
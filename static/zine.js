@@ -13,7 +13,75 @@
         };
     }
 
-    mediumZoom(document.querySelectorAll('.prose p>img'), {
+    // Load the embedded player only when clicking the facade.
+    for (let embed of document.querySelectorAll('.embed[data-embed-src]')) {
+        let facade = embed.querySelector('.embed-facade');
+        if (!facade) continue;
+
+        facade.onclick = (event) => {
+            event.preventDefault();
+            let iframe = document.createElement('iframe');
+            iframe.src = embed.dataset.embedSrc;
+            iframe.title = embed.dataset.embedTitle || '';
+            iframe.allow = 'autoplay; encrypted-media; fullscreen; picture-in-picture';
+            iframe.allowFullscreen = true;
+            facade.replaceWith(iframe);
+        };
+    }
+
+    // Add the copy button to code blocks.
+    let codeBlocks = document.querySelectorAll('.code-block');
+    for (let block of codeBlocks) {
+        let code = block.querySelector('pre>code');
+        if (!code || !navigator.clipboard) continue;
+
+        let button = document.createElement('button');
+        button.className = 'code-copy';
+        button.type = 'button';
+        button.textContent = 'Copy';
+        button.onclick = () => {
+            // Exclude the line numbers.
+            let clone = code.cloneNode(true);
+            for (let lineno of clone.querySelectorAll('.code-lineno')) {
+                lineno.remove();
+            }
+            navigator.clipboard.writeText(clone.textContent.replace(/\n$/, '')).then(() => {
+                button.textContent = 'Copied!';
+                setTimeout(() => button.textContent = 'Copy', 1500);
+            });
+        };
+        block.appendChild(button);
+    }
+
+    // Show the footnote content in a popover when hovering the references.
+    if (document.querySelector('.footnotes[data-popover]')) {
+        let popover = null;
+        for (let ref of document.querySelectorAll('.footnote-ref>a')) {
+            let note = document.getElementById(decodeURIComponent(ref.hash.slice(1)));
+            if (!note) continue;
+
+            ref.onmouseenter = () => {
+                popover = document.createElement('div');
+                popover.className = 'footnote-popover';
+                popover.innerHTML = note.innerHTML;
+                for (let backref of popover.querySelectorAll('.footnote-backref')) {
+                    backref.remove();
+                }
+                document.body.appendChild(popover);
+
+                let rect = ref.getBoundingClientRect();
+                let left = Math.min(rect.left, document.documentElement.clientWidth - popover.offsetWidth - 16);
+                popover.style.left = `${Math.max(left, 16) + window.scrollX}px`;
+                popover.style.top = `${rect.bottom + window.scrollY + 6}px`;
+            };
+            ref.onmouseleave = () => {
+                if (popover) popover.remove();
+                popover = null;
+            };
+        }
+    }
+
+    mediumZoom(document.querySelectorAll('.prose p>img, .prose p>picture>img, .prose figure img'), {
         margin: 24,
         background: '#000C',
     });